@@ -0,0 +1,224 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+/* Typed key/value attributes on files, e.g. client=ACME, due=2026-11-01, rating=4
+ *
+ * Values are stored in file_attributes next to a kind column naming their type.
+ * Dates are stored as YYYY-MM-DD text so they compare correctly as strings.
+ */
+
+use crate::{FileChestError, FileRef, NotesDB};
+
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+
+// Serialized as {"kind": "date", "value": "2026-11-01"}, matching the kind column
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "lowercase")]
+pub enum AttrValue {
+	Text(String),
+	Integer(i64),
+	Real(f64),
+	// Calendar date in YYYY-MM-DD form
+	Date(String),
+}
+
+impl AttrValue {
+	// Guess the type of a value typed by the user: integer, then real, then date, otherwise text
+	pub fn parse(text: &str) -> Self {
+		let text = text.trim();
+		if let Ok(i) = text.parse::<i64>() {
+			AttrValue::Integer(i)
+		} else if let Some(r) = text.parse::<f64>().ok().filter(|r| r.is_finite()) {
+			AttrValue::Real(r)
+		} else if is_valid_date(text) {
+			AttrValue::Date(text.to_string())
+		} else {
+			AttrValue::Text(text.to_string())
+		}
+	}
+
+	// Name stored in the kind column
+	pub fn kind(&self) -> &'static str {
+		match self {
+			AttrValue::Text(_) => "text",
+			AttrValue::Integer(_) => "integer",
+			AttrValue::Real(_) => "real",
+			AttrValue::Date(_) => "date",
+		}
+	}
+
+	pub(crate) fn to_sql_value(&self) -> Value {
+		match self {
+			AttrValue::Text(s) | AttrValue::Date(s) => Value::Text(s.clone()),
+			AttrValue::Integer(i) => Value::Integer(*i),
+			AttrValue::Real(r) => Value::Real(*r),
+		}
+	}
+
+	fn from_sql(kind: &str, value: ValueRef) -> Result<Self, rusqlite::types::FromSqlError> {
+		Ok(match kind {
+			"integer" => AttrValue::Integer(value.as_i64()?),
+			"real" => AttrValue::Real(value.as_f64()?),
+			"date" => AttrValue::Date(value.as_str()?.to_string()),
+			_ => AttrValue::Text(value.as_str()?.to_string()),
+		})
+	}
+
+	// Kinds a value can be compared against in a query. Integers and reals compare with each other.
+	pub(crate) fn comparable_kinds(&self) -> &'static str {
+		match self {
+			AttrValue::Integer(_) | AttrValue::Real(_) => "('integer', 'real')",
+			AttrValue::Date(_) => "('date')",
+			AttrValue::Text(_) => "('text')",
+		}
+	}
+}
+
+impl std::fmt::Display for AttrValue {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			AttrValue::Text(s) | AttrValue::Date(s) => write!(f, "{s}"),
+			AttrValue::Integer(i) => write!(f, "{i}"),
+			AttrValue::Real(r) => write!(f, "{r}"),
+		}
+	}
+}
+
+// Check for a real calendar date written as YYYY-MM-DD
+pub fn is_valid_date(text: &str) -> bool {
+	let bytes = text.as_bytes();
+	if bytes.len() != 10 || bytes[4] != b'-' || bytes[7] != b'-' {
+		return false;
+	}
+
+	let (year, month, day) = match (text[0..4].parse::<u32>(), text[5..7].parse::<u32>(), text[8..10].parse::<u32>()) {
+		(Ok(y), Ok(m), Ok(d)) => (y, m, d),
+		_ => return false,
+	};
+
+	let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+	let days_in_month = match month {
+		1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+		4 | 6 | 9 | 11 => 30,
+		2 if leap => 29,
+		2 => 28,
+		_ => return false,
+	};
+
+	day >= 1 && day <= days_in_month
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+	Eq,
+	Ne,
+	Lt,
+	Le,
+	Gt,
+	Ge,
+}
+
+impl CompareOp {
+	pub fn parse(op: &str) -> Option<Self> {
+		match op {
+			"=" | "==" => Some(CompareOp::Eq),
+			"!=" => Some(CompareOp::Ne),
+			"<" => Some(CompareOp::Lt),
+			"<=" => Some(CompareOp::Le),
+			">" => Some(CompareOp::Gt),
+			">=" => Some(CompareOp::Ge),
+			_ => None,
+		}
+	}
+
+	pub fn sql(&self) -> &'static str {
+		match self {
+			CompareOp::Eq => "=",
+			CompareOp::Ne => "!=",
+			CompareOp::Lt => "<",
+			CompareOp::Le => "<=",
+			CompareOp::Gt => ">",
+			CompareOp::Ge => ">=",
+		}
+	}
+}
+
+impl NotesDB {
+	pub fn set_attribute(&self, file_ref: &FileRef, name: &str, value: &AttrValue) -> Result<(), FileChestError> {
+		let name = name.trim();
+		if name.is_empty() {
+			return Err(FileChestError::InvalidInput("Attribute names can not be empty".to_string()));
+		}
+		if let AttrValue::Date(date) = value {
+			if !is_valid_date(date) {
+				return Err(FileChestError::InvalidInput(format!("{date} is not a date in YYYY-MM-DD form")));
+			}
+		}
+
+		self.atomic(|| {
+			self.add_file(file_ref)?;
+			self.conn.execute(
+				"INSERT INTO file_attributes(file_id, name, kind, value)
+				SELECT id, ?3, ?4, ?5 FROM file_notes WHERE device=?1 AND inode=?2
+				ON CONFLICT(file_id, name) DO UPDATE SET kind=excluded.kind, value=excluded.value;",
+				(file_ref.device, file_ref.inode, name, value.kind(), value.to_sql_value())
+			)?;
+			Ok(())
+		})
+	}
+
+	pub fn get_attribute(&self, file_ref: &FileRef, name: &str) -> Result<Option<AttrValue>, FileChestError> {
+		self.check_not_stale(file_ref)?;
+
+		Ok(self.conn.query_row(
+			"SELECT file_attributes.kind, file_attributes.value FROM file_attributes
+			INNER JOIN file_notes ON file_attributes.file_id=file_notes.id
+			WHERE file_notes.device=?1 AND file_notes.inode=?2 AND file_attributes.name=?3",
+			(file_ref.device, file_ref.inode, name.trim()),
+			|row| Ok(AttrValue::from_sql(&row.get::<usize, String>(0)?, row.get_ref(1)?)?)
+		).optional()?)
+	}
+
+	// Every attribute of a file, sorted by name
+	pub fn get_attributes(&self, file_ref: &FileRef) -> Result<Vec<(String, AttrValue)>, FileChestError> {
+		self.check_not_stale(file_ref)?;
+
+		let mut stmt = self.conn.prepare(
+			"SELECT file_attributes.name, file_attributes.kind, file_attributes.value FROM file_attributes
+			INNER JOIN file_notes ON file_attributes.file_id=file_notes.id
+			WHERE file_notes.device=?1 AND file_notes.inode=?2
+			ORDER BY file_attributes.name")?;
+		let attr_iter = stmt.query_map((file_ref.device, file_ref.inode), |row| {
+			Ok((row.get(0)?, AttrValue::from_sql(&row.get::<usize, String>(1)?, row.get_ref(2)?)?))
+		})?;
+
+		Ok(attr_iter.collect::<Result<_, _>>()?)
+	}
+
+	pub fn delete_attribute(&self, file_ref: &FileRef, name: &str) -> Result<(), FileChestError> {
+		self.check_not_stale(file_ref)?;
+
+		self.conn.execute(
+			"DELETE FROM file_attributes WHERE name=?3 AND file_id IN
+			(SELECT id FROM file_notes WHERE device=?1 AND inode=?2)",
+			(file_ref.device, file_ref.inode, name.trim())
+		)?;
+		Ok(())
+	}
+}
@@ -0,0 +1,271 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+/* Content hashing, duplicate detection and hard links
+ *
+ * Files are hashed with BLAKE3. Hashes are cached in the content_hashes table under the file's
+ * device and inode together with the size and mtime it had, so a file is only read again once
+ * it changes. The cache covers every hashed file, whether or not it has a record.
+ *
+ * A copy of a file is a new inode and starts without notes or tags, so they can be copied or
+ * merged between files with the same content. Hard links share an inode and so a single record
+ * already; find_hard_links lists the paths it can be reached by.
+ */
+
+use crate::{walk_dir, FileChestError, FileRef, Fingerprint, NotesDB};
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use rusqlite::OptionalExtension;
+
+// Copies of the same content found by NotesDB::find_duplicates
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+	// BLAKE3 hash of the content as hex
+	pub hash: String,
+	pub size: u64,
+	// One entry per copy, sorted by path
+	pub files: Vec<FileRef>,
+}
+
+// Hash the contents of the file at path
+fn hash_file(path: &Path) -> Result<String, FileChestError> {
+	let mut hasher = blake3::Hasher::new();
+	std::io::copy(&mut File::open(path)?, &mut hasher)?;
+	Ok(hasher.finalize().to_hex().to_string())
+}
+
+// The file as it is now, which the fingerprint of a FileRef may no longer describe.
+// Fails with NotFound if something else is at the file's path.
+fn current_fingerprint(file_ref: &FileRef) -> Result<Fingerprint, FileChestError> {
+	let m = std::fs::metadata(&file_ref.file_path)?;
+	if m.dev() != file_ref.device || m.ino() != file_ref.inode {
+		return Err(FileChestError::NotFound(format!("{} at its known path", file_ref.file_path.display())));
+	}
+	Ok(Fingerprint::from_metadata(&m))
+}
+
+impl NotesDB {
+	// The hash of a file's contents, read from the cache if the file has not changed since it was hashed
+	pub fn content_hash(&self, file_ref: &FileRef) -> Result<String, FileChestError> {
+		let fingerprint = current_fingerprint(file_ref)?;
+		if let Some(hash) = self.cached_hash(file_ref, &fingerprint)? {
+			return Ok(hash);
+		}
+
+		let hash = hash_file(&file_ref.file_path)?;
+		self.store_hash(file_ref, &fingerprint, &hash)?;
+		Ok(hash)
+	}
+
+	fn cached_hash(&self, file_ref: &FileRef, fingerprint: &Fingerprint) -> Result<Option<String>, FileChestError> {
+		Ok(self.conn.query_row(
+			"SELECT hash FROM content_hashes WHERE device=?1 AND inode=?2 AND size=?3 AND mtime=?4;",
+			(file_ref.device, file_ref.inode, fingerprint.size, fingerprint.mtime),
+			|row| row.get::<usize, String>(0)
+		).optional()?)
+	}
+
+	fn store_hash(&self, file_ref: &FileRef, fingerprint: &Fingerprint, hash: &str) -> Result<(), FileChestError> {
+		self.conn.execute(
+			"INSERT INTO content_hashes(device, inode, size, mtime, hash) VALUES(?1, ?2, ?3, ?4, ?5)
+			ON CONFLICT(device, inode) DO UPDATE SET size=excluded.size, mtime=excluded.mtime, hash=excluded.hash;",
+			(file_ref.device, file_ref.inode, fingerprint.size, fingerprint.mtime, hash)
+		)?;
+		Ok(())
+	}
+
+	// Group the regular files below roots by content, returning every group with more than one copy.
+	// Only files which share their size with another file are hashed. Empty files are left out,
+	// as are extra names of a file which has several hard links.
+	pub fn find_duplicates<P: AsRef<Path>>(&self, roots: &[P]) -> Result<Vec<DuplicateGroup>, FileChestError> {
+		let mut by_size: HashMap<u64, Vec<FileRef>> = HashMap::new();
+		let mut seen = HashSet::new();
+		for root in roots {
+			walk_dir(root.as_ref(), &mut |path, m| {
+				if m.is_file() && m.size() > 0 && seen.insert((m.dev(), m.ino())) {
+					by_size.entry(m.size()).or_default().push(FileRef {
+						file_path: path.to_path_buf(),
+						device: m.dev(),
+						inode: m.ino(),
+						fingerprint: Some(Fingerprint::from_metadata(m)),
+					});
+				}
+			});
+		}
+
+		// Files are read outside of any transaction, so the database stays writable meanwhile,
+		// and the new hashes are cached together at the end
+		let candidates = by_size.into_values().filter(|files| files.len() > 1).flatten();
+		let mut by_hash: HashMap<String, Vec<FileRef>> = HashMap::new();
+		let mut hashed = Vec::new();
+		for file_ref in candidates {
+			// Files which vanished, were replaced or can not be read since the walk are skipped
+			let fingerprint = match current_fingerprint(&file_ref) {
+				Ok(fingerprint) => fingerprint,
+				Err(_) => continue,
+			};
+			let hash = match self.cached_hash(&file_ref, &fingerprint)? {
+				Some(hash) => hash,
+				None => match hash_file(&file_ref.file_path) {
+					Ok(hash) => {
+						hashed.push((file_ref.clone(), fingerprint, hash.clone()));
+						hash
+					},
+					Err(_) => continue,
+				},
+			};
+			by_hash.entry(hash).or_default().push(file_ref);
+		}
+
+		self.atomic(|| {
+			for (file_ref, fingerprint, hash) in &hashed {
+				self.store_hash(file_ref, fingerprint, hash)?;
+			}
+			Ok(())
+		})?;
+
+		let mut groups: Vec<DuplicateGroup> = by_hash.into_iter()
+			.filter(|(_, files)| files.len() > 1)
+			.map(|(hash, mut files)| {
+				files.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+				let size = files[0].fingerprint.as_ref().map_or(0, |fp| fp.size);
+				DuplicateGroup { hash, size, files }
+			})
+			.collect();
+
+		// Largest first, as they waste the most space
+		groups.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.files[0].file_path.cmp(&b.files[0].file_path)));
+		Ok(groups)
+	}
+
+	// Give every file in to the note and tags of from. Tags are added to the ones each file has,
+	// while the note replaces theirs (earlier notes stay in the note history).
+	// Fails without changing anything if any file's content differs from from's.
+	// Returns the number of files which changed.
+	pub fn copy_metadata(&self, from: &FileRef, to: &[FileRef]) -> Result<usize, FileChestError> {
+		self.check_same_content(from, to)?;
+
+		let note = self.stored_note(from)?.unwrap_or_default();
+		let tags = self.get_tags(from)?;
+		self.atomic(|| {
+			let mut changed = 0;
+			for file_ref in to {
+				if self.give_metadata(file_ref, &note, &tags)? {
+					changed += 1;
+				}
+			}
+			Ok(changed)
+		})
+	}
+
+	// Give every file the tags of all of them, and a note made of their distinct notes joined by
+	// blank lines, in the order the files are given. Fails without changing anything if the files'
+	// contents differ. Returns the number of files which changed.
+	pub fn merge_metadata(&self, files: &[FileRef]) -> Result<usize, FileChestError> {
+		let (first, rest) = match files.split_first() {
+			Some(split) => split,
+			None => return Ok(0),
+		};
+		self.check_same_content(first, rest)?;
+
+		let mut notes: Vec<String> = Vec::new();
+		let mut tags = BTreeSet::new();
+		for file_ref in files {
+			if let Some(note) = self.stored_note(file_ref)?.filter(|note| !note.trim().is_empty()) {
+				if !notes.contains(&note) {
+					notes.push(note);
+				}
+			}
+			tags.extend(self.get_tags(file_ref)?);
+		}
+
+		let note = notes.join("\n\n");
+		let tags: Vec<String> = tags.into_iter().collect();
+		self.atomic(|| {
+			let mut changed = 0;
+			for file_ref in files {
+				if self.give_metadata(file_ref, &note, &tags)? {
+					changed += 1;
+				}
+			}
+			Ok(changed)
+		})
+	}
+
+	// Set a file's note and add tags to it, returning whether anything changed
+	fn give_metadata(&self, file_ref: &FileRef, note: &str, tags: &[String]) -> Result<bool, FileChestError> {
+		let mut changed = false;
+		if self.stored_note(file_ref)?.unwrap_or_default() != note {
+			self.set_note(file_ref, note)?;
+			changed = true;
+		}
+
+		let mut current = self.get_tags(file_ref)?;
+		let missing: Vec<&String> = tags.iter().filter(|tag| !current.contains(tag)).collect();
+		if !missing.is_empty() {
+			current.extend(missing.into_iter().cloned());
+			self.set_tags(file_ref, current.iter().map(|tag| tag.as_str()).collect())?;
+			changed = true;
+		}
+		Ok(changed)
+	}
+
+	fn check_same_content(&self, file_ref: &FileRef, others: &[FileRef]) -> Result<(), FileChestError> {
+		let hash = self.content_hash(file_ref)?;
+		for other in others {
+			let shares_inode = other.device == file_ref.device && other.inode == file_ref.inode;
+			if !shares_inode && self.content_hash(other)? != hash {
+				return Err(FileChestError::InvalidInput(format!(
+					"{} does not have the same content as {}", other.file_path.display(), file_ref.file_path.display()
+				)));
+			}
+		}
+		Ok(())
+	}
+}
+
+impl FileRef {
+	// Number of paths the file can be reached by. More than one means it has hard links.
+	// Only regular files can have hard links; the link count of a directory counts its
+	// subdirectories instead, so anything else has a single path.
+	pub fn link_count(&self) -> Result<u64, FileChestError> {
+		let m = std::fs::symlink_metadata(&self.file_path)?;
+		Ok(if m.is_file() { m.nlink() } else { 1 })
+	}
+}
+
+// Every path below roots which leads to the file, sorted. Roots are searched in order until as
+// many paths as the file has links are found. Links outside of roots are not found.
+pub fn find_hard_links<P: AsRef<Path>>(file_ref: &FileRef, roots: &[P]) -> Result<Vec<PathBuf>, FileChestError> {
+	let wanted = file_ref.link_count()? as usize;
+	let mut found = BTreeSet::new();
+	for root in roots {
+		if found.len() >= wanted {
+			break;
+		}
+		walk_dir(root.as_ref(), &mut |path, m| {
+			if m.is_file() && m.dev() == file_ref.device && m.ino() == file_ref.inode {
+				found.insert(path.to_path_buf());
+			}
+		});
+	}
+	Ok(found.into_iter().collect())
+}
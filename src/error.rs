@@ -0,0 +1,105 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+use crate::query::QueryError;
+
+use std::path::PathBuf;
+
+// Every way a file_chest operation can fail
+#[derive(Debug)]
+pub enum FileChestError {
+	Database(rusqlite::Error),
+	Io(std::io::Error),
+	// A relative path, which would not lead back to the file from another working directory
+	InvalidPath(PathBuf),
+	// A record, tag or revision which does not exist
+	NotFound(String),
+	// The record stored under a file's device and inode belongs to a different file,
+	// which means the inode was recycled
+	StaleRecord(PathBuf),
+	// The database was written by a newer version of FileChest
+	SchemaVersion { found: u32, supported: u32 },
+	// Arguments which were rejected, e.g. an empty attribute name or renaming onto an existing tag
+	InvalidInput(String),
+	Query(QueryError),
+	// An export which could not be read or written
+	Json(serde_json::Error),
+	// Directories which could not be watched for changes
+	Watch(notify::Error),
+}
+
+impl std::fmt::Display for FileChestError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			FileChestError::Database(e) => write!(f, "Database error: {e}"),
+			FileChestError::Io(e) => write!(f, "I/O error: {e}"),
+			FileChestError::InvalidPath(path) => write!(f, "Can not store the path {}", path.display()),
+			FileChestError::NotFound(what) => write!(f, "{what} not found"),
+			FileChestError::StaleRecord(path) => write!(f, "The stored record for {} belongs to a different file", path.display()),
+			FileChestError::SchemaVersion { found, supported } => {
+				write!(f, "Database schema version {found} is newer than the supported version {supported}")
+			},
+			FileChestError::InvalidInput(message) => write!(f, "{message}"),
+			FileChestError::Query(e) => write!(f, "{e}"),
+			FileChestError::Json(e) => write!(f, "JSON error: {e}"),
+			FileChestError::Watch(e) => write!(f, "Watch error: {e}"),
+		}
+	}
+}
+
+impl std::error::Error for FileChestError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			FileChestError::Database(e) => Some(e),
+			FileChestError::Io(e) => Some(e),
+			FileChestError::Query(e) => Some(e),
+			FileChestError::Json(e) => Some(e),
+			FileChestError::Watch(e) => Some(e),
+			_ => None,
+		}
+	}
+}
+
+impl From<rusqlite::Error> for FileChestError {
+	fn from(e: rusqlite::Error) -> Self {
+		FileChestError::Database(e)
+	}
+}
+
+impl From<std::io::Error> for FileChestError {
+	fn from(e: std::io::Error) -> Self {
+		FileChestError::Io(e)
+	}
+}
+
+impl From<serde_json::Error> for FileChestError {
+	fn from(e: serde_json::Error) -> Self {
+		FileChestError::Json(e)
+	}
+}
+
+impl From<notify::Error> for FileChestError {
+	fn from(e: notify::Error) -> Self {
+		FileChestError::Watch(e)
+	}
+}
+
+impl From<QueryError> for FileChestError {
+	fn from(e: QueryError) -> Self {
+		FileChestError::Query(e)
+	}
+}
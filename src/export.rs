@@ -0,0 +1,282 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+/* JSON export and import of every file record
+ *
+ * Format version 1:
+ *
+ *     {
+ *       "format": "filechest",
+ *       "version": 1,
+ *       "files": [
+ *         {
+ *           "path": "/home/user/budget.ods",
+ *           "device": 2049,
+ *           "inode": 1001,
+ *           "fingerprint": { "birth_time": 1672531200000000000, "size": 48213, "mtime": 1672531200000000000 },
+ *           "note": "Q3 budget draft",
+ *           "tags": ["finance", "project/alpha"],
+ *           "attributes": {
+ *             "due": { "kind": "date", "value": "2023-02-01" },
+ *             "rating": { "kind": "integer", "value": 4 }
+ *           }
+ *         }
+ *       ]
+ *     }
+ *
+ * path is the file's last known path. Paths which are not valid UTF-8 are written lossily
+ * in path, with the exact bytes in an extra path_bytes array.
+ * fingerprint (times in nanoseconds since the unix epoch) and note may be null.
+ * birth_time may be null on its own, and the fingerprint may also hold a ctime.
+ * tags, attributes and fingerprint may be left out.
+ * Attribute kinds are text, integer, real and date (YYYY-MM-DD).
+ *
+ * Files are matched by device and inode. If a file exists at the exported path its current
+ * device and inode are used instead, so an export can be loaded on another machine or after
+ * restoring files from a backup.
+ */
+
+use crate::attributes::AttrValue;
+use crate::{FileChestError, FileRef, Fingerprint, NotesDB, RecordStatus};
+
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::io::{Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+
+pub const FORMAT_NAME: &str = "filechest";
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Document {
+	format: String,
+	version: u32,
+	files: Vec<FileEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FileEntry {
+	path: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	path_bytes: Option<Vec<u8>>,
+	device: u64,
+	inode: u64,
+	#[serde(default)]
+	fingerprint: Option<Fingerprint>,
+	note: Option<String>,
+	#[serde(default)]
+	tags: Vec<String>,
+	#[serde(default)]
+	attributes: BTreeMap<String, AttrValue>,
+}
+
+impl FileEntry {
+	fn file_path(&self) -> PathBuf {
+		match &self.path_bytes {
+			Some(bytes) => PathBuf::from(OsStr::from_bytes(bytes)),
+			None => PathBuf::from(&self.path),
+		}
+	}
+
+	// The file this entry describes, as it is now if it is still at its path
+	fn file_ref(&self) -> FileRef {
+		let file_path = self.file_path();
+		match FileRef::from_pathbuf(&file_path) {
+			Ok(file_ref) => file_ref,
+			Err(_) => FileRef {
+				file_path,
+				device: self.device,
+				inode: self.inode,
+				fingerprint: self.fingerprint.clone(),
+			},
+		}
+	}
+}
+
+// What to do when an imported value differs from the one already stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+	// Keep the stored value
+	Merge,
+	// Replace the stored value with the imported one
+	Overwrite,
+}
+
+// A note or attribute whose imported value differed from the stored one
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportConflict {
+	pub file_path: PathBuf,
+	// "note", "record", or the name of the attribute
+	pub field: String,
+	pub existing: String,
+	pub imported: String,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ImportReport {
+	// Files which had no record before
+	pub added: usize,
+	// Files whose existing record was merged with the import
+	pub merged: usize,
+	pub conflicts: Vec<ImportConflict>,
+}
+
+impl NotesDB {
+	// Write every file record as a JSON document in the format described above
+	pub fn export_json<W: Write>(&self, writer: W) -> Result<(), FileChestError> {
+		let mut files = Vec::new();
+		for file_ref in self.get_all_files()? {
+			let mut tags = self.get_tags(&file_ref)?;
+			tags.sort();
+
+			let bytes = file_ref.file_path.as_os_str().as_bytes();
+			files.push(FileEntry {
+				path: file_ref.file_path.to_string_lossy().to_string(),
+				path_bytes: std::str::from_utf8(bytes).is_err().then(|| bytes.to_vec()),
+				device: file_ref.device,
+				inode: file_ref.inode,
+				note: self.stored_note(&file_ref)?,
+				tags,
+				attributes: self.get_attributes(&file_ref)?.into_iter().collect(),
+				fingerprint: file_ref.fingerprint,
+			});
+		}
+
+		let document = Document {
+			format: FORMAT_NAME.to_string(),
+			version: FORMAT_VERSION,
+			files,
+		};
+		serde_json::to_writer_pretty(writer, &document)?;
+		Ok(())
+	}
+
+	// Load a JSON export into the database. Tags are combined with the stored ones. Notes and
+	// attributes which differ from the stored ones are reported, and replaced only in Overwrite mode.
+	// Nothing is imported if the document is invalid.
+	pub fn import_json<R: Read>(&self, reader: R, mode: ImportMode) -> Result<ImportReport, FileChestError> {
+		let document: Document = serde_json::from_reader(reader)?;
+		if document.format != FORMAT_NAME {
+			return Err(FileChestError::InvalidInput(format!("Not a FileChest export: format is {}", document.format)));
+		}
+		if document.version > FORMAT_VERSION {
+			return Err(FileChestError::InvalidInput(format!(
+				"Export format version {} is newer than the supported version {FORMAT_VERSION}", document.version
+			)));
+		}
+
+		self.atomic(|| {
+			let mut report = ImportReport::default();
+			for entry in &document.files {
+				self.import_entry(entry, mode, &mut report)?;
+			}
+			Ok(report)
+		})
+	}
+
+	fn import_entry(&self, entry: &FileEntry, mode: ImportMode, report: &mut ImportReport) -> Result<(), FileChestError> {
+		let file_ref = entry.file_ref();
+		if !self.begin_import(&file_ref, mode, report)? {
+			return Ok(());
+		}
+
+		if let Some(imported) = &entry.note {
+			self.import_note(&file_ref, imported, mode, report)?;
+		}
+
+		for tag in &entry.tags {
+			self.add_tag(&file_ref, tag)?;
+		}
+
+		for (name, imported) in &entry.attributes {
+			match self.get_attribute(&file_ref, name)? {
+				Some(existing) if existing == *imported => {},
+				Some(existing) => {
+					report.conflicts.push(ImportConflict {
+						file_path: file_ref.file_path.clone(),
+						field: name.clone(),
+						existing: existing.to_string(),
+						imported: imported.to_string(),
+					});
+					if mode == ImportMode::Overwrite {
+						self.set_attribute(&file_ref, name, imported)?;
+					}
+				},
+				None => self.set_attribute(&file_ref, name, imported)?,
+			}
+		}
+
+		Ok(())
+	}
+
+	// Make sure a record exists for an imported file and count it as added or merged.
+	// Returns false if the file should be skipped because its record belongs to a deleted file.
+	pub(crate) fn begin_import(&self, file_ref: &FileRef, mode: ImportMode, report: &mut ImportReport) -> Result<bool, FileChestError> {
+		match self.record_status(file_ref)? {
+			RecordStatus::Missing => report.added += 1,
+			RecordStatus::Current => report.merged += 1,
+			RecordStatus::Stale => {
+				report.conflicts.push(ImportConflict {
+					file_path: file_ref.file_path.clone(),
+					field: "record".to_string(),
+					existing: "a deleted file".to_string(),
+					imported: file_ref.file_path.display().to_string(),
+				});
+				if mode == ImportMode::Merge {
+					return Ok(false);
+				}
+				self.forget_record(file_ref)?;
+				report.added += 1;
+			},
+		}
+		self.add_file(file_ref)?;
+		Ok(true)
+	}
+
+	// An empty stored note is replaced without counting as a conflict
+	pub(crate) fn import_note(&self, file_ref: &FileRef, imported: &str, mode: ImportMode, report: &mut ImportReport) -> Result<(), FileChestError> {
+		match self.stored_note(file_ref)? {
+			Some(existing) if existing == imported => {},
+			Some(existing) if !existing.is_empty() => {
+				report.conflicts.push(ImportConflict {
+					file_path: file_ref.file_path.clone(),
+					field: "note".to_string(),
+					existing,
+					imported: imported.to_string(),
+				});
+				if mode == ImportMode::Overwrite {
+					self.set_note(file_ref, imported)?;
+				}
+			},
+			_ => self.set_note(file_ref, imported)?,
+		}
+		Ok(())
+	}
+
+	// The note column of a record, None if there is no record or it has no note
+	pub(crate) fn stored_note(&self, file_ref: &FileRef) -> Result<Option<String>, FileChestError> {
+		Ok(self.conn.query_row(
+			"SELECT note FROM file_notes WHERE device=?1 AND inode=?2;",
+			(file_ref.device, file_ref.inode),
+			|row| row.get::<usize, Option<String>>(0)
+		).optional()?.flatten())
+	}
+}
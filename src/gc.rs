@@ -0,0 +1,240 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+/* Garbage collection of records nothing refers to any more
+ *
+ * A file record is only removed once its file has been missing for a grace period,
+ * counted from the first collection which noticed it was gone. This keeps the notes of
+ * files on unmounted drives, or files which were moved and not yet found by reconcile.
+ * Dry runs note missing files too, so looking at what a collection would remove starts
+ * the grace period just like running it.
+ */
+
+use crate::{file_ref_from_row, FileChestError, FileRef, NotesDB, RecordStatus, FILE_REF_COLUMNS};
+
+use std::fs;
+use std::io::ErrorKind;
+use std::os::unix::fs::MetadataExt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Thirty days
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+#[derive(Debug, Clone)]
+pub struct GcOptions {
+	// Report what would be removed without changing anything
+	pub dry_run: bool,
+	// How long a file must have been missing before its record is removed
+	pub grace_period: Duration,
+}
+
+impl Default for GcOptions {
+	fn default() -> Self {
+		Self {
+			dry_run: false,
+			grace_period: DEFAULT_GRACE_PERIOD,
+		}
+	}
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct GcReport {
+	// Records whose file has been missing for longer than the grace period, removed with their tags and notes
+	pub expired_files: Vec<FileRef>,
+	// Records whose file is missing but which are still within the grace period
+	pub missing_files: Vec<FileRef>,
+	// Tags which no file uses, directly or through a tag below them
+	pub unused_tags: Vec<String>,
+	// Tag relations, attributes and note revisions left pointing at a file or tag which no longer exists
+	pub orphaned_rows: usize,
+}
+
+impl GcReport {
+	// Whether nothing was or would be removed. Files still in their grace period are not removed.
+	pub fn is_empty(&self) -> bool {
+		self.expired_files.is_empty() && self.unused_tags.is_empty() && self.orphaned_rows == 0
+	}
+}
+
+impl std::fmt::Display for GcReport {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		writeln!(f, "{} records of deleted files", self.expired_files.len())?;
+		for file in &self.expired_files {
+			writeln!(f, "    {}", file.file_path.display())?;
+		}
+		writeln!(f, "{} unused tags", self.unused_tags.len())?;
+		for tag in &self.unused_tags {
+			writeln!(f, "    {tag}")?;
+		}
+		writeln!(f, "{} orphaned rows", self.orphaned_rows)?;
+		write!(f, "{} missing files kept until their grace period ends", self.missing_files.len())
+	}
+}
+
+// Seconds since the unix epoch
+pub(crate) fn now() -> i64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+}
+
+// Whether the file of a record is still at its known path. Errors other than the file not
+// existing, such as a permission error, count as present since nothing can be told from them.
+pub(crate) fn is_present(record: &FileRef) -> bool {
+	match fs::symlink_metadata(&record.file_path) {
+		Ok(m) => m.dev() == record.device && m.ino() == record.inode,
+		Err(e) => e.kind() != ErrorKind::NotFound,
+	}
+}
+
+impl NotesDB {
+	// Remove records of long deleted files, unused tags and rows left pointing at nothing.
+	// With dry_run set every removal is rolled back, so the report describes what would be removed.
+	pub fn gc(&self, options: &GcOptions) -> Result<GcReport, FileChestError> {
+		let missing = self.atomic(|| self.note_missing_files())?;
+
+		self.conn.execute_batch("SAVEPOINT filechest_gc;")?;
+		let result = self.collect_garbage(missing, options.grace_period);
+		if options.dry_run || result.is_err() {
+			self.conn.execute_batch("ROLLBACK TO filechest_gc; RELEASE filechest_gc;")?;
+		} else {
+			self.conn.execute_batch("RELEASE filechest_gc;")?;
+		}
+		result
+	}
+
+	// Record when each missing file was first noticed gone, and clear it for files which came
+	// back. Returns the missing files with the time they went missing.
+	fn note_missing_files(&self) -> Result<Vec<(FileRef, i64)>, FileChestError> {
+		let now = now();
+		self.adopt_devices()?;
+
+		let records = {
+			let mut stmt = self.conn.prepare(&format!("SELECT {FILE_REF_COLUMNS}, file_notes.missing_since FROM file_notes"))?;
+			let rows = stmt.query_map((), |row| Ok((file_ref_from_row(row)?, row.get::<usize, Option<i64>>(7)?)))?;
+			rows.collect::<Result<Vec<_>, _>>()?
+		};
+
+		let mut missing = Vec::new();
+		for (record, missing_since) in records {
+			if is_present(&record) {
+				if missing_since.is_some() {
+					self.set_missing_since(&record, None)?;
+				}
+				continue;
+			}
+
+			let missing_since = match missing_since {
+				Some(t) => t,
+				None => {
+					self.set_missing_since(&record, Some(now))?;
+					now
+				},
+			};
+			missing.push((record, missing_since));
+		}
+		Ok(missing)
+	}
+
+	fn collect_garbage(&self, missing: Vec<(FileRef, i64)>, grace_period: Duration) -> Result<GcReport, FileChestError> {
+		let mut report = GcReport::default();
+		let now = now();
+
+		for (record, missing_since) in missing {
+			if now.saturating_sub(missing_since) >= grace_period.as_secs() as i64 {
+				self.forget_record(&record)?;
+				report.expired_files.push(record);
+			} else {
+				report.missing_files.push(record);
+			}
+		}
+
+		report.orphaned_rows = self.remove_orphaned_rows()?;
+		for (id, name) in self.unused_tags()? {
+			self.conn.execute("DELETE FROM file_tags WHERE id=?1", (id,))?;
+			report.unused_tags.push(name);
+		}
+		report.unused_tags.sort();
+
+		report.expired_files.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+		report.missing_files.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+		Ok(report)
+	}
+
+	// Remove what a dry run reported, and nothing it did not. Files which came back and tags
+	// which were put to use since are kept. Returns what was removed.
+	pub fn gc_previewed(&self, preview: &GcReport) -> Result<GcReport, FileChestError> {
+		self.atomic(|| {
+			let mut report = GcReport::default();
+			for record in &preview.expired_files {
+				if is_present(record) || self.record_status(record)? == RecordStatus::Missing {
+					continue;
+				}
+				self.forget_record(record)?;
+				report.expired_files.push(record.clone());
+			}
+
+			report.orphaned_rows = self.remove_orphaned_rows()?;
+			for (id, name) in self.unused_tags()? {
+				if preview.unused_tags.contains(&name) {
+					self.conn.execute("DELETE FROM file_tags WHERE id=?1", (id,))?;
+					report.unused_tags.push(name);
+				}
+			}
+			report.unused_tags.sort();
+			Ok(report)
+		})
+	}
+
+	// Delete tag relations, attributes and note revisions of files or tags which no longer exist.
+	// Returns the number of rows deleted.
+	fn remove_orphaned_rows(&self) -> Result<usize, FileChestError> {
+		let mut removed = self.conn.execute(
+			"DELETE FROM tag_relations
+			WHERE file_id NOT IN (SELECT id FROM file_notes) OR tag_id NOT IN (SELECT id FROM file_tags)",
+			()
+		)?;
+		for table in ["file_attributes", "note_revisions"] {
+			removed += self.conn.execute(
+				&format!("DELETE FROM {table} WHERE file_id NOT IN (SELECT id FROM file_notes)"),
+				()
+			)?;
+		}
+		Ok(removed)
+	}
+
+	// The ids and names of the tags no file uses, children before their parents, which they
+	// reference. A tag is in use if it or any tag below it is on a file.
+	fn unused_tags(&self) -> Result<Vec<(i64, String)>, FileChestError> {
+		let mut stmt = self.conn.prepare(
+			"WITH RECURSIVE used(id) AS (
+				SELECT tag_id FROM tag_relations
+				UNION
+				SELECT file_tags.parent_id FROM file_tags INNER JOIN used ON file_tags.id=used.id
+				WHERE file_tags.parent_id IS NOT NULL
+			)
+			SELECT id, tag_name FROM file_tags WHERE id NOT IN used ORDER BY length(tag_name) DESC")?;
+		let rows = stmt.query_map((), |row| Ok((row.get::<usize, i64>(0)?, row.get::<usize, String>(1)?)))?;
+		Ok(rows.collect::<Result<Vec<_>, _>>()?)
+	}
+
+	fn set_missing_since(&self, record: &FileRef, since: Option<i64>) -> Result<(), FileChestError> {
+		self.conn.execute(
+			"UPDATE file_notes SET missing_since=?3 WHERE device=?1 AND inode=?2",
+			(record.device, record.inode, since)
+		)?;
+		Ok(())
+	}
+}
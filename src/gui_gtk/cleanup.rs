@@ -0,0 +1,123 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+use crate::messages::*;
+
+use file_chest::gc::GcReport;
+
+use gtk::prelude::*;
+use relm4::prelude::*;
+
+// Dialog showing what a dry run of the garbage collector found, so it can be confirmed
+pub struct Cleanup {
+	visible: bool,
+	// The dry run shown, which is what gets deleted on confirmation
+	report: Option<GcReport>,
+	report_buffer: gtk::TextBuffer,
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for Cleanup {
+	type Init = ();
+	type Input = CleanupMsg;
+	type Output = CleanupOutput;
+
+	view! {
+		gtk::Window {
+			set_title: Some("Clean Up Database"),
+			set_modal: true,
+			set_hide_on_close: true,
+			set_default_width: 480,
+			set_default_height: 360,
+			#[watch]
+			set_visible: model.visible,
+
+			connect_close_request[sender] => move |_| {
+				sender.input(CleanupMsg::Hide);
+				gtk::Inhibit(false)
+			},
+
+			gtk::Box {
+				set_orientation: gtk::Orientation::Vertical,
+				set_margin_all: 12,
+				set_spacing: 6,
+
+				gtk::ScrolledWindow {
+					set_vexpand: true,
+
+					gtk::TextView {
+						set_editable: false,
+						set_cursor_visible: false,
+						set_buffer: Some(&model.report_buffer),
+					},
+				},
+
+				gtk::Box {
+					set_orientation: gtk::Orientation::Horizontal,
+					set_spacing: 6,
+					set_homogeneous: true,
+
+					gtk::Button {
+						set_label: "Cancel",
+						connect_clicked[sender] => move |_| {
+							sender.input(CleanupMsg::Hide);
+						}
+					},
+
+					gtk::Button {
+						set_label: "Delete",
+						#[watch]
+						set_sensitive: model.report.as_ref().is_some_and(|report| !report.is_empty()),
+						connect_clicked[sender] => move |_| {
+							sender.input(CleanupMsg::Confirm);
+						}
+					},
+				},
+			}
+		}
+	}
+
+	fn update(&mut self, msg: CleanupMsg, sender: ComponentSender<Self>) {
+		match msg {
+			CleanupMsg::Show(report) => {
+				self.report_buffer.set_text(&report.to_string());
+				self.report = Some(report);
+				self.visible = true;
+			},
+			CleanupMsg::Hide => {
+				self.visible = false;
+			},
+			CleanupMsg::Confirm => {
+				self.visible = false;
+				if let Some(report) = self.report.take() {
+					sender.output(CleanupOutput::Confirm(report));
+				}
+			},
+		}
+	}
+
+	fn init(_init: Self::Init, root: &Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self> {
+		let model = Cleanup {
+			visible: false,
+			report: None,
+			report_buffer: gtk::TextBuffer::builder().text("").build(),
+		};
+
+		let widgets = view_output!();
+		ComponentParts { model, widgets }
+	}
+}
@@ -0,0 +1,176 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+use crate::messages::*;
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use gtk::prelude::*;
+use relm4::prelude::*;
+
+// One copy of a duplicated file with what is stored for it
+#[derive(Debug)]
+pub struct DuplicateCopy {
+	pub file_path: PathBuf,
+	pub note: Option<String>,
+	pub tags: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct DuplicateSet {
+	pub size: u64,
+	pub copies: Vec<DuplicateCopy>,
+}
+
+// Human readable file size, e.g. 1.2 MB
+fn format_size(bytes: u64) -> String {
+	const UNITS: [&str; 5] = ["bytes", "KB", "MB", "GB", "TB"];
+	let mut size = bytes as f64;
+	let mut unit = 0;
+	while size >= 1000.0 && unit < UNITS.len() - 1 {
+		size /= 1000.0;
+		unit += 1;
+	}
+	if unit == 0 {
+		format!("{bytes} {}", UNITS[0])
+	} else {
+		format!("{size:.1} {}", UNITS[unit])
+	}
+}
+
+fn describe(sets: &[DuplicateSet]) -> String {
+	if sets.is_empty() {
+		return String::from("No duplicate files found.");
+	}
+
+	let mut text = String::new();
+	for set in sets {
+		let _ = writeln!(text, "{} copies of {}", set.copies.len(), format_size(set.size));
+		for copy in &set.copies {
+			let _ = writeln!(text, "    {}", copy.file_path.display());
+			if !copy.tags.is_empty() {
+				let _ = writeln!(text, "        Tags: {}", copy.tags.join(", "));
+			}
+			let note = copy.note.as_deref().and_then(|note| note.lines().find(|line| !line.trim().is_empty()));
+			if let Some(line) = note {
+				let _ = writeln!(text, "        Note: {line}");
+			} else if copy.tags.is_empty() {
+				let _ = writeln!(text, "        No notes or tags");
+			}
+		}
+		text.push('\n');
+	}
+	text
+}
+
+// Dialog listing groups of identical files, showing which copy carries which notes and tags
+pub struct Duplicates {
+	visible: bool,
+	can_merge: bool,
+	report_buffer: gtk::TextBuffer,
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for Duplicates {
+	type Init = ();
+	type Input = DuplicatesMsg;
+	type Output = DuplicatesOutput;
+
+	view! {
+		gtk::Window {
+			set_title: Some("Duplicate Files"),
+			set_modal: true,
+			set_hide_on_close: true,
+			set_default_width: 560,
+			set_default_height: 420,
+			#[watch]
+			set_visible: model.visible,
+
+			connect_close_request[sender] => move |_| {
+				sender.input(DuplicatesMsg::Hide);
+				gtk::Inhibit(false)
+			},
+
+			gtk::Box {
+				set_orientation: gtk::Orientation::Vertical,
+				set_margin_all: 12,
+				set_spacing: 6,
+
+				gtk::ScrolledWindow {
+					set_vexpand: true,
+
+					gtk::TextView {
+						set_editable: false,
+						set_cursor_visible: false,
+						set_buffer: Some(&model.report_buffer),
+					},
+				},
+
+				gtk::Box {
+					set_orientation: gtk::Orientation::Horizontal,
+					set_spacing: 6,
+					set_homogeneous: true,
+
+					gtk::Button {
+						set_label: "Close",
+						connect_clicked[sender] => move |_| {
+							sender.input(DuplicatesMsg::Hide);
+						}
+					},
+
+					gtk::Button {
+						set_label: "Merge Notes and Tags",
+						set_tooltip_text: Some("Give every copy the notes and tags of all its copies"),
+						#[watch]
+						set_sensitive: model.can_merge,
+						connect_clicked[sender] => move |_| {
+							sender.input(DuplicatesMsg::MergeAll);
+						}
+					},
+				},
+			}
+		}
+	}
+
+	fn update(&mut self, msg: DuplicatesMsg, sender: ComponentSender<Self>) {
+		match msg {
+			DuplicatesMsg::Show(sets) => {
+				self.can_merge = !sets.is_empty();
+				self.report_buffer.set_text(&describe(&sets));
+				self.visible = true;
+			},
+			DuplicatesMsg::Hide => {
+				self.visible = false;
+			},
+			DuplicatesMsg::MergeAll => {
+				sender.output(DuplicatesOutput::MergeAll);
+			},
+		}
+	}
+
+	fn init(_init: Self::Init, root: &Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self> {
+		let model = Duplicates {
+			visible: false,
+			can_merge: false,
+			report_buffer: gtk::TextBuffer::builder().text("").build(),
+		};
+
+		let widgets = view_output!();
+		ComponentParts { model, widgets }
+	}
+}
@@ -16,20 +16,44 @@
  */
 
 use file_chest::FileRef;
+use file_chest::metadata::FileMetadata;
 use crate::messages::*;
 
+use gtk::gio;
 use gtk::prelude::*;
 use relm4::prelude::*;
 
 #[derive(Debug)]
 pub struct FileElement {
 	pub file: FileRef,
+	// Looked up when the list is filled, so drawing the row does not touch the disk
+	pub metadata: Option<FileMetadata>,
+	// The part of the note which matched a note search
+	snippet: Option<String>,
+	icon: gio::Icon,
     pub completed: bool,
 }
 
+// The theme's icon for the file's content type
+fn icon_for(metadata: Option<&FileMetadata>) -> gio::Icon {
+	let mime_type = metadata.and_then(|m| m.mime_type.as_deref());
+	match mime_type.and_then(gio::content_type_from_mime_type) {
+		Some(content_type) => gio::content_type_get_icon(&content_type),
+		None => gio::ThemedIcon::new("text-x-generic").upcast(),
+	}
+}
+
+impl FileElement {
+	// Replace the metadata the row was listed with, e.g. once the file's contents were checked
+	pub fn set_metadata(&mut self, metadata: FileMetadata) {
+		self.icon = icon_for(Some(&metadata));
+		self.metadata = Some(metadata);
+	}
+}
+
 #[relm4::factory(pub)]
 impl FactoryComponent for FileElement {
-	type Init = FileRef;
+	type Init = (FileRef, Option<FileMetadata>, Option<String>);
 	type Input = FileElementInput;
 	type Output = FileElementOutput;
 	type CommandOutput = ();
@@ -41,23 +65,28 @@ impl FactoryComponent for FileElement {
             set_orientation: gtk::Orientation::Horizontal,
 
 			gtk::Image {
-				set_icon_name: {
-					if self.file.file_path.is_dir() {
-						Some("folder")
-					} else {
-						Some("text-x-generic")
-					}
-				},
+				#[watch]
+				set_from_gicon: &self.icon,
 			},
 
 			#[name(label)]
             gtk::Label {
-                set_label: &self.file.file_path.file_name().unwrap().to_str().unwrap(),
+                // Names which are not valid UTF-8 are only made lossy for display
+                set_label: &self.file.file_path.file_name().unwrap_or(self.file.file_path.as_os_str()).to_string_lossy(),
                 set_hexpand: true,
                 set_halign: gtk::Align::Start,
                 set_margin_all: 2,
 				//set_selectable: true,
             },
+
+			gtk::Label {
+				set_visible: self.snippet.is_some(),
+				set_label: self.snippet.as_deref().unwrap_or_default(),
+				set_ellipsize: gtk::pango::EllipsizeMode::End,
+				set_max_width_chars: 60,
+				add_css_class: "dim-label",
+				set_margin_all: 2,
+			},
         }
     }
 
@@ -71,9 +100,12 @@ impl FactoryComponent for FileElement {
 		None
     }
 
-    fn init_model(file: Self::Init, _index: &DynamicIndex, _sender: FactorySender<Self>) -> Self {
+    fn init_model((file, metadata, snippet): Self::Init, _index: &DynamicIndex, _sender: FactorySender<Self>) -> Self {
         Self {
+			icon: icon_for(metadata.as_ref()),
 			file,
+			metadata,
+			snippet,
             completed: false,
         }
     }
@@ -17,10 +17,23 @@
 
 use crate::messages::*;
 use crate::file_element::*;
-
-use file_chest::{FileRef, NotesDB};
+use crate::tag_manager::TagManager;
+use crate::note_history::NoteHistory;
+use crate::cleanup::Cleanup;
+use crate::duplicates::{DuplicateCopy, DuplicateSet, Duplicates};
+use crate::watcher::BackgroundWatcher;
+
+use file_chest::{FileRef, NotesDB, RecordStatus};
+use file_chest::query;
+use file_chest::attributes::AttrValue;
+use file_chest::gc::GcOptions;
+use file_chest::index::IndexOptions;
+use file_chest::duplicates::{find_hard_links, DuplicateGroup};
+use file_chest::metadata::{FileMetadata, SortKey, SortOrder};
 
 use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use gtk::prelude::*;
@@ -28,21 +41,54 @@ use gtk::prelude::*;
 use relm4::factory::FactoryVecDeque;
 use relm4::prelude::*;
 
+// Orders offered by the sort drop down, in the order they are listed
+const SORT_ORDERS: &[(&str, SortOrder)] = &[
+	("Name", SortOrder { key: SortKey::Path, descending: false }),
+	("Largest", SortOrder { key: SortKey::Size, descending: true }),
+	("Newest", SortOrder { key: SortKey::Modified, descending: true }),
+	("Type", SortOrder { key: SortKey::Type, descending: false }),
+];
+
 pub struct AppModel {
 	db: NotesDB,
     file_elements: FactoryVecDeque<FileElement>,
-	search_dir: String,
+	search_dir: PathBuf,
 	show_hidden: bool,
+	sort_order: SortOrder,
 	dir_entry_buffer: gtk::EntryBuffer,
 	tag_entry_buffer: gtk::EntryBuffer,
 	notes_buffer: gtk::TextBuffer,
+	attributes_buffer: gtk::TextBuffer,
+	attribute_entry_buffer: gtk::EntryBuffer,
 	current_file: Option<FileRef>,
+	current_file_stale: bool,
 	view_file_context: gtk::PopoverMenu,
+	tag_manager: Controller<TagManager>,
+	note_history: Controller<NoteHistory>,
+	cleanup: Controller<Cleanup>,
+	duplicates: Controller<Duplicates>,
+	// An index of the current directory is running in the background
+	indexing: bool,
+	index_fraction: f64,
+	index_status: String,
+	// A search for duplicates of the current directory is running in the background
+	finding_duplicates: bool,
+	// The tagging rules are being applied to the current directory in the background
+	applying_rules: bool,
+	// Groups shown in the duplicates dialog
+	duplicate_groups: Vec<DuplicateGroup>,
+	// Other paths of the selected file, if it has hard links
+	links_text: String,
+	// Running when the app was started with --watch
+	watcher: Option<BackgroundWatcher>,
+	// The current directory is one of the watched directories
+	search_dir_watched: bool,
 }
 
 #[relm4::component(pub)]
 impl SimpleComponent for AppModel {
-    type Init = NotesDB;
+    // The database, and whether to watch the stored directories while the app runs
+    type Init = (NotesDB, bool);
     type Input = AppMsg;
     type Output = ();
 
@@ -50,6 +96,11 @@ impl SimpleComponent for AppModel {
         main_window = gtk::ApplicationWindow {
             set_width_request: 360,
             set_title: Some("To-Do"),
+			// Closing waits for the watcher to stop, see AppMsg::Quit
+			connect_close_request[sender] => move |_| {
+				sender.input(AppMsg::Quit);
+				gtk::Inhibit(true)
+			},
 
             gtk::Box {
                 set_orientation: gtk::Orientation::Vertical,
@@ -90,7 +141,80 @@ impl SimpleComponent for AppModel {
 							set_text: "Show Hidden Files",
 						},
 						
-					}
+					},
+
+					gtk::Box {
+						set_orientation: gtk::Orientation::Vertical,
+						set_margin_all: 2,
+						set_spacing: 2,
+
+						gtk::DropDown::from_strings(&SORT_ORDERS.iter().map(|(name, _)| *name).collect::<Vec<_>>()) {
+							connect_selected_notify[sender] => move |drop_down| {
+								sender.input(AppMsg::SetSortOrder(drop_down.selected()));
+							},
+						},
+
+						gtk::Label {
+							set_text: "Sort By",
+						},
+					},
+
+					gtk::Button {
+						set_label: "Index Directory",
+						#[watch]
+						set_sensitive: !model.indexing,
+
+						connect_clicked[sender] => move |_| {
+							sender.input(AppMsg::IndexDir);
+						}
+					},
+
+					gtk::Button {
+						set_label: "Find Duplicates",
+						#[watch]
+						set_sensitive: !model.finding_duplicates,
+
+						connect_clicked[sender] => move |_| {
+							sender.input(AppMsg::FindDuplicates);
+						}
+					},
+
+					gtk::Button {
+						set_label: "Apply Rules",
+						#[watch]
+						set_sensitive: !model.applying_rules,
+
+						connect_clicked[sender] => move |_| {
+							sender.input(AppMsg::ApplyRules);
+						}
+					},
+
+					gtk::Button {
+						#[watch]
+						set_label: if model.search_dir_watched { "Stop Watching Directory" } else { "Watch Directory" },
+
+						connect_clicked[sender] => move |_| {
+							sender.input(AppMsg::ToggleWatchDir);
+						}
+					},
+
+					gtk::Button {
+						set_label: "Clean Up Database",
+
+						connect_clicked[sender] => move |_| {
+							sender.input(AppMsg::ShowCleanup);
+						}
+					},
+				},
+
+				gtk::ProgressBar {
+					set_show_text: true,
+					#[watch]
+					set_visible: model.indexing,
+					#[watch]
+					set_fraction: model.index_fraction,
+					#[watch]
+					set_text: Some(&model.index_status),
 				},
 
 				gtk::Box {
@@ -133,6 +257,42 @@ impl SimpleComponent for AppModel {
 						set_margin_all: 6,
 						set_spacing: 6,
 
+						gtk::Box {
+							set_orientation: gtk::Orientation::Vertical,
+							set_spacing: 6,
+							#[watch]
+							set_visible: model.current_file_stale,
+
+							gtk::Label {
+								set_wrap: true,
+								set_text: "This file has the inode of a deleted file which had a note. If it is the same file, \
+									e.g. it was edited, keep the note. Otherwise the old note, tags and attributes are discarded.",
+							},
+
+							gtk::Box {
+								set_orientation: gtk::Orientation::Horizontal,
+								set_spacing: 6,
+
+								gtk::Button {
+									set_label: "Same File, Keep Note",
+									add_css_class: "suggested-action",
+
+									connect_clicked[sender] => move |_| {
+										sender.input(AppMsg::KeepStaleRecord);
+									}
+								},
+
+								gtk::Button {
+									set_label: "Different File, Discard Note",
+									add_css_class: "destructive-action",
+
+									connect_clicked[sender] => move |_| {
+										sender.input(AppMsg::ForgetStaleRecord);
+									}
+								},
+							},
+						},
+
 						gtk::ScrolledWindow {
 							set_hexpand: true,
 							set_vexpand: true,
@@ -153,6 +313,14 @@ impl SimpleComponent for AppModel {
 								sender.input(AppMsg::SubmitNote);
 							}
 						},
+
+						gtk::Button {
+							set_label: "Note History",
+
+							connect_clicked[sender] => move |_| {
+								sender.input(AppMsg::ShowNoteHistory);
+							}
+						},
 					
 						gtk::Entry {
 							set_placeholder_text: Some("Enter tags"),
@@ -164,65 +332,128 @@ impl SimpleComponent for AppModel {
 					        	sender.input(AppMsg::SubmitTags(buffer.text()));
 							},
 						},
+
+						gtk::Button {
+							set_label: "Manage Tags",
+
+							connect_clicked[sender] => move |_| {
+								sender.input(AppMsg::ShowTagManager);
+							}
+						},
+
+						gtk::ScrolledWindow {
+							set_hexpand: true,
+							set_min_content_height: 80,
+
+							gtk::TextView {
+								set_editable: false,
+								set_cursor_visible: false,
+								set_buffer: Some(&model.attributes_buffer),
+							},
+						},
+
+						gtk::Label {
+							set_halign: gtk::Align::Start,
+							set_selectable: true,
+							#[watch]
+							set_visible: !model.links_text.is_empty(),
+							#[watch]
+							set_text: &model.links_text,
+						},
+
+						gtk::Entry {
+							set_placeholder_text: Some("Set attribute: name=value (empty value removes it)"),
+							set_buffer: &model.attribute_entry_buffer,
+
+							set_hexpand: true,
+							connect_activate[sender] => move |entry| {
+								let buffer = entry.buffer();
+								sender.input(AppMsg::SubmitAttribute(buffer.text()));
+								buffer.delete_text(0, None);
+							},
+						},
 					}
 				}
             }
         }
     }
 
-    fn update(&mut self, msg: AppMsg, _sender: ComponentSender<Self>) {
+    fn update(&mut self, msg: AppMsg, sender: ComponentSender<Self>) {
         match msg {
 			// Set the currently displayed search directory or current tag search
             AppMsg::SetDir(name) => {
-				let len = name.len();
-				if len > 4 && &name[0..4] == "tag:" {
-					if let Ok(files) = self.db.get_files_by_tag((&name[4..len]).trim()) {
-						self.file_elements.guard().clear();
-						for fr in files {
-							self.file_elements.guard().push_back(fr);
-						}
+				if query::looks_like_query(&name) {
+					let results: Result<Vec<(FileRef, Option<String>)>, _> = match query::parse(&name) {
+						// A search of notes alone lists the best matches first, with the part of each note which matched
+						Ok(query::Query::Note(text)) => self.db.search_notes(&text)
+							.map(|matches| matches.into_iter().map(|m| (m.file, Some(m.snippet))).collect()),
+						_ => self.db.query_sorted(&name, self.sort_order)
+							.map(|files| files.into_iter().map(|fr| (fr, None)).collect()),
+					};
+					match results {
+						Ok(files) => {
+							self.file_elements.guard().clear();
+							for (fr, snippet) in files {
+								let metadata = self.db.get_metadata(&fr).ok().flatten();
+								self.file_elements.guard().push_back((fr, metadata, snippet));
+							}
+						},
+						Err(e) => eprintln!("Error running search {e}"),
 					}
 				} else {
-					self.search_dir = name;
+					self.search_dir = PathBuf::from(name);
 					self.reload_dir();
 				}
             },
 			// Set the currently displayed directory after double clicking on a file
 			AppMsg::SetDirFromSelected => {
 				if let Some(file) = &self.current_file {
-					self.search_dir = file.file_path.to_string_lossy().to_string();
+					self.search_dir = file.file_path.clone();
 					self.reload_dir();
-					self.dir_entry_buffer.set_text(&self.search_dir);
+					self.dir_entry_buffer.set_text(&self.search_dir.to_string_lossy());
 				}
 			},
 			AppMsg::SetShowHidden(do_show) => {
 				self.show_hidden = do_show;
 				self.reload_dir();
 			},
+			AppMsg::SetSortOrder(index) => {
+				if let Some((_, order)) = SORT_ORDERS.get(index as usize) {
+					self.sort_order = *order;
+					// Run the search again, or list the directory again, in the new order
+					sender.input(AppMsg::SetDir(self.dir_entry_buffer.text()));
+				}
+			},
 			//Update the UI to reflect a newly selected file
 			AppMsg::SelectFile(index) => {
-				let fr = self.get_fileref_by_index(index as usize).unwrap(); 
-
-				match self.db.get_note(&fr) {
-					Ok(note) => {
-						self.notes_buffer.set_text(&note);
-					},
-					Err(_) => {
-						self.notes_buffer.set_text("Enter a new note!");
-					},
+				self.detect_mime_type(index as usize);
+				if let Some(fr) = self.get_fileref_by_index(index as usize) {
+					self.show_file(fr, &sender);
 				}
-				
-				match self.db.get_tags(&fr) {
-					Ok(tags) => {
-						self.tag_entry_buffer.set_text(&tags.join(", "));
-					},
-					Err(_) => {},
+			},
+			// The selected file is the one its stale record was stored for, e.g. it was edited
+			AppMsg::KeepStaleRecord => {
+				if let Some(file) = self.current_file.clone() {
+					if let Err(e) = self.db.refresh_fingerprint(&file) {
+						eprintln!("Error keeping record {e}");
+					}
+					self.show_file(file, &sender);
+				}
+			},
+			// The selected file is a new one which reused the inode of a deleted file
+			AppMsg::ForgetStaleRecord => {
+				if let Some(file) = self.current_file.clone() {
+					if let Err(e) = self.db.forget_record(&file) {
+						eprintln!("Error removing stale record {e}");
+					}
+					self.show_file(file, &sender);
 				}
-
-				self.current_file = Some(fr.clone());
 			},
 			// Submit notes for the currently selected file to the rusqlite database
 			AppMsg::SubmitNote => {
+				if self.refuse_if_stale() {
+					return;
+				}
 				if let Some(file) = &self.current_file {
 					let start = self.notes_buffer.start_iter();
 					let end = self.notes_buffer.end_iter();
@@ -233,6 +464,9 @@ impl SimpleComponent for AppModel {
 			},
 			// Submit tags for the currently selected file to the rusqlite database
 			AppMsg::SubmitTags(tag_string) => {
+				if self.refuse_if_stale() {
+					return;
+				}
 				if let Some(file) = &self.current_file {
 					let tags = tag_string.split(",").map(|t| t.trim()).collect();
 
@@ -241,6 +475,23 @@ impl SimpleComponent for AppModel {
 					}
 				}
 			},
+			// Set, or with an empty value remove, an attribute of the currently selected file
+			AppMsg::SubmitAttribute(text) => {
+				if self.refuse_if_stale() {
+					return;
+				}
+				if let (Some(file), Some((name, value))) = (&self.current_file, text.split_once('=')) {
+					let result = if value.trim().is_empty() {
+						self.db.delete_attribute(file, name)
+					} else {
+						self.db.set_attribute(file, name, &AttrValue::parse(value))
+					};
+					if let Err(e) = result {
+						eprintln!("Error submitting attribute {e}");
+					}
+				}
+				self.show_attributes();
+			},
 			// Show the right click menu for the selected file
 			AppMsg::ShowFileContext(x, y) => {
 				if self.current_file.is_some() {
@@ -252,22 +503,243 @@ impl SimpleComponent for AppModel {
 			AppMsg::OpenCurrentFile(open_type) => {
 				match (open_type, &self.current_file) {
 					(OpenType::OpenFile, Some(cur_file)) => {
-						let path = cur_file.file_path.to_str().expect("Could not unwrap file path");
-						Command::new("xdg-open").arg(path).output().expect("Failed to open file");
+						Command::new("xdg-open").arg(&cur_file.file_path).output().expect("Failed to open file");
 					},
 					(OpenType::OpenParent, Some(cur_file)) => {
 						if let Some(parent_path) = cur_file.file_path.parent() {
-							let path = parent_path.to_str().expect("Could not unwrap file path");
-							Command::new("xdg-open").arg(path).output().expect("Failed to open file");
+							Command::new("xdg-open").arg(parent_path).output().expect("Failed to open file");
 						}
 					},
 					(_,_) => {},
 				}
 			},
+			AppMsg::ShowTagManager => {
+				match self.db.list_tags() {
+					Ok(tags) => self.tag_manager.emit(TagManagerMsg::Show(tags)),
+					Err(e) => eprintln!("Error listing tags {e}"),
+				}
+			},
+			AppMsg::ShowNoteHistory => {
+				if let Some(file) = &self.current_file {
+					match self.db.note_history(file) {
+						Ok(revisions) => self.note_history.emit(NoteHistoryMsg::Show(revisions)),
+						Err(e) => eprintln!("Error loading note history {e}"),
+					}
+				}
+			},
+			AppMsg::RestoreNote(NoteHistoryOutput::Restore(revision_id)) => {
+				if let Some(file) = &self.current_file {
+					if let Err(e) = self.db.restore_note_revision(file, revision_id) {
+						eprintln!("Error restoring note {e}");
+					}
+					if let Ok(note) = self.db.get_note(file) {
+						self.notes_buffer.set_text(&note);
+					}
+					if let Ok(revisions) = self.db.note_history(file) {
+						self.note_history.emit(NoteHistoryMsg::Show(revisions));
+					}
+				}
+			},
+			// Show what a clean up would remove before doing it
+			AppMsg::ShowCleanup => {
+				match self.db.gc(&GcOptions { dry_run: true, ..GcOptions::default() }) {
+					Ok(report) => self.cleanup.emit(CleanupMsg::Show(report)),
+					Err(e) => eprintln!("Error checking database {e}"),
+				}
+			},
+			// Only what was shown is deleted, even if more went missing in the meantime
+			AppMsg::Cleanup(CleanupOutput::Confirm(preview)) => {
+				if let Err(e) = self.db.gc_previewed(&preview) {
+					eprintln!("Error cleaning up database {e}");
+				}
+			},
+			// Index the current directory on a separate thread with its own connection, so the window
+			// stays responsive while large trees are walked
+			AppMsg::IndexDir => {
+				let db_path = match self.db.path() {
+					Some(path) => path.to_path_buf(),
+					None => return,
+				};
+				if self.indexing || self.search_dir.as_os_str().is_empty() {
+					return;
+				}
+
+				self.indexing = true;
+				self.index_fraction = 0.0;
+				self.index_status = String::from("Scanning");
+
+				let root = self.search_dir.clone();
+				let rules = self.db.rules().clone();
+				std::thread::spawn(move || {
+					let result = NotesDB::open(db_path).and_then(|mut db| {
+						db.set_rules(rules);
+						db.index(&root, &IndexOptions::default(), &mut |progress| {
+							sender.input(AppMsg::IndexProgress(progress.clone()));
+						})
+					});
+					sender.input(AppMsg::IndexFinished(result.map_err(|e| e.to_string())));
+				});
+			},
+			AppMsg::IndexProgress(progress) => {
+				self.index_fraction = progress.fraction().unwrap_or(0.0);
+				self.index_status = progress.to_string();
+			},
+			AppMsg::IndexFinished(result) => {
+				self.indexing = false;
+				match result {
+					Ok(report) if !report.stale.is_empty() => {
+						eprintln!("Skipped {} files still holding the records of deleted files", report.stale.len());
+					},
+					Ok(_) => {},
+					Err(e) => eprintln!("Error indexing directory {e}"),
+				}
+			},
+			// Hash on a separate thread like IndexDir, then look up the notes and tags of every copy here
+			AppMsg::FindDuplicates => {
+				let db_path = match self.db.path() {
+					Some(path) => path.to_path_buf(),
+					None => return,
+				};
+				if self.finding_duplicates || self.search_dir.as_os_str().is_empty() {
+					return;
+				}
+
+				self.finding_duplicates = true;
+				let root = self.search_dir.clone();
+				std::thread::spawn(move || {
+					let result = NotesDB::open(db_path).and_then(|db| db.find_duplicates(&[root]));
+					sender.input(AppMsg::DuplicatesFound(result.map_err(|e| e.to_string())));
+				});
+			},
+			AppMsg::HardLinksFound(file, count, found) => {
+				// Another file may have been selected while searching
+				let current = self.current_file.as_ref().map(|f| (f.device, f.inode));
+				if current == Some((file.device, file.inode)) {
+					self.links_text = hard_links_text(count, &found);
+				}
+			},
+			AppMsg::DuplicatesFound(result) => {
+				self.finding_duplicates = false;
+				match result {
+					Ok(groups) => {
+						self.duplicate_groups = groups;
+						self.duplicates.emit(DuplicatesMsg::Show(self.duplicate_sets()));
+					},
+					Err(e) => eprintln!("Error finding duplicates {e}"),
+				}
+			},
+			// The window was closed. The watcher is stopped first, so the event it is applying is not cut short.
+			AppMsg::Quit => {
+				if let Some(watcher) = self.watcher.take() {
+					watcher.stop();
+				}
+				relm4::main_application().quit();
+			},
+			// Add the current directory to the watched ones, or remove it, and restart the watcher to match
+			AppMsg::ToggleWatchDir => {
+				let dir = match fs::canonicalize(&self.search_dir) {
+					Ok(dir) => dir,
+					Err(e) => {
+						eprintln!("Error watching directory {e}");
+						return;
+					},
+				};
+				let result = if self.search_dir_watched {
+					self.db.remove_watch_root(&dir)
+				} else {
+					self.db.add_watch_root(&dir)
+				};
+				if let Err(e) = result {
+					eprintln!("Error changing the watched directories {e}");
+				}
+				self.search_dir_watched = self.is_watched(&self.search_dir);
+
+				if let Some(watcher) = self.watcher.take() {
+					watcher.stop();
+					if let Some(path) = self.db.path() {
+						self.watcher = Some(BackgroundWatcher::spawn(path.to_path_buf(), self.db.rules().clone()));
+					}
+				}
+			},
+			// Run the tagging rules over the current directory on a separate thread like IndexDir
+			AppMsg::ApplyRules => {
+				let db_path = match self.db.path() {
+					Some(path) => path.to_path_buf(),
+					None => return,
+				};
+				if self.applying_rules || self.search_dir.as_os_str().is_empty() {
+					return;
+				}
+
+				self.applying_rules = true;
+				let root = self.search_dir.clone();
+				let rules = self.db.rules().clone();
+				std::thread::spawn(move || {
+					let result = NotesDB::open(db_path).and_then(|mut db| {
+						db.set_rules(rules);
+						db.apply_rules_below(&root, &IndexOptions::default())
+					});
+					sender.input(AppMsg::RulesApplied(result.map_err(|e| e.to_string())));
+				});
+			},
+			AppMsg::RulesApplied(result) => {
+				self.applying_rules = false;
+				match result {
+					Ok(report) if !report.stale.is_empty() => {
+						eprintln!("Skipped {} files still holding the records of deleted files", report.stale.len());
+					},
+					Ok(_) => {},
+					Err(e) => eprintln!("Error applying rules {e}"),
+				}
+
+				// The selected file may have been tagged
+				if let Some(file) = &self.current_file {
+					if let Ok(tags) = self.db.get_tags(file) {
+						self.tag_entry_buffer.set_text(&tags.join(", "));
+					}
+				}
+				self.show_attributes();
+			},
+			// Give every copy in each group the notes and tags of all of them
+			AppMsg::MergeDuplicates(DuplicatesOutput::MergeAll) => {
+				for group in &self.duplicate_groups {
+					if let Err(e) = self.db.merge_metadata(&group.files) {
+						eprintln!("Error merging duplicates {e}");
+					}
+				}
+				self.duplicates.emit(DuplicatesMsg::Show(self.duplicate_sets()));
+			},
+			// Apply a change made in the tag manager, then refresh it and the selected file's tags
+			AppMsg::EditTags(edit) => {
+				let result = match edit {
+					TagManagerOutput::CountFiles(tag) => {
+						match self.db.get_files_by_tag(&tag) {
+							Ok(files) => self.tag_manager.emit(TagManagerMsg::AskDelete(tag, files.len())),
+							Err(e) => eprintln!("Error counting tagged files {e}"),
+						}
+						return;
+					},
+					TagManagerOutput::Rename(old, new) => self.db.rename_tag(&old, &new),
+					TagManagerOutput::Merge(from, into) => self.db.merge_tags(&from, &into),
+					TagManagerOutput::Delete(tag) => self.db.delete_tag(&tag),
+				};
+				if let Err(e) = result {
+					eprintln!("Error editing tags {e}");
+				}
+
+				if let Ok(tags) = self.db.list_tags() {
+					self.tag_manager.emit(TagManagerMsg::Show(tags));
+				}
+				if let Some(file) = &self.current_file {
+					if let Ok(tags) = self.db.get_tags(file) {
+						self.tag_entry_buffer.set_text(&tags.join(", "));
+					}
+				}
+			},
         }
     }
 
-    fn init(db: Self::Init, root: &Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self> {
+    fn init((db, watch): Self::Init, root: &Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self> {
 		// Right Click Menus
 		let menu_list = gtk::gio::Menu::new();
 		menu_list.append(Some("Open File"), Some("win.action_open"));
@@ -305,18 +777,60 @@ impl SimpleComponent for AppModel {
 		});
 		view_click_box.add_controller(&gesture);
 
+		let tag_manager = TagManager::builder()
+			.transient_for(root)
+			.launch(())
+			.forward(sender.input_sender(), AppMsg::EditTags);
+
+		let note_history = NoteHistory::builder()
+			.transient_for(root)
+			.launch(())
+			.forward(sender.input_sender(), AppMsg::RestoreNote);
+
+		let cleanup = Cleanup::builder()
+			.transient_for(root)
+			.launch(())
+			.forward(sender.input_sender(), AppMsg::Cleanup);
+
+		let duplicates = Duplicates::builder()
+			.transient_for(root)
+			.launch(())
+			.forward(sender.input_sender(), AppMsg::MergeDuplicates);
+
+		let watcher = match db.path() {
+			Some(path) if watch => Some(BackgroundWatcher::spawn(path.to_path_buf(), db.rules().clone())),
+			_ => None,
+		};
+
 		// App Model
 		let model = AppModel {
 			db,
             file_elements: FactoryVecDeque::new(gtk::ListBox::default(), sender.input_sender()), 
-			search_dir: String::from(""),
+			search_dir: PathBuf::new(),
 			show_hidden: false,
+			sort_order: SortOrder::default(),
 			dir_entry_buffer: gtk::EntryBuffer::new(Some("")),
 			tag_entry_buffer: gtk::EntryBuffer::new(Some("")),
 			notes_buffer: gtk::TextBuffer::builder().text("").build(),
+			attributes_buffer: gtk::TextBuffer::builder().text("").build(),
+			attribute_entry_buffer: gtk::EntryBuffer::new(Some("")),
 			current_file: None,
+			current_file_stale: false,
 			
 			view_file_context: view_file_context.clone(),
+			tag_manager,
+			note_history,
+			cleanup,
+			duplicates,
+			indexing: false,
+			index_fraction: 0.0,
+			index_status: String::new(),
+			finding_duplicates: false,
+			applying_rules: false,
+			duplicate_groups: Vec::new(),
+			links_text: String::new(),
+			watcher,
+			search_dir_watched: false,
         };
 
         let view_files_list: &gtk::ListBox = model.file_elements.widget();
@@ -327,6 +841,99 @@ impl SimpleComponent for AppModel {
 }
 
 impl AppModel {
+	// Show the note, tags and attributes of a file in the side panel
+	fn show_file(&mut self, fr: FileRef, sender: &ComponentSender<Self>) {
+		// The stored record may belong to a deleted file whose inode was reused. Nothing is shown
+		// or changed until the user says whether it is the same file.
+		self.current_file_stale = matches!(self.db.record_status(&fr), Ok(RecordStatus::Stale));
+		if self.current_file_stale {
+			self.notes_buffer.set_text("");
+			self.tag_entry_buffer.set_text("");
+			self.attributes_buffer.set_text("");
+			self.current_file = Some(fr);
+			self.show_hard_links(sender);
+			return;
+		}
+
+		match self.db.get_note(&fr) {
+			Ok(note) => {
+				self.notes_buffer.set_text(&note);
+			},
+			Err(_) => {
+				self.notes_buffer.set_text("Enter a new note!");
+			},
+		}
+
+		match self.db.get_tags(&fr) {
+			Ok(tags) => {
+				self.tag_entry_buffer.set_text(&tags.join(", "));
+			},
+			Err(_) => {},
+		}
+
+		self.current_file = Some(fr);
+		self.show_attributes();
+		self.show_hard_links(sender);
+	}
+
+	// Edits are not saved while the selected file's record may belong to another file
+	fn refuse_if_stale(&self) -> bool {
+		if self.current_file_stale {
+			eprintln!("Choose whether to keep or discard the stored note first");
+		}
+		self.current_file_stale
+	}
+
+	// List the attributes of the current file in the side panel
+	fn show_attributes(&mut self) {
+		let text = match &self.current_file {
+			Some(file) => match self.db.get_attributes(file) {
+				Ok(attrs) => attrs.iter()
+					.map(|(name, value)| format!("{name} = {value} ({})", value.kind()))
+					.collect::<Vec<_>>()
+					.join("\n"),
+				Err(_) => String::new(),
+			},
+			None => String::new(),
+		};
+		self.attributes_buffer.set_text(&text);
+	}
+
+	// List the other paths of the current file when it has hard links. Only the current
+	// directory is searched, on a separate thread as it may be large, so links elsewhere are
+	// counted but not shown.
+	fn show_hard_links(&mut self, sender: &ComponentSender<Self>) {
+		self.links_text = String::new();
+		let file = match &self.current_file {
+			Some(file) => file.clone(),
+			None => return,
+		};
+		let count = match file.link_count() {
+			Ok(count) if count > 1 => count,
+			_ => return,
+		};
+
+		self.links_text = format!("{count} hard links to this file, searching the current directory");
+		let roots = [self.search_dir.clone()];
+		let sender = sender.clone();
+		std::thread::spawn(move || {
+			let found = find_hard_links(&file, &roots).unwrap_or_default();
+			sender.input(AppMsg::HardLinksFound(file, count, found));
+		});
+	}
+
+	// The notes and tags of every copy in the groups found by the last search for duplicates
+	fn duplicate_sets(&self) -> Vec<DuplicateSet> {
+		self.duplicate_groups.iter().map(|group| DuplicateSet {
+			size: group.size,
+			copies: group.files.iter().map(|file| DuplicateCopy {
+				file_path: file.file_path.clone(),
+				note: self.db.get_note_no_update(file).ok().filter(|note| !note.is_empty()),
+				tags: self.db.get_tags(file).unwrap_or_default(),
+			}).collect(),
+		}).collect()
+	}
+
 	fn get_fileref_by_index(&self, index: usize) -> Option<FileRef> {
 		if let Some(fe) = self.file_elements.get(index) {
 			return Some(fe.file.clone());
@@ -335,14 +942,35 @@ impl AppModel {
 		None
 	}
 
+	// Directories are listed with MIME types guessed from names, so only the selected file is
+	// opened to detect its type from its contents
+	fn detect_mime_type(&mut self, index: usize) {
+		let mut elements = self.file_elements.guard();
+		if let Some(element) = elements.get_mut(index) {
+			if let Ok(m) = fs::symlink_metadata(&element.file.file_path) {
+				let metadata = FileMetadata::from_metadata(&element.file.file_path, &m);
+				element.set_metadata(metadata);
+			}
+		}
+	}
+
+	// Whether dir is one of the watched directories
+	fn is_watched(&self, dir: &Path) -> bool {
+		match fs::canonicalize(dir) {
+			Ok(dir) => self.db.watch_roots().unwrap_or_default().contains(&dir),
+			Err(_) => false,
+		}
+	}
+
 	fn reload_dir(&mut self) {
 		self.file_elements.guard().clear();
+		self.search_dir_watched = self.is_watched(&self.search_dir);
 
 		if let Ok(paths) = fs::read_dir(&self.search_dir)
 		{
 			let mut paths_vec: Vec<std::fs::DirEntry> = paths
-				.map(|p| p.unwrap())
-				.filter(|f| self.show_hidden || f.file_name().into_string().unwrap().as_bytes()[0] != '.' as u8 )
+				.filter_map(Result::ok)
+				.filter(|f| self.show_hidden || !f.file_name().as_bytes().starts_with(b".") )
 				.collect();
 			//let mut paths_vec: Vec<_> = vec![];
 			//for p in paths {
@@ -351,12 +979,30 @@ impl AppModel {
 			//		paths_vec.push(file);
 			//	}
 			//}
-			paths_vec.sort_by_key(|dir| dir.path());
+			let mut files: Vec<(FileRef, Option<FileMetadata>)> = paths_vec.iter()
+				// Entries which vanished or can not be read since the listing are left out
+				.filter_map(|file| {
+					let fr = FileRef::from_direntry(file).ok()?;
+					let metadata = file.metadata().ok().and_then(|m| self.db.current_metadata(&fr, &m).ok());
+					Some((fr, metadata))
+				})
+				.collect();
+			self.sort_order.sort(&mut files);
 
-			for (_i, file) in paths_vec.iter().enumerate() {
-				let fr = FileRef::from_direntry(&file).expect("Tried to create invalid FileRef");
-				self.file_elements.guard().push_back(fr);
+			for (fr, metadata) in files {
+				self.file_elements.guard().push_back((fr, metadata, None));
 			};
 		}
 	}
 }
+
+fn hard_links_text(count: u64, found: &[PathBuf]) -> String {
+	let mut text = format!("{count} hard links to this file:");
+	for path in found {
+		text.push_str(&format!("\n{}", path.display()));
+	}
+	if (found.len() as u64) < count {
+		text.push_str("\nOthers are outside the current directory");
+	}
+	text
+}
@@ -22,15 +22,58 @@
 mod gui_model;
 mod messages;
 mod file_element;
+mod tag_manager;
+mod note_history;
+mod cleanup;
+mod duplicates;
+mod watcher;
 
 use file_chest::NotesDB;
+use file_chest::rules::RuleSet;
 
 use crate::gui_model::AppModel;
 use relm4::prelude::*;
 
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+// Parse the --db <path> (or --db=<path>) command line flag. Arguments are not required to be
+// UTF-8, as paths need not be.
+fn db_path_from_args() -> Option<PathBuf> {
+	let mut args = std::env::args_os().skip(1);
+	while let Some(arg) = args.next() {
+		if arg == "--db" {
+			return args.next().map(PathBuf::from);
+		} else if let Some(path) = arg.as_bytes().strip_prefix(b"--db=") {
+			return Some(PathBuf::from(OsStr::from_bytes(path)));
+		}
+	}
+	None
+}
+
+// Whether a flag such as --sync-xattrs was given on the command line
+fn has_flag(flag: &str) -> bool {
+	std::env::args_os().skip(1).any(|arg| arg == flag)
+}
+
+fn print_warning(warning: &str) {
+	eprintln!("Warning: {warning}");
+}
+
 fn main() {
     let app = RelmApp::new("com.danielragsdale.file_chest");
-	let db = NotesDB::build().expect("Could not load database");
+	let mut db = match db_path_from_args() {
+		Some(path) => NotesDB::open(path),
+		None => NotesDB::build(),
+	}.expect("Could not load database");
+	db.set_warning_handler(print_warning);
+	db.set_xattr_sync(has_flag("--sync-xattrs"));
+	db.set_sidecar_sync(has_flag("--sidecars"));
+	match RuleSet::load_default() {
+		Ok(rules) => db.set_rules(rules),
+		Err(e) => eprintln!("Error loading tagging rules {e}"),
+	}
 
-    app.run::<AppModel>(db);
+    app.run::<AppModel>((db, has_flag("--watch")));
 }
\ No newline at end of file
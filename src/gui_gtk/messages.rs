@@ -26,15 +26,98 @@ pub enum AppMsg {
     SetDir(String),
     SetDirFromSelected,
 	SetShowHidden(bool),
+	SetSortOrder(u32),
 	SelectFile(i32),
+	KeepStaleRecord,
+	ForgetStaleRecord,
 	SubmitNote,
 	SubmitTags(String),
+	SubmitAttribute(String),
 	ShowFileContext(f64, f64),
 	OpenCurrentFile(OpenType),
+	ShowTagManager,
+	EditTags(TagManagerOutput),
+	ShowNoteHistory,
+	RestoreNote(NoteHistoryOutput),
+	ShowCleanup,
+	Cleanup(CleanupOutput),
+	IndexDir,
+	IndexProgress(file_chest::index::IndexProgress),
+	IndexFinished(Result<file_chest::index::IndexReport, String>),
+	FindDuplicates,
+	DuplicatesFound(Result<Vec<file_chest::duplicates::DuplicateGroup>, String>),
+	// A file, its number of links and the paths to it found below the current directory
+	HardLinksFound(file_chest::FileRef, u64, Vec<std::path::PathBuf>),
+	MergeDuplicates(DuplicatesOutput),
+	ToggleWatchDir,
+	Quit,
+	ApplyRules,
+	RulesApplied(Result<file_chest::index::IndexReport, String>),
 }
 
 #[derive(Debug)]
 pub enum OpenType {
 	OpenFile,
 	OpenParent,
+}
+
+#[derive(Debug)]
+pub enum TagManagerMsg {
+	Show(Vec<file_chest::TagInfo>),
+	Hide,
+	Select(i32),
+	Rename,
+	Merge,
+	Delete,
+	// A tag to delete and the number of files using it or a tag below it
+	AskDelete(String, usize),
+	CancelDelete,
+	ConfirmDelete,
+}
+
+#[derive(Debug)]
+pub enum TagManagerOutput {
+	Rename(String, String),
+	Merge(String, String),
+	// Count the files a deletion would affect, before asking to confirm it
+	CountFiles(String),
+	Delete(String),
+}
+
+#[derive(Debug)]
+pub enum NoteHistoryMsg {
+	Show(Vec<file_chest::history::NoteRevision>),
+	Hide,
+	Select(i32),
+	Restore,
+}
+
+#[derive(Debug)]
+pub enum NoteHistoryOutput {
+	Restore(i64),
+}
+
+#[derive(Debug)]
+pub enum CleanupMsg {
+	Show(file_chest::gc::GcReport),
+	Hide,
+	Confirm,
+}
+
+#[derive(Debug)]
+pub enum CleanupOutput {
+	// Delete what the dry run found
+	Confirm(file_chest::gc::GcReport),
+}
+
+#[derive(Debug)]
+pub enum DuplicatesMsg {
+	Show(Vec<crate::duplicates::DuplicateSet>),
+	Hide,
+	MergeAll,
+}
+
+#[derive(Debug)]
+pub enum DuplicatesOutput {
+	MergeAll,
 }
\ No newline at end of file
@@ -0,0 +1,182 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+use crate::messages::*;
+
+use file_chest::history::{diff_lines, DiffLine, NoteRevision};
+
+use gtk::prelude::*;
+use relm4::prelude::*;
+
+// Dialog listing the saved versions of the current file's note. Selecting a version shows
+// what changed compared to the version before it.
+pub struct NoteHistory {
+	visible: bool,
+	revisions: Vec<NoteRevision>,
+	selected: Option<usize>,
+	diff_buffer: gtk::TextBuffer,
+	revision_list: gtk::ListBox,
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for NoteHistory {
+	type Init = ();
+	type Input = NoteHistoryMsg;
+	type Output = NoteHistoryOutput;
+
+	view! {
+		gtk::Window {
+			set_title: Some("Note History"),
+			set_modal: true,
+			set_hide_on_close: true,
+			set_default_width: 640,
+			set_default_height: 420,
+			#[watch]
+			set_visible: model.visible,
+
+			connect_close_request[sender] => move |_| {
+				sender.input(NoteHistoryMsg::Hide);
+				gtk::Inhibit(false)
+			},
+
+			gtk::Box {
+				set_orientation: gtk::Orientation::Horizontal,
+				set_margin_all: 12,
+				set_spacing: 6,
+
+				gtk::Box {
+					set_orientation: gtk::Orientation::Vertical,
+					set_spacing: 6,
+
+					gtk::ScrolledWindow {
+						set_hscrollbar_policy: gtk::PolicyType::Never,
+						set_width_request: 200,
+						set_vexpand: true,
+
+						#[local_ref]
+						revision_list -> gtk::ListBox {
+							connect_row_selected[sender] => move |_self, opt| {
+								if let Some(row) = opt {
+									sender.input(NoteHistoryMsg::Select(row.index()));
+								};
+							},
+						},
+					},
+
+					gtk::Button {
+						set_label: "Restore",
+						connect_clicked[sender] => move |_| {
+							sender.input(NoteHistoryMsg::Restore);
+						}
+					},
+				},
+
+				gtk::ScrolledWindow {
+					set_hexpand: true,
+					set_vexpand: true,
+
+					gtk::TextView {
+						set_editable: false,
+						set_cursor_visible: false,
+						set_monospace: true,
+						set_buffer: Some(&model.diff_buffer),
+					},
+				},
+			}
+		}
+	}
+
+	fn update(&mut self, msg: NoteHistoryMsg, sender: ComponentSender<Self>) {
+		match msg {
+			NoteHistoryMsg::Show(revisions) => {
+				self.set_revisions(revisions);
+				self.visible = true;
+			},
+			NoteHistoryMsg::Hide => {
+				self.visible = false;
+			},
+			NoteHistoryMsg::Select(index) => {
+				self.selected = usize::try_from(index).ok();
+				self.show_diff();
+			},
+			NoteHistoryMsg::Restore => {
+				if let Some(revision) = self.selected.and_then(|i| self.revisions.get(i)) {
+					sender.output(NoteHistoryOutput::Restore(revision.id));
+				}
+			},
+		}
+	}
+
+	fn init(_init: Self::Init, root: &Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self> {
+		let model = NoteHistory {
+			visible: false,
+			revisions: Vec::new(),
+			selected: None,
+			diff_buffer: gtk::TextBuffer::builder().text("").build(),
+			revision_list: gtk::ListBox::default(),
+		};
+
+		let revision_list = &model.revision_list;
+
+		let widgets = view_output!();
+		ComponentParts { model, widgets }
+	}
+}
+
+impl NoteHistory {
+	fn set_revisions(&mut self, revisions: Vec<NoteRevision>) {
+		while let Some(row) = self.revision_list.row_at_index(0) {
+			self.revision_list.remove(&row);
+		}
+
+		for revision in &revisions {
+			let saved_at = gtk::glib::DateTime::from_unix_local(revision.saved_at)
+				.and_then(|t| t.format("%Y-%m-%d %H:%M:%S"))
+				.map(|t| t.to_string())
+				.unwrap_or_else(|_| revision.saved_at.to_string());
+
+			let label = gtk::Label::new(Some(&saved_at));
+			label.set_halign(gtk::Align::Start);
+			label.set_margin_all(2);
+			self.revision_list.append(&label);
+		}
+
+		self.revisions = revisions;
+		self.selected = None;
+		self.diff_buffer.set_text("");
+	}
+
+	// Revisions are newest first, so the one before the selection is at the next index
+	fn show_diff(&mut self) {
+		let text = match self.selected.and_then(|i| self.revisions.get(i).map(|r| (i, r))) {
+			Some((i, revision)) => {
+				let previous = self.revisions.get(i + 1).map(|r| r.note.as_str()).unwrap_or("");
+				diff_lines(previous, &revision.note)
+					.iter()
+					.map(|line| match line {
+						DiffLine::Same(l) => format!("  {l}"),
+						DiffLine::Added(l) => format!("+ {l}"),
+						DiffLine::Removed(l) => format!("- {l}"),
+					})
+					.collect::<Vec<_>>()
+					.join("\n")
+			},
+			None => String::new(),
+		};
+		self.diff_buffer.set_text(&text);
+	}
+}
@@ -0,0 +1,235 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+use crate::messages::*;
+
+use file_chest::TagInfo;
+
+use gtk::prelude::*;
+use relm4::prelude::*;
+
+// Dialog listing every tag, with buttons to rename, merge and delete them.
+// The database lives in AppModel, so changes are sent there as output.
+pub struct TagManager {
+	visible: bool,
+	tags: Vec<TagInfo>,
+	selected: Option<usize>,
+	name_buffer: gtk::EntryBuffer,
+	tag_list: gtk::ListBox,
+	// Tag waiting for its deletion to be confirmed, and the question asked
+	pending_delete: Option<String>,
+	delete_prompt: String,
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for TagManager {
+	type Init = ();
+	type Input = TagManagerMsg;
+	type Output = TagManagerOutput;
+
+	view! {
+		gtk::Window {
+			set_title: Some("Manage Tags"),
+			set_modal: true,
+			set_hide_on_close: true,
+			set_default_width: 360,
+			set_default_height: 420,
+			#[watch]
+			set_visible: model.visible,
+
+			connect_close_request[sender] => move |_| {
+				sender.input(TagManagerMsg::Hide);
+				gtk::Inhibit(false)
+			},
+
+			gtk::Box {
+				set_orientation: gtk::Orientation::Vertical,
+				set_margin_all: 12,
+				set_spacing: 6,
+
+				gtk::ScrolledWindow {
+					set_hscrollbar_policy: gtk::PolicyType::Never,
+					set_vexpand: true,
+
+					#[local_ref]
+					tag_list -> gtk::ListBox {
+						connect_row_selected[sender] => move |_self, opt| {
+							if let Some(row) = opt {
+								sender.input(TagManagerMsg::Select(row.index()));
+							};
+						},
+					},
+				},
+
+				gtk::Entry {
+					set_placeholder_text: Some("New tag name"),
+					set_buffer: &model.name_buffer,
+				},
+
+				gtk::Box {
+					set_orientation: gtk::Orientation::Horizontal,
+					set_spacing: 6,
+					set_homogeneous: true,
+
+					gtk::Button {
+						set_label: "Rename",
+						connect_clicked[sender] => move |_| {
+							sender.input(TagManagerMsg::Rename);
+						}
+					},
+
+					gtk::Button {
+						set_label: "Merge Into",
+						connect_clicked[sender] => move |_| {
+							sender.input(TagManagerMsg::Merge);
+						}
+					},
+
+					gtk::Button {
+						set_label: "Delete",
+						connect_clicked[sender] => move |_| {
+							sender.input(TagManagerMsg::Delete);
+						}
+					},
+				},
+
+				gtk::Box {
+					set_orientation: gtk::Orientation::Vertical,
+					set_spacing: 6,
+					#[watch]
+					set_visible: model.pending_delete.is_some(),
+
+					gtk::Label {
+						set_wrap: true,
+						#[watch]
+						set_text: &model.delete_prompt,
+					},
+
+					gtk::Box {
+						set_orientation: gtk::Orientation::Horizontal,
+						set_spacing: 6,
+						set_homogeneous: true,
+
+						gtk::Button {
+							set_label: "Keep Tag",
+							connect_clicked[sender] => move |_| {
+								sender.input(TagManagerMsg::CancelDelete);
+							}
+						},
+
+						gtk::Button {
+							set_label: "Delete Tag",
+							add_css_class: "destructive-action",
+							connect_clicked[sender] => move |_| {
+								sender.input(TagManagerMsg::ConfirmDelete);
+							}
+						},
+					},
+				},
+			}
+		}
+	}
+
+	fn update(&mut self, msg: TagManagerMsg, sender: ComponentSender<Self>) {
+		match msg {
+			TagManagerMsg::Show(tags) => {
+				self.set_tags(tags);
+				self.visible = true;
+			},
+			TagManagerMsg::Hide => {
+				self.visible = false;
+				self.pending_delete = None;
+			},
+			TagManagerMsg::Select(index) => {
+				self.pending_delete = None;
+				self.selected = usize::try_from(index).ok();
+				if let Some(tag) = self.selected_tag() {
+					self.name_buffer.set_text(&tag);
+				}
+			},
+			TagManagerMsg::Rename => {
+				if let Some(tag) = self.selected_tag() {
+					sender.output(TagManagerOutput::Rename(tag, self.name_buffer.text()));
+				}
+			},
+			TagManagerMsg::Merge => {
+				if let Some(tag) = self.selected_tag() {
+					sender.output(TagManagerOutput::Merge(tag, self.name_buffer.text()));
+				}
+			},
+			// AppModel counts the files using the tag and asks back with AskDelete
+			TagManagerMsg::Delete => {
+				if let Some(tag) = self.selected_tag() {
+					sender.output(TagManagerOutput::CountFiles(tag));
+				}
+			},
+			TagManagerMsg::AskDelete(tag, file_count) => {
+				let files = if file_count == 1 { "1 file uses it".to_string() } else { format!("{file_count} files use it") };
+				self.delete_prompt = format!("Delete {tag} and the tags below it? {files}.");
+				self.pending_delete = Some(tag);
+			},
+			TagManagerMsg::CancelDelete => {
+				self.pending_delete = None;
+			},
+			TagManagerMsg::ConfirmDelete => {
+				if let Some(tag) = self.pending_delete.take() {
+					sender.output(TagManagerOutput::Delete(tag));
+				}
+			},
+		}
+	}
+
+	fn init(_init: Self::Init, root: &Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self> {
+		let model = TagManager {
+			visible: false,
+			tags: Vec::new(),
+			selected: None,
+			name_buffer: gtk::EntryBuffer::new(Some("")),
+			tag_list: gtk::ListBox::default(),
+			pending_delete: None,
+			delete_prompt: String::new(),
+		};
+
+		let tag_list = &model.tag_list;
+
+		let widgets = view_output!();
+		ComponentParts { model, widgets }
+	}
+}
+
+impl TagManager {
+	fn selected_tag(&self) -> Option<String> {
+		self.selected.and_then(|i| self.tags.get(i)).map(|t| t.name.clone())
+	}
+
+	fn set_tags(&mut self, tags: Vec<TagInfo>) {
+		while let Some(row) = self.tag_list.row_at_index(0) {
+			self.tag_list.remove(&row);
+		}
+
+		for tag in &tags {
+			let label = gtk::Label::new(Some(&format!("{} ({})", tag.name, tag.file_count)));
+			label.set_halign(gtk::Align::Start);
+			label.set_margin_all(2);
+			self.tag_list.append(&label);
+		}
+
+		self.tags = tags;
+		self.selected = None;
+		self.pending_delete = None;
+	}
+}
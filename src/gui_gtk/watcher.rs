@@ -0,0 +1,56 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+use file_chest::NotesDB;
+use file_chest::rules::RuleSet;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+
+// Keeps known paths current while the app runs, using a separate connection on its own thread
+pub struct BackgroundWatcher {
+	stop: Arc<AtomicBool>,
+	thread: JoinHandle<()>,
+}
+
+impl BackgroundWatcher {
+	// Watch the directories stored in the database at db_path
+	pub fn spawn(db_path: PathBuf, rules: RuleSet) -> Self {
+		let stop = Arc::new(AtomicBool::new(false));
+		let thread_stop = stop.clone();
+		let thread = std::thread::spawn(move || {
+			let result = NotesDB::open(db_path).and_then(|mut db| {
+				db.set_warning_handler(crate::print_warning);
+				db.set_rules(rules);
+				let roots = db.watch_roots()?;
+				db.watch(&roots, &thread_stop)
+			});
+			if let Err(e) = result {
+				eprintln!("Error watching directories {e}");
+			}
+		});
+		Self { stop, thread }
+	}
+
+	// Stop watching once the events being applied are done
+	pub fn stop(self) {
+		self.stop.store(true, Ordering::Relaxed);
+		let _ = self.thread.join();
+	}
+}
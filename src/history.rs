@@ -0,0 +1,109 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+/* Note revision history
+ *
+ * Triggers on file_notes copy every new version of a note into note_revisions,
+ * so nothing written through set_note is ever lost.
+ */
+
+use crate::{FileChestError, FileRef, NotesDB};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoteRevision {
+	pub id: i64,
+	pub note: String,
+	// Seconds since the unix epoch
+	pub saved_at: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine {
+	Same(String),
+	Added(String),
+	Removed(String),
+}
+
+// Line based diff of two texts, using the longest common subsequence of their lines
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+	let old: Vec<&str> = old.lines().collect();
+	let new: Vec<&str> = new.lines().collect();
+
+	// lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+	let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+	for i in (0..old.len()).rev() {
+		for j in (0..new.len()).rev() {
+			lcs[i][j] = if old[i] == new[j] {
+				lcs[i + 1][j + 1] + 1
+			} else {
+				lcs[i + 1][j].max(lcs[i][j + 1])
+			};
+		}
+	}
+
+	let mut diff = Vec::new();
+	let (mut i, mut j) = (0, 0);
+	while i < old.len() && j < new.len() {
+		if old[i] == new[j] {
+			diff.push(DiffLine::Same(old[i].to_string()));
+			i += 1;
+			j += 1;
+		} else if lcs[i + 1][j] >= lcs[i][j + 1] {
+			diff.push(DiffLine::Removed(old[i].to_string()));
+			i += 1;
+		} else {
+			diff.push(DiffLine::Added(new[j].to_string()));
+			j += 1;
+		}
+	}
+	diff.extend(old[i..].iter().map(|l| DiffLine::Removed(l.to_string())));
+	diff.extend(new[j..].iter().map(|l| DiffLine::Added(l.to_string())));
+
+	diff
+}
+
+impl NotesDB {
+	// Every saved version of a file's note, newest first
+	pub fn note_history(&self, file_ref: &FileRef) -> Result<Vec<NoteRevision>, FileChestError> {
+		self.check_not_stale(file_ref)?;
+
+		let mut stmt = self.conn.prepare(
+			"SELECT note_revisions.id, note_revisions.note, note_revisions.saved_at FROM note_revisions
+			INNER JOIN file_notes ON note_revisions.file_id=file_notes.id
+			WHERE file_notes.device=?1 AND file_notes.inode=?2
+			ORDER BY note_revisions.id DESC")?;
+		let revision_iter = stmt.query_map((file_ref.device, file_ref.inode), |row| {
+			Ok(NoteRevision {
+				id: row.get(0)?,
+				note: row.get::<usize, Option<String>>(1)?.unwrap_or_default(),
+				saved_at: row.get(2)?,
+			})
+		})?;
+
+		Ok(revision_iter.collect::<Result<_, _>>()?)
+	}
+
+	// Make an earlier revision the current note. This is saved as a new revision, so it can be undone.
+	pub fn restore_note_revision(&self, file_ref: &FileRef, revision_id: i64) -> Result<(), FileChestError> {
+		let revision = self.note_history(file_ref)?
+			.into_iter()
+			.find(|r| r.id == revision_id)
+			.ok_or_else(|| FileChestError::NotFound(format!("Revision {revision_id} of {}", file_ref.file_path.display())))?;
+
+		self.set_note(file_ref, &revision.note)
+	}
+}
@@ -0,0 +1,290 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+/* Recursive indexing of directory trees
+ *
+ * Registers every file below a root with add_file and stores its metadata (see metadata.rs),
+ * so files can be searched and cleaned up without being tagged or noted first. Automatic tagging
+ * rules are applied to each file as it is registered.
+ * Hidden files are skipped, as are paths matched by .gitignore, .ignore or .filechestignore
+ * files, which use the gitignore syntax. Symbolic links are recorded but not followed.
+ */
+
+use crate::metadata::FileMetadata;
+use crate::sidecar::SIDECAR_FILE;
+use crate::{fingerprint_params, path_param, FileChestError, FileRef, Fingerprint, NotesDB, RecordStatus};
+
+use std::ffi::OsStr;
+use std::fs::Metadata;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use rusqlite::OptionalExtension;
+
+pub const IGNORE_FILE: &str = ".filechestignore";
+
+// Files are written in batches of this size, each in one transaction. Indexing reports its
+// progress after each one. Also used when applying the tagging rules.
+pub(crate) const BATCH_SIZE: usize = 500;
+
+// Kind of filesystem entry, stored in the file_type column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+	File,
+	Dir,
+	Symlink,
+	// Devices, sockets and pipes
+	Other,
+}
+
+impl FileType {
+	pub fn from_metadata(m: &Metadata) -> Self {
+		let file_type = m.file_type();
+		if file_type.is_symlink() {
+			FileType::Symlink
+		} else if file_type.is_dir() {
+			FileType::Dir
+		} else if file_type.is_file() {
+			FileType::File
+		} else {
+			FileType::Other
+		}
+	}
+
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			FileType::File => "file",
+			FileType::Dir => "dir",
+			FileType::Symlink => "symlink",
+			FileType::Other => "other",
+		}
+	}
+
+	pub fn parse(text: &str) -> Option<Self> {
+		match text {
+			"file" => Some(FileType::File),
+			"dir" => Some(FileType::Dir),
+			"symlink" => Some(FileType::Symlink),
+			"other" => Some(FileType::Other),
+			_ => None,
+		}
+	}
+}
+
+impl std::fmt::Display for FileType {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{}", self.as_str())
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct IndexOptions {
+	// Index files and directories whose names start with a dot
+	pub include_hidden: bool,
+	// Skip paths matched by .gitignore, .ignore and .filechestignore files
+	pub respect_ignore_files: bool,
+	// Register directories as well as the files in them
+	pub include_dirs: bool,
+}
+
+impl Default for IndexOptions {
+	fn default() -> Self {
+		Self {
+			include_hidden: false,
+			respect_ignore_files: true,
+			include_dirs: false,
+		}
+	}
+}
+
+// Passed to the progress callback of NotesDB::index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexProgress {
+	// Still walking the tree, so the total is not known yet
+	Scanning { found: usize },
+	Indexing { done: usize, total: usize },
+}
+
+impl IndexProgress {
+	// Share of the work done, from 0 to 1. None while scanning.
+	pub fn fraction(&self) -> Option<f64> {
+		match self {
+			IndexProgress::Scanning { .. } => None,
+			IndexProgress::Indexing { total: 0, .. } => Some(1.0),
+			IndexProgress::Indexing { done, total } => Some(*done as f64 / *total as f64),
+		}
+	}
+}
+
+impl std::fmt::Display for IndexProgress {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			IndexProgress::Scanning { found } => write!(f, "Scanning: {found} files found"),
+			IndexProgress::Indexing { done, total } => write!(f, "Indexing: {done} of {total} files"),
+		}
+	}
+}
+
+// Result of NotesDB::index and NotesDB::apply_rules_below
+#[derive(Debug, Default, Clone)]
+pub struct IndexReport {
+	// Entries looked at, including stale ones. Rules only look at regular files.
+	pub checked: usize,
+	// Files which had no record before
+	pub added: usize,
+	// Files whose existing record was refreshed
+	pub updated: usize,
+	// Files left alone because their inode still holds the record of a deleted file
+	pub stale: Vec<PathBuf>,
+	// Entries which could not be read, e.g. for lack of permission
+	pub unreadable: usize,
+	// Files which matched at least one automatic tagging rule, see rules.rs
+	pub matched_rules: usize,
+}
+
+impl NotesDB {
+	// Register every file below root. progress is called while the tree is walked and after
+	// every batch of files written, not for every single file.
+	pub fn index<P: AsRef<Path>>(
+		&self,
+		root: P,
+		options: &IndexOptions,
+		progress: &mut dyn FnMut(&IndexProgress),
+	) -> Result<IndexReport, FileChestError> {
+		let mut report = IndexReport::default();
+		let found = scan(root.as_ref(), options, &mut report, progress)?;
+
+		let total = found.len();
+		for (batch_number, batch) in found.chunks(BATCH_SIZE).enumerate() {
+			self.atomic(|| {
+				for (file_ref, metadata) in batch {
+					self.index_file(file_ref, metadata, &mut report)?;
+				}
+				Ok(())
+			})?;
+			progress(&IndexProgress::Indexing { done: batch_number * BATCH_SIZE + batch.len(), total });
+		}
+
+		if total == 0 {
+			progress(&IndexProgress::Indexing { done: 0, total });
+		}
+		Ok(report)
+	}
+
+	fn index_file(&self, file_ref: &FileRef, metadata: &FileMetadata, report: &mut IndexReport) -> Result<(), FileChestError> {
+		report.checked += 1;
+		match self.record_status(file_ref)? {
+			RecordStatus::Missing => report.added += 1,
+			RecordStatus::Current => report.updated += 1,
+			RecordStatus::Stale => {
+				report.stale.push(file_ref.file_path.clone());
+				return Ok(());
+			},
+		}
+
+		self.add_file(file_ref)?;
+		self.set_file_metadata(file_ref, metadata)?;
+		if self.apply_rules_to(file_ref, metadata)? > 0 {
+			report.matched_rules += 1;
+		}
+		Ok(())
+	}
+
+	// Store the file's current path, fingerprint and metadata in its record
+	pub(crate) fn set_file_metadata(&self, file_ref: &FileRef, metadata: &FileMetadata) -> Result<(), FileChestError> {
+		let (birth_time, _, _, _) = fingerprint_params(file_ref);
+		self.conn.execute(
+			"UPDATE file_notes SET known_path = ?1, missing_since = NULL, birth_time = COALESCE(?4, birth_time)
+			WHERE device = ?2 AND inode = ?3;",
+			(path_param(file_ref)?, file_ref.device, file_ref.inode, birth_time)
+		)?;
+		self.store_metadata(file_ref, metadata)
+	}
+
+	// The type stored by the last index, None for files which were never indexed
+	pub fn get_file_type(&self, file_ref: &FileRef) -> Result<Option<FileType>, FileChestError> {
+		let stored = self.conn.query_row(
+			"SELECT file_type FROM file_notes WHERE device=?1 AND inode=?2;",
+			(file_ref.device, file_ref.inode),
+			|row| row.get::<usize, Option<String>>(0)
+		).optional()?.flatten();
+
+		Ok(stored.as_deref().and_then(FileType::parse))
+	}
+}
+
+// Walk the tree below root, returning every entry to index.
+// A relative root is made absolute first, so that the paths found can be stored.
+pub(crate) fn scan(
+	root: &Path,
+	options: &IndexOptions,
+	report: &mut IndexReport,
+	progress: &mut dyn FnMut(&IndexProgress),
+) -> Result<Vec<(FileRef, FileMetadata)>, FileChestError> {
+	let mut builder = ignore::WalkBuilder::new(std::path::absolute(root)?);
+	builder.hidden(!options.include_hidden)
+		.git_ignore(options.respect_ignore_files)
+		.git_global(false)
+		.git_exclude(options.respect_ignore_files)
+		.ignore(options.respect_ignore_files)
+		.parents(options.respect_ignore_files)
+		// Honour .gitignore files outside of git repositories too
+		.require_git(false);
+	if options.respect_ignore_files {
+		builder.add_custom_ignore_filename(IGNORE_FILE);
+	}
+
+	let mut found = Vec::new();
+	for entry in builder.build() {
+		let entry = match entry {
+			Ok(entry) => entry,
+			Err(_) => {
+				report.unreadable += 1;
+				continue;
+			},
+		};
+		// The root itself, and FileChest's own files
+		if entry.depth() == 0 || [SIDECAR_FILE, IGNORE_FILE].iter().any(|name| entry.file_name() == OsStr::new(name)) {
+			continue;
+		}
+
+		let m = match entry.metadata() {
+			Ok(m) => m,
+			Err(_) => {
+				report.unreadable += 1;
+				continue;
+			},
+		};
+		let metadata = FileMetadata::from_metadata(entry.path(), &m);
+		if metadata.file_type == FileType::Dir && !options.include_dirs {
+			continue;
+		}
+
+		found.push((FileRef {
+			file_path: entry.into_path(),
+			device: m.dev(),
+			inode: m.ino(),
+			fingerprint: Some(Fingerprint::from_metadata(&m)),
+		}, metadata));
+
+		if found.len() % BATCH_SIZE == 0 {
+			progress(&IndexProgress::Scanning { found: found.len() });
+		}
+	}
+
+	Ok(found)
+}
@@ -15,181 +15,1266 @@
  * Place, Suite 330, Boston, MA  02111-1307  USA
  */
 
+pub mod attributes;
+pub mod duplicates;
+pub mod error;
+pub mod export;
+pub mod gc;
+pub mod history;
+pub mod index;
+pub mod metadata;
+pub mod mime_type;
+pub mod query;
+pub mod rules;
+pub mod sidecar;
+pub mod watch;
+pub mod xattrs;
+
 use std::fs;
 //use std::io::ErrorKind;
-use std::fs::DirEntry;
-use std::path::PathBuf;
+use std::fs::{DirEntry, Metadata};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 
 use std::os::unix::fs::MetadataExt;
 use std::os::unix::fs::DirEntryExt;
+use std::os::unix::ffi::OsStrExt;
+use std::ffi::OsStr;
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use rusqlite::{Connection, OptionalExtension, Result, Row, Transaction};
+use rusqlite::types::{FromSqlError, ValueRef};
+
+use crate::metadata::SortOrder;
+use crate::query::Query;
+use crate::rules::RuleSet;
+
+pub use crate::error::FileChestError;
+
+// Environment variable which overrides the default database location
+pub const DB_ENV_VAR: &str = "FILECHEST_DB";
+
+const FC_DIR: &str = "filechest";
+const DB_FILE: &str = "chest.db";
+
+// Where earlier versions kept the database: .filechest in the working directory in debug
+// builds, and ~/.filechest in release builds, where the ~ was never expanded and so named
+// a directory in the working directory
+const LEGACY_HOME_DIR: &str = "~";
+const LEGACY_FC_DIR: &str = ".filechest";
+const LEGACY_DB_FILE: &str = "test.db";
+
+// Resolve the database location: $FILECHEST_DB if set, otherwise $XDG_DATA_HOME/filechest/chest.db
+pub fn default_db_path() -> Option<PathBuf> {
+	if let Some(path) = env_db_path() {
+		return Some(path);
+	}
 
-//use dirs::home_dir;
+	dirs::data_dir().map(|dir| dir.join(FC_DIR).join(DB_FILE))
+}
 
-use rusqlite::{Connection, Result};
+fn env_db_path() -> Option<PathBuf> {
+	std::env::var_os(DB_ENV_VAR).filter(|path| !path.is_empty()).map(PathBuf::from)
+}
 
-#[cfg(debug_assertions)]
-const FC_DIR: &str = ".filechest";
+// Copy the database at legacy to path, going through a temporary file so an interrupted copy
+// is not taken for a database
+fn copy_legacy_db(legacy: &Path, path: &Path) -> Result<(), FileChestError> {
+	if let Some(parent) = path.parent() {
+		fs::create_dir_all(parent)?;
+	}
+	let temp = path.with_extension("db.tmp");
+	fs::copy(legacy, &temp)?;
+	fs::rename(&temp, path)?;
+	Ok(())
+}
 
-#[cfg(not(debug_assertions))]
-const FC_DIR: &str = "~/.filechest";
+// Databases left behind by older versions, in the order they are looked for
+pub fn legacy_db_paths() -> Vec<PathBuf> {
+	let mut paths: Vec<PathBuf> = dirs::home_dir().into_iter()
+		.map(|home| home.join(LEGACY_FC_DIR).join(LEGACY_DB_FILE))
+		.collect();
+	paths.push(Path::new(LEGACY_HOME_DIR).join(LEGACY_FC_DIR).join(LEGACY_DB_FILE));
+	paths.push(Path::new(LEGACY_FC_DIR).join(LEGACY_DB_FILE));
+	paths
+}
 
+// A file is identified by the device it lives on together with its inode on that device.
+// Inode numbers alone are only unique within a single filesystem.
 #[derive(Debug, Default, Clone)]
 pub struct FileRef {
 	pub file_path: PathBuf,
+	pub device: u64,
 	pub inode: u64,
+	pub fingerprint: Option<Fingerprint>,
 }
 
 impl FileRef {
-	pub fn from_pathbuf(pb: &PathBuf) -> Result<Self, std::io::Error> {
+	// Relative paths are made absolute against the working directory, without following links
+	pub fn from_pathbuf(pb: &PathBuf) -> Result<Self, FileChestError> {
 		//let file_path = pb.canonicalize()?;
 		//println!("Raw {:?} , Canonical: {:?}", pb, file_path);
 		let m = std::fs::symlink_metadata(pb)?;
-		let inode = m.ino();
 		
-		Ok(Self { file_path: pb.clone(), inode, })
+		Ok(Self {
+			file_path: std::path::absolute(pb)?,
+			device: m.dev(),
+			inode: m.ino(),
+			fingerprint: Some(Fingerprint::from_metadata(&m)),
+		})
+	}
+
+	pub fn from_direntry(de: &DirEntry) -> Result<Self, FileChestError> {
+		let m = de.metadata()?;
+		Ok(Self {
+			file_path: std::path::absolute(de.path())?,
+			device: m.dev(),
+			inode: de.ino(),
+			fingerprint: Some(Fingerprint::from_metadata(&m)),
+		})
+	}
+}
+
+// Properties of a file used to tell it apart from a later file which reuses the same inode.
+// Times are in nanoseconds since the unix epoch.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Fingerprint {
+	pub birth_time: Option<i64>,
+	pub size: u64,
+	pub mtime: i64,
+	// Unknown for records stored before version 14 of the schema
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub ctime: Option<i64>,
+}
+
+impl Fingerprint {
+	pub fn from_metadata(m: &Metadata) -> Self {
+		let birth_time = m.created().ok()
+			.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+			.map(|d| d.as_nanos() as i64);
+
+		Self {
+			birth_time,
+			size: m.size(),
+			mtime: m.mtime() * 1_000_000_000 + m.mtime_nsec(),
+			ctime: Some(m.ctime() * 1_000_000_000 + m.ctime_nsec()),
+		}
+	}
+
+	// Whether the file now described by current could be the one this fingerprint was stored for.
+	// Only birth times can tell. Without them an edited file can not be told apart from a new file
+	// which reused the inode, and taking an edit for a new file would cost the user their notes,
+	// so the file counts as the same: inode reuse goes unnoticed on filesystems without birth times.
+	pub fn matches(&self, current: &Fingerprint) -> bool {
+		match (self.birth_time, current.birth_time) {
+			(Some(a), Some(b)) => a == b,
+			_ => true,
+		}
+	}
+}
+
+// How a stored record relates to the file a FileRef currently points at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordStatus {
+	Missing,
+	Current,
+	Stale,
+}
+
+// Ordered list of schema migrations. MIGRATIONS[n] upgrades a database from version n to n + 1.
+// Existing entries must never be edited once released; add a new migration instead.
+type Migration = fn(&Transaction) -> Result<(), rusqlite::Error>;
+
+const MIGRATIONS: &[Migration] = &[
+	migrate_v1,
+	migrate_v2,
+	migrate_v3,
+	migrate_v4,
+	migrate_v5,
+	migrate_v6,
+	migrate_v7,
+	migrate_v8,
+	migrate_v9,
+	migrate_v10,
+	migrate_v11,
+	migrate_v12,
+	migrate_v13,
+	migrate_v14,
+];
+
+// Current version of the database schema, stored in PRAGMA user_version
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+// Bring the database up to SCHEMA_VERSION, applying all pending migrations in a single transaction
+fn migrate(conn: &mut Connection) -> Result<(), FileChestError> {
+	let version: u32 = conn.query_row("PRAGMA user_version;", (), |row| row.get(0))?;
+
+	if version > SCHEMA_VERSION {
+		return Err(FileChestError::SchemaVersion { found: version, supported: SCHEMA_VERSION });
+	}
+
+	if version == SCHEMA_VERSION {
+		return Ok(());
+	}
+
+	let tx = conn.transaction()?;
+	for migration in &MIGRATIONS[version as usize..] {
+		migration(&tx)?;
+	}
+	tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+	tx.commit()?;
+
+	Ok(())
+}
+
+// 0 -> 1: Initial schema. Databases created before versioning already have these tables.
+fn migrate_v1(tx: &Transaction) -> Result<(), rusqlite::Error> {
+	tx.execute(
+		"CREATE TABLE IF NOT EXISTS file_notes (
+			inode INTEGER PRIMARY KEY,
+			known_path TEXT,
+			note TEXT
+		);",
+		()
+	)?;
+
+	tx.execute(
+		"CREATE TABLE IF NOT EXISTS file_tags (
+			id INTEGER PRIMARY KEY AUTOINCREMENT,
+			tag_name VARCHAR(255) UNIQUE
+		);",
+		()
+	)?;
+
+	tx.execute(
+		"CREATE TABLE IF NOT EXISTS tag_relations (
+			--relation_id INTEGER PRIMARY KEY AUTOINCREMENT,
+			tag_id INTEGER NOT NULL,
+			file_id INTEGER NOT NULL,
+
+			FOREIGN KEY(tag_id) REFERENCES file_tags(id),	
+			FOREIGN KEY(file_id) REFERENCES file_notes(inode),
+
+			CONSTRAINT uc_tfid UNIQUE (tag_id, file_id)
+		);",
+		()
+	)?;
+
+	Ok(())
+}
+
+// 1 -> 2: Identify files by (device, inode) instead of the bare inode.
+// The device of existing rows is recovered by checking the file at known_path still has the stored inode.
+// Rows whose file can not be found get device 0 until the file turns up at known_path, see NotesDB::adopt_device.
+fn migrate_v2(tx: &Transaction) -> Result<(), rusqlite::Error> {
+	tx.execute_batch(
+		"ALTER TABLE file_notes RENAME TO file_notes_v1;
+		ALTER TABLE tag_relations RENAME TO tag_relations_v1;
+
+		CREATE TABLE file_notes (
+			id INTEGER PRIMARY KEY AUTOINCREMENT,
+			device INTEGER NOT NULL,
+			inode INTEGER NOT NULL,
+			known_path TEXT,
+			note TEXT,
+
+			CONSTRAINT uc_devino UNIQUE (device, inode)
+		);
+
+		CREATE TABLE tag_relations (
+			tag_id INTEGER NOT NULL,
+			file_id INTEGER NOT NULL,
+
+			FOREIGN KEY(tag_id) REFERENCES file_tags(id),
+			FOREIGN KEY(file_id) REFERENCES file_notes(id),
+
+			CONSTRAINT uc_tfid UNIQUE (tag_id, file_id)
+		);"
+	)?;
+
+	let old_files = {
+		let mut stmt = tx.prepare("SELECT inode, known_path, note FROM file_notes_v1;")?;
+		let rows = stmt.query_map((), |row| {
+			Ok((row.get::<usize, u64>(0)?, row.get::<usize, Option<String>>(1)?, row.get::<usize, Option<String>>(2)?))
+		})?;
+		rows.collect::<Result<Vec<_>, _>>()?
+	};
+
+	for (inode, known_path, note) in old_files {
+		let device = known_path.as_ref()
+			.and_then(|p| fs::symlink_metadata(p).ok())
+			.filter(|m| m.ino() == inode)
+			.map_or(0, |m| m.dev());
+
+		tx.execute(
+			"INSERT INTO file_notes(device, inode, known_path, note) VALUES(?1, ?2, ?3, ?4);",
+			(device, inode, known_path, note),
+		)?;
+	}
+
+	tx.execute_batch(
+		"INSERT INTO tag_relations(tag_id, file_id)
+			SELECT tag_relations_v1.tag_id, file_notes.id FROM tag_relations_v1
+			INNER JOIN file_notes ON file_notes.inode=tag_relations_v1.file_id;
+
+		DROP TABLE tag_relations_v1;
+		DROP TABLE file_notes_v1;"
+	)?;
+
+	Ok(())
+}
+
+// 2 -> 3: Store a fingerprint with each file to detect inode reuse.
+// Existing rows get no fingerprint and pick one up the next time they are looked up.
+fn migrate_v3(tx: &Transaction) -> Result<(), rusqlite::Error> {
+	tx.execute_batch(
+		"ALTER TABLE file_notes ADD COLUMN birth_time INTEGER;
+		ALTER TABLE file_notes ADD COLUMN size INTEGER;
+		ALTER TABLE file_notes ADD COLUMN mtime INTEGER;"
+	)
+}
+
+// 3 -> 4: Hierarchical tags. Every tag "a/b/c" gets a parent "a/b", created if needed.
+fn migrate_v4(tx: &Transaction) -> Result<(), rusqlite::Error> {
+	tx.execute(
+		"ALTER TABLE file_tags ADD COLUMN parent_id INTEGER REFERENCES file_tags(id);",
+		()
+	)?;
+
+	let tags = {
+		let mut stmt = tx.prepare("SELECT tag_name FROM file_tags WHERE tag_name LIKE '%/%';")?;
+		let rows = stmt.query_map((), |row| row.get::<usize, String>(0))?;
+		rows.collect::<Result<Vec<_>, _>>()?
+	};
+
+	for tag in tags {
+		ensure_tag(tx, &tag)?;
+	}
+
+	Ok(())
+}
+
+// 4 -> 5: Full text index over notes, kept in sync with file_notes by triggers
+fn migrate_v5(tx: &Transaction) -> Result<(), rusqlite::Error> {
+	tx.execute_batch(
+		"CREATE VIRTUAL TABLE notes_fts USING fts5(note, content='file_notes', content_rowid='id');
+
+		CREATE TRIGGER file_notes_fts_insert AFTER INSERT ON file_notes BEGIN
+			INSERT INTO notes_fts(rowid, note) VALUES (new.id, new.note);
+		END;
+
+		CREATE TRIGGER file_notes_fts_delete AFTER DELETE ON file_notes BEGIN
+			INSERT INTO notes_fts(notes_fts, rowid, note) VALUES ('delete', old.id, old.note);
+		END;
+
+		CREATE TRIGGER file_notes_fts_update AFTER UPDATE OF note ON file_notes BEGIN
+			INSERT INTO notes_fts(notes_fts, rowid, note) VALUES ('delete', old.id, old.note);
+			INSERT INTO notes_fts(rowid, note) VALUES (new.id, new.note);
+		END;
+
+		INSERT INTO notes_fts(notes_fts) VALUES ('rebuild');"
+	)
+}
+
+// 5 -> 6: Typed key/value attributes on files
+fn migrate_v6(tx: &Transaction) -> Result<(), rusqlite::Error> {
+	tx.execute(
+		"CREATE TABLE file_attributes (
+			file_id INTEGER NOT NULL,
+			name TEXT NOT NULL,
+			kind TEXT NOT NULL,
+			value,
+
+			FOREIGN KEY(file_id) REFERENCES file_notes(id),
+
+			CONSTRAINT uc_fileattr UNIQUE (file_id, name)
+		);",
+		()
+	)?;
+	Ok(())
+}
+
+// 6 -> 7: Keep every version of a note. Existing notes become the first revision.
+fn migrate_v7(tx: &Transaction) -> Result<(), rusqlite::Error> {
+	tx.execute_batch(
+		"CREATE TABLE note_revisions (
+			id INTEGER PRIMARY KEY AUTOINCREMENT,
+			file_id INTEGER NOT NULL,
+			note TEXT,
+			saved_at INTEGER NOT NULL,
+
+			FOREIGN KEY(file_id) REFERENCES file_notes(id)
+		);
+
+		CREATE TRIGGER file_notes_revision_insert AFTER INSERT ON file_notes
+		WHEN new.note IS NOT NULL BEGIN
+			INSERT INTO note_revisions(file_id, note, saved_at)
+			VALUES (new.id, new.note, CAST(strftime('%s', 'now') AS INTEGER));
+		END;
+
+		CREATE TRIGGER file_notes_revision_update AFTER UPDATE OF note ON file_notes
+		WHEN new.note IS NOT old.note BEGIN
+			INSERT INTO note_revisions(file_id, note, saved_at)
+			VALUES (new.id, new.note, CAST(strftime('%s', 'now') AS INTEGER));
+		END;
+
+		INSERT INTO note_revisions(file_id, note, saved_at)
+		SELECT id, note, CAST(strftime('%s', 'now') AS INTEGER) FROM file_notes WHERE note IS NOT NULL;"
+	)
+}
+
+// 7 -> 8: Store known_path as the raw bytes of the path so names which are not valid UTF-8 survive.
+// The column keeps its TEXT declaration; SQLite stores BLOB values in it unchanged.
+fn migrate_v8(tx: &Transaction) -> Result<(), rusqlite::Error> {
+	tx.execute(
+		"UPDATE file_notes SET known_path = CAST(known_path AS BLOB) WHERE typeof(known_path) = 'text';",
+		()
+	)?;
+	Ok(())
+}
+
+// 8 -> 9: Remember when a file was first found missing, so gc can wait out a grace period
+fn migrate_v9(tx: &Transaction) -> Result<(), rusqlite::Error> {
+	tx.execute("ALTER TABLE file_notes ADD COLUMN missing_since INTEGER;", ())?;
+	Ok(())
+}
+
+// 9 -> 10: Remember when a file's note or tags last changed, so sidecar sync can tell which side is newer.
+// Existing rows get no time and lose to any sidecar which has one.
+fn migrate_v10(tx: &Transaction) -> Result<(), rusqlite::Error> {
+	tx.execute_batch(
+		"ALTER TABLE file_notes ADD COLUMN changed_at INTEGER;
+
+		CREATE TRIGGER file_notes_changed_insert AFTER INSERT ON file_notes
+		WHEN new.note IS NOT NULL BEGIN
+			UPDATE file_notes SET changed_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE id = new.id;
+		END;
+
+		CREATE TRIGGER file_notes_changed_update AFTER UPDATE OF note ON file_notes
+		WHEN new.note IS NOT old.note BEGIN
+			UPDATE file_notes SET changed_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE id = new.id;
+		END;
+
+		CREATE TRIGGER tag_relations_changed_insert AFTER INSERT ON tag_relations BEGIN
+			UPDATE file_notes SET changed_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE id = new.file_id;
+		END;
+
+		CREATE TRIGGER tag_relations_changed_delete AFTER DELETE ON tag_relations BEGIN
+			UPDATE file_notes SET changed_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE id = old.file_id;
+		END;"
+	)
+}
+
+// 10 -> 11: Record whether an entry is a file, directory or link, filled in by the indexer
+fn migrate_v11(tx: &Transaction) -> Result<(), rusqlite::Error> {
+	tx.execute("ALTER TABLE file_notes ADD COLUMN file_type TEXT;", ())?;
+	Ok(())
+}
+
+// 11 -> 12: Directory trees watched for moves and deletions, shared by the app and the watch daemon
+fn migrate_v12(tx: &Transaction) -> Result<(), rusqlite::Error> {
+	tx.execute("CREATE TABLE watch_roots (path BLOB NOT NULL UNIQUE);", ())?;
+	Ok(())
+}
+
+// 12 -> 13: Cache of content hashes, valid while a file keeps the size and mtime it was hashed with
+fn migrate_v13(tx: &Transaction) -> Result<(), rusqlite::Error> {
+	tx.execute(
+		"CREATE TABLE content_hashes (
+			device INTEGER NOT NULL,
+			inode INTEGER NOT NULL,
+			size INTEGER NOT NULL,
+			mtime INTEGER NOT NULL,
+			hash TEXT NOT NULL,
+
+			CONSTRAINT uc_hash_devino UNIQUE (device, inode)
+		);",
+		()
+	)?;
+	tx.execute("CREATE INDEX content_hashes_hash ON content_hashes(hash);", ())?;
+	Ok(())
+}
+
+// 13 -> 14: Cache the rest of a file's metadata next to its fingerprint, see metadata.rs.
+// Existing rows fill these in the next time they are indexed or looked up.
+fn migrate_v14(tx: &Transaction) -> Result<(), rusqlite::Error> {
+	tx.execute_batch(
+		"ALTER TABLE file_notes ADD COLUMN ctime INTEGER;
+		ALTER TABLE file_notes ADD COLUMN permissions INTEGER;
+		ALTER TABLE file_notes ADD COLUMN mime_type TEXT;
+
+		CREATE INDEX file_notes_size ON file_notes(size);
+		CREATE INDEX file_notes_mtime ON file_notes(mtime);"
+	)
+}
+
+// Turn user input into an FTS5 query matching notes which contain every word.
+// Words are quoted so punctuation is not read as FTS5 syntax; a trailing * keeps prefix matching.
+// Text without any words gives an empty query, which FTS5 rejects.
+pub fn fts_query(text: &str) -> String {
+	text.split_whitespace()
+		.filter_map(|word| {
+			let (word, star) = match word.strip_suffix('*') {
+				Some(w) => (w, "*"),
+				None => (word, ""),
+			};
+			(!word.is_empty()).then(|| format!("\"{}\"{star}", word.replace('"', "\"\"")))
+		})
+		.collect::<Vec<_>>()
+		.join(" ")
+}
+
+// Tidy up a tag path: "project / alpha//design/" becomes "project/alpha/design"
+pub fn normalize_tag(tag: &str) -> String {
+	tag.split('/')
+		.map(|part| part.trim())
+		.filter(|part| !part.is_empty())
+		.collect::<Vec<_>>()
+		.join("/")
+}
+
+// Get the id of a (normalized) tag, creating it and any missing ancestors
+fn ensure_tag(conn: &Connection, tag: &str) -> Result<i64, rusqlite::Error> {
+	let parent_id = match tag.rsplit_once('/') {
+		Some((parent, _)) => Some(ensure_tag(conn, parent)?),
+		None => None,
+	};
+
+	conn.execute(
+		"INSERT OR IGNORE INTO file_tags(tag_name) VALUES(?1);",
+		(tag,),
+	)?;
+	conn.execute(
+		"UPDATE file_tags SET parent_id=?2 WHERE tag_name=?1;",
+		(tag, parent_id),
+	)?;
+
+	conn.query_row(
+		"SELECT id FROM file_tags WHERE tag_name=?1",
+		(tag,),
+		|row| { row.get::<usize, i64>(0)}
+	)
+}
+
+// A tag and every tag below it in the hierarchy as (id, name) pairs, parents before children
+fn tag_subtree(conn: &Connection, tag: &str) -> Result<Vec<(i64, String)>, rusqlite::Error> {
+	let mut stmt = conn.prepare(
+		"WITH RECURSIVE subtree(id, tag_name) AS (
+			SELECT id, tag_name FROM file_tags WHERE tag_name=?1
+			UNION
+			SELECT file_tags.id, file_tags.tag_name FROM file_tags INNER JOIN subtree ON file_tags.parent_id=subtree.id
+		)
+		SELECT id, tag_name FROM subtree ORDER BY length(tag_name)")?;
+	let rows = stmt.query_map((tag,), |row| Ok((row.get(0)?, row.get(1)?)))?;
+	rows.collect()
+}
+
+// Split a FileRef's fingerprint into the birth_time, size, mtime and ctime columns
+fn fingerprint_params(file_ref: &FileRef) -> (Option<i64>, Option<u64>, Option<i64>, Option<i64>) {
+	match &file_ref.fingerprint {
+		Some(fp) => (fp.birth_time, Some(fp.size), Some(fp.mtime), fp.ctime),
+		None => (None, None, None, None),
+	}
+}
+
+// A path as stored in the known_path column, the raw bytes of the OS path.
+// Relative paths would point somewhere else from another working directory, so they are refused.
+// An empty path is a record whose path was never known.
+fn path_bytes(path: &Path) -> Result<&[u8], FileChestError> {
+	if path.is_relative() && !path.as_os_str().is_empty() {
+		return Err(FileChestError::InvalidPath(path.to_path_buf()));
+	}
+	Ok(path.as_os_str().as_bytes())
+}
+
+fn path_param(file_ref: &FileRef) -> Result<&[u8], FileChestError> {
+	path_bytes(&file_ref.file_path)
+}
+
+// Read back a known_path. Text is accepted as well, in case the row was written by another tool.
+fn path_from_sql(value: ValueRef) -> Result<PathBuf, FromSqlError> {
+	match value {
+		ValueRef::Null => Ok(PathBuf::new()),
+		ValueRef::Blob(bytes) | ValueRef::Text(bytes) => Ok(PathBuf::from(OsStr::from_bytes(bytes))),
+		_ => Err(FromSqlError::InvalidType),
+	}
+}
+
+// Columns needed to rebuild a FileRef from file_notes, in the order file_ref_from_row expects
+const FILE_REF_COLUMNS: &str = "file_notes.device, file_notes.inode, file_notes.known_path,
+	file_notes.birth_time, file_notes.size, file_notes.mtime, file_notes.ctime";
+
+fn file_ref_from_row(row: &Row) -> Result<FileRef, rusqlite::Error> {
+	Ok(FileRef {
+		file_path: path_from_sql(row.get_ref(2)?)?,
+		device: row.get(0)?,
+		inode: row.get(1)?,
+		fingerprint: match (row.get::<usize, Option<u64>>(4)?, row.get::<usize, Option<i64>>(5)?) {
+			(Some(size), Some(mtime)) => Some(Fingerprint { birth_time: row.get(3)?, size, mtime, ctime: row.get(6)? }),
+			_ => None,
+		},
+	})
+}
+
+// Whether the file described by the metadata is the one a stored record refers to
+fn is_same_file(record: &FileRef, m: &Metadata) -> bool {
+	record.device == m.dev() && record.inode == m.ino() && match &record.fingerprint {
+		Some(fp) => fp.matches(&Fingerprint::from_metadata(m)),
+		None => true,
+	}
+}
+
+// Recursively visit every entry below dir without following symbolic links.
+// Directories which can not be read are skipped. A relative dir is made absolute first,
+// so that the paths visited can be stored.
+fn walk_dir(dir: &Path, visit: &mut dyn FnMut(&Path, &Metadata)) {
+	if let Ok(dir) = std::path::absolute(dir) {
+		walk_below(&dir, visit);
 	}
+}
 
-	pub fn from_direntry(de: &DirEntry) -> Result<Self, std::io::Error> {
-		Ok(Self { file_path: de.path(), inode: de.ino()})
+fn walk_below(dir: &Path, visit: &mut dyn FnMut(&Path, &Metadata)) {
+	let entries = match fs::read_dir(dir) {
+		Ok(entries) => entries,
+		Err(_) => return,
+	};
+
+	for entry in entries.flatten() {
+		let path = entry.path();
+		if let Ok(m) = entry.metadata() {
+			visit(&path, &m);
+			if m.is_dir() {
+				walk_below(&path, visit);
+			}
+		}
 	}
 }
 
+// Result of NotesDB::reconcile
+#[derive(Debug, Default, Clone)]
+pub struct ReconcileReport {
+	// Records whose known_path was updated, with the path they were previously known by
+	pub moved: Vec<(PathBuf, FileRef)>,
+	// Records whose file could not be found at its known path or below any of the roots
+	pub missing: Vec<FileRef>,
+}
+
+// Markers placed around matched words in NoteMatch::snippet
+pub const SNIPPET_OPEN: &str = "[";
+pub const SNIPPET_CLOSE: &str = "]";
+
+// Result of NotesDB::search_notes
+#[derive(Debug, Clone)]
+pub struct NoteMatch {
+	pub file: FileRef,
+	// Excerpt of the note with matched words between SNIPPET_OPEN and SNIPPET_CLOSE
+	pub snippet: String,
+	// FTS5 bm25 rank, lower is a better match
+	pub rank: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagInfo {
+	pub name: String,
+	// Number of files carrying exactly this tag
+	pub file_count: usize,
+}
+
 pub struct NotesDB {
 	conn: Connection,
+	// Mirror tags and notes into extended attributes, see xattrs.rs
+	xattr_sync: bool,
+	// Mirror tags and notes into sidecar manifests, see sidecar.rs
+	sidecar_sync: bool,
+	// Applied to files as they are indexed or appear, see rules.rs
+	rules: RuleSet,
+	// Told about failures which do not stop an operation, see set_warning_handler
+	warning_handler: Option<WarningHandler>,
 }
 
+// Receives a description of each failure which was skipped over
+pub type WarningHandler = Box<dyn Fn(&str) + Send>;
+
 impl NotesDB {
-	pub fn build() -> Result<Self, Box<dyn std::error::Error>> {
-		fs::create_dir_all(FC_DIR)?;
-		let conn = Connection::open(format!("{FC_DIR}/test.db"))?;
-		conn.execute(
-			"CREATE TABLE IF NOT EXISTS file_notes (
-				inode INTEGER PRIMARY KEY,
-				known_path TEXT,
-				note TEXT
-			);",
-			()
-		)?;
-		
-		conn.execute(
-			"CREATE TABLE IF NOT EXISTS file_tags (
-				id INTEGER PRIMARY KEY AUTOINCREMENT,
-				tag_name VARCHAR(255) UNIQUE
-			);",
-			()
-		)?;
+	// Open the database at the default location. The first time, a database left behind by an
+	// older version is copied there, so its notes carry over. The old file is kept as a backup.
+	pub fn build() -> Result<Self, FileChestError> {
+		let path = default_db_path().ok_or_else(|| FileChestError::NotFound("Data directory for the database".to_string()))?;
+		if env_db_path().is_none() && !path.exists() {
+			if let Some(legacy) = legacy_db_paths().into_iter().find(|legacy| legacy.is_file()) {
+				copy_legacy_db(&legacy, &path)?;
+			}
+		}
+		Self::open(path)
+	}
 
-		conn.execute(
-			"CREATE TABLE IF NOT EXISTS tag_relations (
-				--relation_id INTEGER PRIMARY KEY AUTOINCREMENT,
-				tag_id INTEGER NOT NULL,
-				file_id INTEGER NOT NULL,
+	// Open (or create) the database stored at the given path
+	pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, FileChestError> {
+		let path = path.as_ref();
+		if let Some(parent) = path.parent() {
+			if !parent.as_os_str().is_empty() {
+				fs::create_dir_all(parent)?;
+			}
+		}
 
-				FOREIGN KEY(tag_id) REFERENCES file_tags(id),	
-				FOREIGN KEY(file_id) REFERENCES file_notes(inode),
+		Self::from_connection(Connection::open(path)?)
+	}
 
-				CONSTRAINT uc_tfid UNIQUE (tag_id, file_id)
-			);",
-			()
-		)?;
+	// Open a temporary database which only lives as long as the NotesDB
+	pub fn open_in_memory() -> Result<Self, FileChestError> {
+		Self::from_connection(Connection::open_in_memory()?)
+	}
+
+	fn from_connection(mut conn: Connection) -> Result<Self, FileChestError> {
+		migrate(&mut conn)?;
 
 		Ok(NotesDB {
-			conn
+			conn,
+			xattr_sync: false,
+			sidecar_sync: false,
+			rules: RuleSet::default(),
+			warning_handler: None,
 		})
 	}
 
-	pub fn get_note(&self, file_ref: &FileRef) -> Result<String, rusqlite::Error> {
+	// Failures which do not make an operation fail, such as a mirrored tag which could not be
+	// written or a watch event which could not be applied, are passed to handler. Without one
+	// they are ignored.
+	pub fn set_warning_handler(&mut self, handler: impl Fn(&str) + Send + 'static) {
+		self.warning_handler = Some(Box::new(handler));
+	}
+
+	pub(crate) fn warn(&self, message: &str) {
+		if let Some(handler) = &self.warning_handler {
+			handler(message);
+		}
+	}
+
+	// Run f so that either all or none of its changes are kept. This uses a savepoint rather than
+	// a transaction so calls can nest, e.g. set_tags inside a batch.
+	fn atomic<T>(&self, f: impl FnOnce() -> Result<T, FileChestError>) -> Result<T, FileChestError> {
+		self.conn.execute_batch("SAVEPOINT filechest_atomic;")?;
+		match f() {
+			Ok(value) => {
+				self.conn.execute_batch("RELEASE filechest_atomic;")?;
+				Ok(value)
+			},
+			Err(e) => {
+				self.conn.execute_batch("ROLLBACK TO filechest_atomic; RELEASE filechest_atomic;")?;
+				Err(e)
+			},
+		}
+	}
+
+	// Group many updates into a single commit. If the closure returns an error nothing it did is kept.
+	//     db.batch(|db| {
+	//         for file in &files {
+	//             db.add_tag(file, "invoice")?;
+	//         }
+	//         Ok(())
+	//     })?;
+	pub fn batch<T>(&self, f: impl FnOnce(&NotesDB) -> Result<T, FileChestError>) -> Result<T, FileChestError> {
+		self.atomic(|| f(self))
+	}
+
+	// The file the database is stored in, None for an in-memory database
+	pub fn path(&self) -> Option<&Path> {
+		self.conn.path().filter(|path| *path != Path::new(":memory:"))
+	}
+
+	// Version of the schema stored in the open database
+	pub fn schema_version(&self) -> Result<u32, FileChestError> {
+		Ok(self.conn.query_row("PRAGMA user_version;", (), |row| row.get(0))?)
+	}
+
+	// Check whether the stored record for a file still belongs to it
+	pub fn record_status(&self, file_ref: &FileRef) -> Result<RecordStatus, FileChestError> {
+		self.adopt_device(file_ref)?;
+
+		let stored = self.conn.query_row(
+			"SELECT birth_time, size, mtime, ctime FROM file_notes WHERE device=?1 AND inode=?2;",
+			(file_ref.device, file_ref.inode),
+			|row| {
+				Ok((
+					row.get::<usize, Option<i64>>(0)?,
+					row.get::<usize, Option<u64>>(1)?,
+					row.get::<usize, Option<i64>>(2)?,
+					row.get::<usize, Option<i64>>(3)?,
+				))
+			}
+		).optional()?;
+
+		let status = match (stored, &file_ref.fingerprint) {
+			(None, _) => RecordStatus::Missing,
+			(Some((birth_time, Some(size), Some(mtime), ctime)), Some(current)) => {
+				if (Fingerprint { birth_time, size, mtime, ctime }).matches(current) {
+					RecordStatus::Current
+				} else {
+					RecordStatus::Stale
+				}
+			},
+			// Nothing to compare against
+			(Some(_), _) => RecordStatus::Current,
+		};
+
+		Ok(status)
+	}
+
+	// Rows migrated from schema version 1 whose file could not be found have device 0. Give such a
+	// row the file's device once the file is back at the row's known path with the same inode.
+	fn adopt_device(&self, file_ref: &FileRef) -> Result<(), FileChestError> {
+		if file_ref.device == 0 {
+			return Ok(());
+		}
+		self.conn.execute(
+			"UPDATE file_notes SET device = ?1 WHERE device = 0 AND inode = ?2 AND known_path = ?3
+			AND NOT EXISTS (SELECT 1 FROM file_notes WHERE device = ?1 AND inode = ?2);",
+			(file_ref.device, file_ref.inode, path_param(file_ref)?)
+		)?;
+		Ok(())
+	}
+
+	// adopt_device for every row with device 0 whose file is at its known path
+	pub(crate) fn adopt_devices(&self) -> Result<(), FileChestError> {
+		let unknown = {
+			let mut stmt = self.conn.prepare(&format!("SELECT {FILE_REF_COLUMNS} FROM file_notes WHERE device = 0"))?;
+			let rows = stmt.query_map((), file_ref_from_row)?;
+			rows.collect::<Result<Vec<_>, _>>()?
+		};
+
+		for record in unknown {
+			if let Ok(m) = fs::symlink_metadata(&record.file_path) {
+				if m.ino() == record.inode {
+					self.adopt_device(&FileRef { device: m.dev(), ..record })?;
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn check_not_stale(&self, file_ref: &FileRef) -> Result<(), FileChestError> {
+		if self.record_status(file_ref)? == RecordStatus::Stale {
+			return Err(FileChestError::StaleRecord(file_ref.file_path.clone()));
+		}
+		Ok(())
+	}
+
+	pub fn get_note(&self, file_ref: &FileRef) -> Result<String, FileChestError> {
+		self.check_not_stale(file_ref)?;
+
+		let (birth_time, size, mtime, ctime) = fingerprint_params(file_ref);
 		self.conn.execute(
-			"UPDATE file_notes SET known_path = ?1 WHERE inode = ?2;",
-			(file_ref.file_path.to_str().unwrap(), &file_ref.inode)
+			"UPDATE file_notes SET known_path = ?1, missing_since = NULL, birth_time = COALESCE(?4, birth_time),
+				size = COALESCE(?5, size), mtime = COALESCE(?6, mtime), ctime = COALESCE(?7, ctime)
+			WHERE device = ?2 AND inode = ?3;",
+			(path_param(file_ref)?, &file_ref.device, &file_ref.inode, birth_time, size, mtime, ctime)
 		)?;
+		self.refresh_metadata_if_present(file_ref)?;
 
 		self.get_note_no_update(file_ref)
 	}
 	
-	pub fn get_note_no_update(&self, file_ref: &FileRef) -> Result<String, rusqlite::Error> {
+	pub fn get_note_no_update(&self, file_ref: &FileRef) -> Result<String, FileChestError> {
+		self.check_not_stale(file_ref)?;
+
 		self.conn.query_row(
-			"SELECT note FROM file_notes WHERE inode=?1;",
-			(file_ref.inode,),
-			|row| { row.get::<usize, String>(0)}
-		)
+			"SELECT note FROM file_notes WHERE device=?1 AND inode=?2;",
+			(file_ref.device, file_ref.inode),
+			|row| { row.get::<usize, Option<String>>(0)}
+		).optional()?
+			.flatten()
+			.ok_or_else(|| FileChestError::NotFound(format!("Note for {}", file_ref.file_path.display())))
 	}
 
-	pub fn set_note(&self, file_ref: &FileRef, note: &str) -> Result<(), rusqlite::Error> {
+	pub fn set_note(&self, file_ref: &FileRef, note: &str) -> Result<(), FileChestError> {
+		self.check_not_stale(file_ref)?;
+
+		let (birth_time, size, mtime, ctime) = fingerprint_params(file_ref);
 		self.conn.execute(
-        	"INSERT OR REPLACE INTO file_notes(inode, known_path, note) VALUES(?1, ?2, ?3);",
-        	(file_ref.inode, file_ref.file_path.to_str().unwrap(), note, ),
+        	"INSERT INTO file_notes(device, inode, known_path, note, birth_time, size, mtime, ctime) VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+			ON CONFLICT(device, inode) DO UPDATE SET known_path=excluded.known_path, note=excluded.note, missing_since=NULL,
+				birth_time=COALESCE(excluded.birth_time, birth_time), size=COALESCE(excluded.size, size),
+				mtime=COALESCE(excluded.mtime, mtime), ctime=COALESCE(excluded.ctime, ctime);",
+        	(file_ref.device, file_ref.inode, path_param(file_ref)?, note, birth_time, size, mtime, ctime),
 		)?;
+
+		self.sync_xattr(file_ref, xattrs::COMMENT_XATTR, note);
+		self.sync_sidecar_entry(file_ref);
 		Ok(())
 	}
 
-	pub fn add_file(&self, file_ref: &FileRef) -> Result<(), rusqlite::Error> {
+	pub fn add_file(&self, file_ref: &FileRef) -> Result<(), FileChestError> {
+		self.check_not_stale(file_ref)?;
+
+		let (birth_time, size, mtime, ctime) = fingerprint_params(file_ref);
 		self.conn.execute(
-			"INSERT OR IGNORE INTO file_notes(inode, known_path) VALUES(?1, ?2);",
-			(file_ref.inode, file_ref.file_path.to_str().unwrap(), )
+			"INSERT OR IGNORE INTO file_notes(device, inode, known_path, birth_time, size, mtime, ctime) VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7);",
+			(file_ref.device, file_ref.inode, path_param(file_ref)?, birth_time, size, mtime, ctime)
 		)?;
 		Ok(())
 	}
 
-	pub fn add_tag(&self, file_ref: &FileRef, tag: &str) -> Result<(), rusqlite::Error> {
-		//Check if we have a corresponding tag in the tags table. Add the new tag if we don't.
+	// Accept the file as it is now as the owner of its record, e.g. to let a new file which
+	// reused the inode keep the stale record's notes.
+	pub fn refresh_fingerprint(&self, file_ref: &FileRef) -> Result<(), FileChestError> {
+		let (birth_time, size, mtime, ctime) = fingerprint_params(file_ref);
 		self.conn.execute(
-			"INSERT OR IGNORE INTO file_tags(tag_name) VALUES(?1);",
-			(tag,),
-		)?;
-		
-		let tag_id = self.conn.query_row(
-			"SELECT id FROM file_tags WHERE tag_name=?1",
-			(tag,),
-			|row| { row.get::<usize, usize>(0)}
+			"UPDATE file_notes SET known_path = ?1, birth_time = ?4, size = ?5, mtime = ?6, ctime = ?7, missing_since = NULL
+			WHERE device = ?2 AND inode = ?3;",
+			(path_param(file_ref)?, &file_ref.device, &file_ref.inode, birth_time, size, mtime, ctime)
 		)?;
+		Ok(())
+	}
 
-		self.add_file(file_ref)?;
+	// Remove the record stored under a file's device and inode, along with its tags, attributes and note history
+	pub fn forget_record(&self, file_ref: &FileRef) -> Result<(), FileChestError> {
+		self.atomic(|| {
+			for table in ["tag_relations", "file_attributes", "note_revisions"] {
+				self.conn.execute(
+					&format!("DELETE FROM {table} WHERE file_id IN
+					(SELECT id FROM file_notes WHERE device=?1 AND inode=?2)"),
+					(file_ref.device, file_ref.inode)
+				)?;
+			}
+			self.conn.execute(
+				"DELETE FROM file_notes WHERE device=?1 AND inode=?2",
+				(file_ref.device, file_ref.inode)
+			)?;
+			Ok(())
+		})
+	}
 
-		self.conn.execute(
-			"INSERT OR IGNORE INTO tag_relations(tag_id, file_id) VALUES(?1, ?2);",
-			(tag_id, file_ref.inode),
-		)?;
+	pub fn add_tag(&self, file_ref: &FileRef, tag: &str) -> Result<(), FileChestError> {
+		let tag = normalize_tag(tag);
+		if tag.is_empty() {
+			return Ok(());
+		}
+
+		self.insert_tag(file_ref, &tag)?;
+		self.sync_tags(file_ref)
+	}
+
+	// add_tag without updating extended attributes or sidecars, for a normalized tag
+	fn insert_tag(&self, file_ref: &FileRef, tag: &str) -> Result<(), FileChestError> {
+		self.atomic(|| {
+			//Check if we have a corresponding tag in the tags table. Add the new tag (and its parents) if we don't.
+			let tag_id = ensure_tag(&self.conn, tag)?;
+
+			self.add_file(file_ref)?;
+
+			self.conn.execute(
+				"INSERT OR IGNORE INTO tag_relations(tag_id, file_id)
+				SELECT ?1, id FROM file_notes WHERE device=?2 AND inode=?3;",
+				(tag_id, file_ref.device, file_ref.inode),
+			)?;
+			Ok(())
+		})
+	}
+
+	pub fn set_tags(&self, file_ref: &FileRef, tags: Vec<&str>) -> Result<(), FileChestError> {
+		self.check_not_stale(file_ref)?;
+
+		let tags: Vec<String> = tags.iter().map(|tag| normalize_tag(tag)).filter(|tag| !tag.is_empty()).collect();
+		self.atomic(|| {
+			// Only touch the relations which change, so setting the same tags again is not recorded as a change
+			for old in self.get_tags(file_ref)? {
+				if !tags.contains(&old) {
+					self.conn.execute(
+						"DELETE FROM tag_relations WHERE tag_id IN (SELECT id FROM file_tags WHERE tag_name=?3)
+						AND file_id IN (SELECT id FROM file_notes WHERE device=?1 AND inode=?2)",
+						(file_ref.device, file_ref.inode, &old)
+					)?;
+				}
+			}
+
+			for tag in &tags {
+				self.insert_tag(file_ref, tag)?;
+			};
+			Ok(())
+		})?;
+
+		self.sync_tags(file_ref)
+	}
+
+	// Mirror the tags of a file to its extended attributes and sidecar, when either is enabled
+	pub(crate) fn sync_tags(&self, file_ref: &FileRef) -> Result<(), FileChestError> {
+		if !self.xattr_sync && !self.sidecar_sync {
+			return Ok(());
+		}
+
+		let mut tags = self.get_tags(file_ref)?;
+		tags.sort();
+		self.sync_xattr(file_ref, xattrs::TAGS_XATTR, &tags.join(","));
+		self.sync_sidecar_entry(file_ref);
 		Ok(())
 	}
 
-	pub fn set_tags(&self, file_ref: &FileRef, tags: Vec<&str>) -> Result<(), rusqlite::Error> {
-		self.conn.execute(
-			"DELETE FROM tag_relations WHERE file_id=?1",
-			(file_ref.inode,)
-		)?;
-		
-		for tag in tags {
-			self.add_tag(file_ref, tag)?;
-		};
+	// The files whose mirrored tags change along with a tag and the tags below it.
+	// Empty when nothing is mirrored.
+	fn files_to_resync(&self, tag: &str) -> Result<Vec<FileRef>, FileChestError> {
+		if !self.xattr_sync && !self.sidecar_sync {
+			return Ok(Vec::new());
+		}
+		self.get_files_by_tag(tag)
+	}
+
+	// Skips files which are no longer at their known path
+	fn resync_tags(&self, files: &[FileRef]) -> Result<(), FileChestError> {
+		for file in files.iter().filter(|file| gc::is_present(file)) {
+			self.sync_tags(file)?;
+		}
 		Ok(())
 	}
 
-	pub fn get_tags(&self, file_ref: &FileRef) -> Result<Vec<String>, rusqlite::Error> {
+	pub fn get_tags(&self, file_ref: &FileRef) -> Result<Vec<String>, FileChestError> {
+		self.check_not_stale(file_ref)?;
+
 		let mut stmt = self.conn.prepare(
 			"SELECT file_tags.tag_name FROM file_tags
 			INNER JOIN tag_relations ON tag_relations.tag_id=file_tags.id
-			WHERE tag_relations.file_id=?1")?;
-		let tag_iter = stmt.query_map((file_ref.inode,), |row| {
+			INNER JOIN file_notes ON tag_relations.file_id=file_notes.id
+			WHERE file_notes.device=?1 AND file_notes.inode=?2")?;
+		let tag_iter = stmt.query_map((file_ref.device, file_ref.inode), |row| {
 				row.get::<usize, String>(0)
 			})?;
 		
-		Ok(tag_iter.map(|t| t.unwrap()).collect())
+		Ok(tag_iter.collect::<Result<_, _>>()?)
 	}
 
-	pub fn get_files_by_tag(&self, tag: &str) -> Result<Vec<FileRef>, rusqlite::Error> {
+	// The tags of a file together with all of their ancestors, e.g. a file tagged
+	// "project/alpha/design" also reports "project/alpha" and "project"
+	pub fn get_tags_with_ancestors(&self, file_ref: &FileRef) -> Result<Vec<String>, FileChestError> {
+		self.check_not_stale(file_ref)?;
+
 		let mut stmt = self.conn.prepare(
-			"SELECT file_notes.inode, file_notes.known_path FROM file_notes
-			INNER JOIN tag_relations ON tag_relations.file_id=file_notes.inode
-			INNER JOIN file_tags ON tag_relations.tag_id=file_tags.id
-			WHERE file_tags.tag_name=?1")?;
-		let file_iter = stmt.query_map((tag,), |row| {
-			Ok(FileRef {
-				file_path: PathBuf::from(row.get::<usize, String>(1).unwrap()),
-				inode: row.get(0).unwrap(),
+			"WITH RECURSIVE ancestors(id, tag_name, parent_id) AS (
+				SELECT file_tags.id, file_tags.tag_name, file_tags.parent_id FROM file_tags
+				INNER JOIN tag_relations ON tag_relations.tag_id=file_tags.id
+				INNER JOIN file_notes ON tag_relations.file_id=file_notes.id
+				WHERE file_notes.device=?1 AND file_notes.inode=?2
+				UNION
+				SELECT file_tags.id, file_tags.tag_name, file_tags.parent_id FROM file_tags
+				INNER JOIN ancestors ON file_tags.id=ancestors.parent_id
+			)
+			SELECT tag_name FROM ancestors ORDER BY tag_name")?;
+		let tag_iter = stmt.query_map((file_ref.device, file_ref.inode), |row| {
+				row.get::<usize, String>(0)
+			})?;
+
+		Ok(tag_iter.collect::<Result<_, _>>()?)
+	}
+
+	// Files tagged with the tag or any of its descendants
+	pub fn get_files_by_tag(&self, tag: &str) -> Result<Vec<FileRef>, FileChestError> {
+		self.query_files(&Query::Tag(normalize_tag(tag)))
+	}
+
+	// Full text search over all notes, best matches first
+	pub fn search_notes(&self, text: &str) -> Result<Vec<NoteMatch>, FileChestError> {
+		let query = fts_query(text);
+		if query.is_empty() {
+			return Ok(Vec::new());
+		}
+
+		let mut stmt = self.conn.prepare(&format!(
+			"SELECT {FILE_REF_COLUMNS}, snippet(notes_fts, 0, ?2, ?3, '...', 12), notes_fts.rank FROM notes_fts
+			INNER JOIN file_notes ON file_notes.id=notes_fts.rowid
+			WHERE notes_fts MATCH ?1
+			ORDER BY notes_fts.rank"))?;
+		let match_iter = stmt.query_map((query, SNIPPET_OPEN, SNIPPET_CLOSE), |row| {
+			Ok(NoteMatch {
+				file: file_ref_from_row(row)?,
+				snippet: row.get(7)?,
+				rank: row.get(8)?,
 			})
 		})?;
 
-		Ok(file_iter.map(|t| t.unwrap()).collect())
+		Ok(match_iter.collect::<Result<_, _>>()?)
+	}
+
+	// Every tag in the database, sorted by name
+	pub fn list_tags(&self) -> Result<Vec<TagInfo>, FileChestError> {
+		let mut stmt = self.conn.prepare(
+			"SELECT file_tags.tag_name, COUNT(tag_relations.file_id) FROM file_tags
+			LEFT JOIN tag_relations ON tag_relations.tag_id=file_tags.id
+			GROUP BY file_tags.id
+			ORDER BY file_tags.tag_name")?;
+		let tag_iter = stmt.query_map((), |row| {
+			Ok(TagInfo { name: row.get(0)?, file_count: row.get(1)? })
+		})?;
+
+		Ok(tag_iter.collect::<Result<_, _>>()?)
+	}
+
+	// Rename a tag along with everything below it, e.g. renaming "proj" to "project" turns
+	// "proj/alpha" into "project/alpha". Fails if any of the new names are already taken by
+	// other tags; renaming a tag to its own name does nothing.
+	pub fn rename_tag(&self, old: &str, new: &str) -> Result<(), FileChestError> {
+		let (old, new) = (normalize_tag(old), normalize_tag(new));
+		if !new.is_empty() && new == old {
+			return Ok(());
+		}
+		if new.is_empty() || new.starts_with(&format!("{old}/")) {
+			return Err(FileChestError::InvalidInput(format!("Can not rename tag {old} to {new}")));
+		}
+
+		let affected = self.files_to_resync(&old)?;
+		self.atomic(|| {
+			let subtree = tag_subtree(&self.conn, &old)?;
+			if subtree.is_empty() {
+				return Err(FileChestError::NotFound(format!("Tag {old}")));
+			}
+
+			for (id, name) in &subtree {
+				let renamed = format!("{new}{}", &name[old.len()..]);
+				let taken = self.conn.query_row(
+					"SELECT COUNT(*) FROM file_tags WHERE tag_name=?1",
+					(&renamed,),
+					|row| row.get::<usize, i64>(0)
+				)? > 0;
+				if taken {
+					return Err(FileChestError::InvalidInput(format!("A tag named {renamed} already exists, merge the tags instead")));
+				}
+
+				self.conn.execute("UPDATE file_tags SET tag_name=?1 WHERE id=?2", (&renamed, id))?;
+			}
+
+			// Children keep their parent ids, only the top of the subtree may have moved
+			let parent_id = match new.rsplit_once('/') {
+				Some((parent, _)) => Some(ensure_tag(&self.conn, parent)?),
+				None => None,
+			};
+			self.conn.execute("UPDATE file_tags SET parent_id=?1 WHERE id=?2", (parent_id, subtree[0].0))?;
+
+			Ok(())
+		})?;
+		self.resync_tags(&affected)
+	}
+
+	// Move every file tagged with `from` (or a tag below it) onto `into`, then remove `from`.
+	// "from/x" is merged into "into/x".
+	pub fn merge_tags(&self, from: &str, into: &str) -> Result<(), FileChestError> {
+		let (from, into) = (normalize_tag(from), normalize_tag(into));
+		if into.is_empty() || into == from || into.starts_with(&format!("{from}/")) {
+			return Err(FileChestError::InvalidInput(format!("Can not merge tag {from} into {into}")));
+		}
+
+		let affected = self.files_to_resync(&from)?;
+		self.atomic(|| {
+			let subtree = tag_subtree(&self.conn, &from)?;
+			if subtree.is_empty() {
+				return Err(FileChestError::NotFound(format!("Tag {from}")));
+			}
+
+			for (id, name) in &subtree {
+				let target_id = ensure_tag(&self.conn, &format!("{into}{}", &name[from.len()..]))?;
+				self.conn.execute(
+					"INSERT OR IGNORE INTO tag_relations(tag_id, file_id)
+					SELECT ?1, file_id FROM tag_relations WHERE tag_id=?2",
+					(target_id, id)
+				)?;
+				self.conn.execute("DELETE FROM tag_relations WHERE tag_id=?1", (id,))?;
+			}
+			for (id, _) in subtree.iter().rev() {
+				self.conn.execute("DELETE FROM file_tags WHERE id=?1", (id,))?;
+			}
+
+			Ok(())
+		})?;
+		self.resync_tags(&affected)
+	}
+
+	// Remove a tag and every tag below it from all files
+	pub fn delete_tag(&self, tag: &str) -> Result<(), FileChestError> {
+		let tag = normalize_tag(tag);
+
+		let affected = self.files_to_resync(&tag)?;
+		self.atomic(|| {
+			let subtree = tag_subtree(&self.conn, &tag)?;
+			if subtree.is_empty() {
+				return Err(FileChestError::NotFound(format!("Tag {tag}")));
+			}
+
+			for (id, _) in subtree.iter().rev() {
+				self.conn.execute("DELETE FROM tag_relations WHERE tag_id=?1", (id,))?;
+				self.conn.execute("DELETE FROM file_tags WHERE id=?1", (id,))?;
+			}
+
+			Ok(())
+		})?;
+		self.resync_tags(&affected)
+	}
+
+	// Find the files matching a search query such as "tag:invoice AND NOT tag:paid"
+	pub fn query(&self, text: &str) -> Result<Vec<FileRef>, FileChestError> {
+		let query = query::parse(text)?;
+		self.query_files(&query)
+	}
+
+	pub fn query_files(&self, query: &Query) -> Result<Vec<FileRef>, FileChestError> {
+		self.query_files_sorted(query, SortOrder::default())
+	}
+
+	// Every file record in the database
+	pub fn get_all_files(&self) -> Result<Vec<FileRef>, FileChestError> {
+		let mut stmt = self.conn.prepare(&format!("SELECT {FILE_REF_COLUMNS} FROM file_notes"))?;
+		let file_iter = stmt.query_map((), file_ref_from_row)?;
+
+		Ok(file_iter.collect::<Result<_, _>>()?)
+	}
+
+	// Walk the given roots looking for the files of all known records, updating known_path for
+	// files which have moved. Records whose file is not found anywhere are reported as missing.
+	// Relative roots are found from the working directory, and moved records get absolute paths.
+	pub fn reconcile<P: AsRef<Path>>(&self, roots: &[P]) -> Result<ReconcileReport, FileChestError> {
+		self.adopt_devices()?;
+		let mut unseen: HashMap<(u64, u64), FileRef> = self.get_all_files()?
+			.into_iter()
+			.map(|f| ((f.device, f.inode), f))
+			.collect();
+
+		// Records which are still where we left them don't need a walk to be found
+		unseen.retain(|_, record| {
+			match fs::symlink_metadata(&record.file_path) {
+				Ok(m) => !is_same_file(record, &m),
+				Err(_) => true,
+			}
+		});
+
+		let mut report = ReconcileReport::default();
+		for root in roots {
+			if unseen.is_empty() {
+				break;
+			}
+
+			walk_dir(root.as_ref(), &mut |path, m| {
+				let key = (m.dev(), m.ino());
+				if let Entry::Occupied(entry) = unseen.entry(key) {
+					if is_same_file(entry.get(), m) {
+						let old_path = entry.remove().file_path;
+						report.moved.push((old_path, FileRef {
+							file_path: path.to_path_buf(),
+							device: key.0,
+							inode: key.1,
+							fingerprint: Some(Fingerprint::from_metadata(m)),
+						}));
+					}
+				}
+			});
+		}
+
+		self.atomic(|| {
+			for (_, file_ref) in &report.moved {
+				self.refresh_fingerprint(file_ref)?;
+			}
+			Ok(())
+		})?;
+
+		report.missing = unseen.into_values().collect();
+		report.missing.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+
+		Ok(report)
 	}
 }
\ No newline at end of file
@@ -0,0 +1,225 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+/* Cached file metadata
+ *
+ * Records keep the size, mtime, ctime, type, permission bits and MIME type their file had when
+ * it was last indexed or looked up, so searches can filter and sort on them without a stat of
+ * every file. Size and mtime share the fingerprint columns. The MIME type is detected from the
+ * file's contents and name, see mime_type.rs.
+ */
+
+use crate::index::FileType;
+use crate::mime_type;
+use crate::query::Query;
+use crate::{file_ref_from_row, FileChestError, FileRef, Fingerprint, NotesDB, FILE_REF_COLUMNS};
+
+use std::cmp::Ordering;
+use std::fs::Metadata;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use rusqlite::OptionalExtension;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMetadata {
+	pub size: u64,
+	// Nanoseconds since the epoch
+	pub mtime: i64,
+	pub ctime: i64,
+	pub file_type: FileType,
+	// Permission bits of the mode, e.g. 0o644
+	pub permissions: u32,
+	pub mime_type: Option<String>,
+}
+
+impl FileMetadata {
+	// m must come from symlink_metadata, so links are described rather than their targets.
+	// Regular files are opened to detect their MIME type.
+	pub fn from_metadata(path: &Path, m: &Metadata) -> Self {
+		Self::with_mime_type(m, mime_type::detect(path, FileType::from_metadata(m)))
+	}
+
+	// Like from_metadata, without opening the file: the MIME type is guessed from the name
+	pub fn guess(path: &Path, m: &Metadata) -> Self {
+		Self::with_mime_type(m, mime_type::guess(path, FileType::from_metadata(m)))
+	}
+
+	fn with_mime_type(m: &Metadata, mime_type: Option<String>) -> Self {
+		Self {
+			size: m.size(),
+			mtime: m.mtime() * 1_000_000_000 + m.mtime_nsec(),
+			ctime: m.ctime() * 1_000_000_000 + m.ctime_nsec(),
+			file_type: FileType::from_metadata(m),
+			permissions: m.mode() & 0o7777,
+			mime_type,
+		}
+	}
+}
+
+// Column search results can be sorted by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortKey {
+	#[default]
+	Path,
+	Size,
+	Modified,
+	Changed,
+	// File type, then MIME type
+	Type,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SortOrder {
+	pub key: SortKey,
+	pub descending: bool,
+}
+
+impl SortOrder {
+	// ORDER BY clause over file_notes. Records without the metadata come last either way,
+	// and ties are broken by path.
+	fn sql(&self) -> String {
+		let direction = if self.descending { "DESC" } else { "ASC" };
+		match self.key {
+			SortKey::Path => format!("file_notes.known_path {direction}"),
+			SortKey::Size => format!("file_notes.size {direction} NULLS LAST, file_notes.known_path"),
+			SortKey::Modified => format!("file_notes.mtime {direction} NULLS LAST, file_notes.known_path"),
+			SortKey::Changed => format!("file_notes.ctime {direction} NULLS LAST, file_notes.known_path"),
+			SortKey::Type => format!(
+				"file_notes.file_type {direction} NULLS LAST, file_notes.mime_type {direction} NULLS LAST, file_notes.known_path"
+			),
+		}
+	}
+
+	// Sort files which may not have records the way query_files_sorted would
+	pub fn sort(&self, files: &mut [(FileRef, Option<FileMetadata>)]) {
+		files.sort_by(|(a, a_meta), (b, b_meta)| {
+			let by_key = match (a_meta, b_meta) {
+				(Some(a_meta), Some(b_meta)) => {
+					let ordering = match self.key {
+						SortKey::Path => Ordering::Equal,
+						SortKey::Size => a_meta.size.cmp(&b_meta.size),
+						SortKey::Modified => a_meta.mtime.cmp(&b_meta.mtime),
+						SortKey::Changed => a_meta.ctime.cmp(&b_meta.ctime),
+						SortKey::Type => (a_meta.file_type.as_str(), &a_meta.mime_type)
+							.cmp(&(b_meta.file_type.as_str(), &b_meta.mime_type)),
+					};
+					if self.descending { ordering.reverse() } else { ordering }
+				},
+				(Some(_), None) => Ordering::Less,
+				(None, Some(_)) => Ordering::Greater,
+				(None, None) => Ordering::Equal,
+			};
+
+			let by_path = a.file_path.cmp(&b.file_path);
+			by_key.then(if self.key == SortKey::Path && self.descending { by_path.reverse() } else { by_path })
+		});
+	}
+}
+
+impl NotesDB {
+	// Look at the file again and store what is found in its record, if it has one.
+	// Fails with NotFound if something else is at the file's path now, and with StaleRecord
+	// if the record belongs to an earlier file with the same inode, which keeps its metadata.
+	pub fn refresh_metadata(&self, file_ref: &FileRef) -> Result<FileMetadata, FileChestError> {
+		let m = std::fs::symlink_metadata(&file_ref.file_path)?;
+		if m.dev() != file_ref.device || m.ino() != file_ref.inode {
+			return Err(FileChestError::NotFound(format!("{} at its known path", file_ref.file_path.display())));
+		}
+		// Size and mtime share the fingerprint columns, so overwriting them would make a stale
+		// record look current
+		self.check_not_stale(&FileRef { fingerprint: Some(Fingerprint::from_metadata(&m)), ..file_ref.clone() })?;
+
+		let metadata = FileMetadata::from_metadata(&file_ref.file_path, &m);
+		self.store_metadata(file_ref, &metadata)?;
+		Ok(metadata)
+	}
+
+	// Refresh the metadata of a file being looked at, which need not exist on this machine
+	pub(crate) fn refresh_metadata_if_present(&self, file_ref: &FileRef) -> Result<(), FileChestError> {
+		match self.refresh_metadata(file_ref) {
+			Ok(_) | Err(FileChestError::Io(_)) | Err(FileChestError::NotFound(_)) => Ok(()),
+			Err(e) => Err(e),
+		}
+	}
+
+	pub(crate) fn store_metadata(&self, file_ref: &FileRef, metadata: &FileMetadata) -> Result<(), FileChestError> {
+		self.conn.execute(
+			"UPDATE file_notes SET size = ?3, mtime = ?4, ctime = ?5, file_type = ?6, permissions = ?7, mime_type = ?8
+			WHERE device = ?1 AND inode = ?2;",
+			(
+				file_ref.device, file_ref.inode, metadata.size, metadata.mtime, metadata.ctime,
+				metadata.file_type.as_str(), metadata.permissions, &metadata.mime_type,
+			)
+		)?;
+		Ok(())
+	}
+
+	// Describe a file from a fresh stat without storing anything or opening the file. The stored
+	// MIME type is reused while the file keeps its size and mtime, otherwise it is guessed from
+	// the name.
+	pub fn current_metadata(&self, file_ref: &FileRef, m: &Metadata) -> Result<FileMetadata, FileChestError> {
+		let fresh = FileMetadata::with_mime_type(m, None);
+		match self.get_metadata(file_ref)? {
+			Some(stored) if (stored.size, stored.mtime, stored.file_type) == (fresh.size, fresh.mtime, fresh.file_type) => {
+				Ok(FileMetadata { mime_type: stored.mime_type, ..fresh })
+			},
+			_ => Ok(FileMetadata::guess(&file_ref.file_path, m)),
+		}
+	}
+
+	// The metadata stored when the file was last indexed or looked up.
+	// None for files without a record, or whose record was never filled in.
+	pub fn get_metadata(&self, file_ref: &FileRef) -> Result<Option<FileMetadata>, FileChestError> {
+		let stored = self.conn.query_row(
+			"SELECT size, mtime, ctime, file_type, permissions, mime_type FROM file_notes WHERE device=?1 AND inode=?2;",
+			(file_ref.device, file_ref.inode),
+			|row| Ok((
+				row.get::<usize, Option<u64>>(0)?,
+				row.get::<usize, Option<i64>>(1)?,
+				row.get::<usize, Option<i64>>(2)?,
+				row.get::<usize, Option<String>>(3)?,
+				row.get::<usize, Option<u32>>(4)?,
+				row.get::<usize, Option<String>>(5)?,
+			))
+		).optional()?;
+
+		Ok(match stored {
+			Some((Some(size), Some(mtime), Some(ctime), Some(file_type), Some(permissions), mime_type)) => {
+				FileType::parse(&file_type).map(|file_type| FileMetadata { size, mtime, ctime, file_type, permissions, mime_type })
+			},
+			_ => None,
+		})
+	}
+
+	// Like query, with the results in the given order instead of by path
+	pub fn query_sorted(&self, text: &str, order: SortOrder) -> Result<Vec<FileRef>, FileChestError> {
+		let query = crate::query::parse(text)?;
+		self.query_files_sorted(&query, order)
+	}
+
+	pub fn query_files_sorted(&self, query: &Query, order: SortOrder) -> Result<Vec<FileRef>, FileChestError> {
+		let mut params = Vec::new();
+		let condition = query.to_sql(&mut params);
+
+		let mut stmt = self.conn.prepare(&format!(
+			"SELECT {FILE_REF_COLUMNS} FROM file_notes WHERE {condition} ORDER BY {}", order.sql()))?;
+		let file_iter = stmt.query_map(rusqlite::params_from_iter(params), file_ref_from_row)?;
+
+		Ok(file_iter.collect::<Result<_, _>>()?)
+	}
+}
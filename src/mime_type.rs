@@ -0,0 +1,105 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+/* MIME type detection
+ *
+ * The start of a file is checked for the magic bytes of known formats first, then the name's
+ * extension is looked up. Formats which are containers for others, such as zip, give way to the
+ * extension, so a .jar is not reported as a plain zip file. Files matching neither are text/plain
+ * if they look like UTF-8 and application/octet-stream otherwise.
+ *
+ * Directories and links get the inode/ types used by shared-mime-info.
+ *
+ * guess only looks at the name, for listings which can not afford to open every file.
+ */
+
+use crate::index::FileType;
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+// Bytes read from the start of a file to look for magic numbers
+const HEADER_SIZE: u64 = 8192;
+
+// Types for which a known extension is more specific than the magic bytes
+const CONTAINER_TYPES: &[&str] = &["application/zip", "application/octet-stream"];
+
+pub const DIRECTORY: &str = "inode/directory";
+pub const SYMLINK: &str = "inode/symlink";
+pub const EMPTY: &str = "application/x-zerosize";
+pub const TEXT: &str = "text/plain";
+pub const BINARY: &str = "application/octet-stream";
+
+// The MIME type of the entry at path, None for devices, sockets and pipes
+pub fn detect(path: &Path, file_type: FileType) -> Option<String> {
+	match file_type {
+		FileType::Dir => Some(DIRECTORY.to_string()),
+		FileType::Symlink => Some(SYMLINK.to_string()),
+		FileType::File => Some(detect_file(path)),
+		FileType::Other => None,
+	}
+}
+
+// The MIME type of the entry at path going by its name alone. None for files with an
+// unknown extension, as well as for devices, sockets and pipes.
+pub fn guess(path: &Path, file_type: FileType) -> Option<String> {
+	match file_type {
+		FileType::File => mime_guess::from_path(path).first_raw().map(str::to_string),
+		_ => detect(path, file_type),
+	}
+}
+
+fn detect_file(path: &Path) -> String {
+	let by_name = mime_guess::from_path(path).first_raw();
+	// Unreadable files can still be typed by name
+	let header = match read_header(path) {
+		Ok(header) => header,
+		Err(_) => return by_name.unwrap_or(BINARY).to_string(),
+	};
+
+	match (detect_bytes(&header), by_name) {
+		(Some(magic), Some(name)) if CONTAINER_TYPES.contains(&magic) => name.to_string(),
+		(Some(magic), _) => magic.to_string(),
+		(None, Some(name)) => name.to_string(),
+		(None, None) if header.is_empty() => EMPTY.to_string(),
+		(None, None) if looks_like_text(&header) => TEXT.to_string(),
+		(None, None) => BINARY.to_string(),
+	}
+}
+
+// The type given by the magic bytes at the start of a file, if they are known
+pub fn detect_bytes(header: &[u8]) -> Option<&'static str> {
+	infer::get(header).map(|kind| kind.mime_type())
+}
+
+fn read_header(path: &Path) -> std::io::Result<Vec<u8>> {
+	let mut header = Vec::new();
+	File::open(path)?.take(HEADER_SIZE).read_to_end(&mut header)?;
+	Ok(header)
+}
+
+// UTF-8 without NUL bytes. The header may end part way through a character.
+fn looks_like_text(header: &[u8]) -> bool {
+	if header.contains(&0) {
+		return false;
+	}
+	match std::str::from_utf8(header) {
+		Ok(_) => true,
+		Err(e) => e.error_len().is_none(),
+	}
+}
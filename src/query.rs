@@ -0,0 +1,482 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+/* Search query language, e.g.
+ *     tag:invoice AND (tag:2023 OR tag:2024) AND NOT tag:paid
+ *
+ * NOT binds tighter than AND, which binds tighter than OR. Terms written next to each other
+ * without an operator are joined with AND. Values containing spaces or parentheses can be
+ * quoted: tag:"tax return"
+ *
+ * Terms:
+ *     tag:name     files with the tag or a tag below it
+ *     note:words   files whose note contains all of the words
+ *     name=value   files with an attribute compared against a value, using one of
+ *                  = != < <= > >=, e.g. due<2026-12-01 or rating>=4. Unquoted values are
+ *                  read as numbers or dates when possible, quoted values are always text.
+ *
+ * Terms over the cached file metadata (see metadata.rs), which take the place of attributes
+ * of the same name:
+ *     size>10MB          size in bytes, or with a unit: KB MB GB TB, or KiB MiB GiB TiB
+ *     modified<2026-01-01, changed>=2026-01-01
+ *                        local date of the mtime or ctime
+ *     modified:last-week one of today, yesterday, last-week, last-month, last-year, or a date
+ *     changed:today      the same for the ctime
+ *     mode=644           permission bits, in octal
+ *     type:dir           one of file, dir, symlink, other
+ *     type:image         a MIME type, or the part before the slash, e.g. type:application/pdf
+ *     mime:image/png     MIME type, where * matches anything, e.g. mime:video*
+ */
+
+use crate::attributes::{is_valid_date, AttrValue, CompareOp};
+use crate::index::FileType;
+
+use rusqlite::types::Value;
+
+// Prefixes which mark the start of a search term
+const TERM_PREFIXES: &[&str] = &["tag:", "note:", "modified:", "changed:", "type:", "mime:"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+	Tag(String),
+	// Full text search of notes, every word must appear
+	Note(String),
+	Attribute(String, CompareOp, AttrValue),
+	Size(CompareOp, u64),
+	// Local date of the mtime or ctime compared against a YYYY-MM-DD date
+	Date(TimeField, CompareOp, String),
+	Within(TimeField, Period),
+	Type(FileType),
+	// Glob over the MIME type
+	Mime(String),
+	Permissions(CompareOp, u32),
+	And(Box<Query>, Box<Query>),
+	Or(Box<Query>, Box<Query>),
+	Not(Box<Query>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeField {
+	Modified,
+	Changed,
+}
+
+impl TimeField {
+	fn parse(name: &str) -> Option<Self> {
+		match name {
+			"modified" => Some(TimeField::Modified),
+			"changed" => Some(TimeField::Changed),
+			_ => None,
+		}
+	}
+
+	fn column(&self) -> &'static str {
+		match self {
+			TimeField::Modified => "file_notes.mtime",
+			TimeField::Changed => "file_notes.ctime",
+		}
+	}
+}
+
+// Recent stretch of time, ending now
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+	// Since midnight
+	Today,
+	Yesterday,
+	// The last 7, 30 and 365 days
+	LastWeek,
+	LastMonth,
+	LastYear,
+}
+
+impl Period {
+	fn parse(text: &str) -> Option<Self> {
+		match text {
+			"today" => Some(Period::Today),
+			"yesterday" => Some(Period::Yesterday),
+			"last-week" => Some(Period::LastWeek),
+			"last-month" => Some(Period::LastMonth),
+			"last-year" => Some(Period::LastYear),
+			_ => None,
+		}
+	}
+}
+
+// Read a size such as 512, 1.5GB or 4KiB as a number of bytes
+pub fn parse_size(text: &str) -> Option<u64> {
+	let split = text.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(text.len());
+	let (number, unit) = text.split_at(split);
+	let multiplier: u64 = match unit.to_lowercase().as_str() {
+		"" | "b" => 1,
+		"k" | "kb" => 1_000,
+		"m" | "mb" => 1_000_000,
+		"g" | "gb" => 1_000_000_000,
+		"t" | "tb" => 1_000_000_000_000,
+		"kib" => 1 << 10,
+		"mib" => 1 << 20,
+		"gib" => 1 << 30,
+		"tib" => 1 << 40,
+		_ => return None,
+	};
+
+	let number: f64 = number.parse().ok().filter(|n: &f64| n.is_finite())?;
+	Some((number * multiplier as f64).round() as u64)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError {
+	pub message: String,
+}
+
+impl std::fmt::Display for QueryError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "Invalid query: {}", self.message)
+	}
+}
+
+impl std::error::Error for QueryError {}
+
+fn error<T>(message: String) -> Result<T, QueryError> {
+	Err(QueryError { message })
+}
+
+// Top level MIME types which type: accepts on their own, e.g. type:image
+const MIME_CATEGORIES: &[&str] = &["application", "audio", "font", "image", "inode", "model", "text", "video"];
+
+// Characters which may start a comparison operator
+const OPERATOR_CHARS: &[char] = &['=', '!', '<', '>'];
+
+// Whether text typed into the directory entry should be run as a query instead of opened as a path
+pub fn looks_like_query(text: &str) -> bool {
+	let text = text.trim_start();
+	let first_word = text.split_whitespace().next().unwrap_or("");
+	text.starts_with('(')
+		|| text.starts_with("NOT ")
+		|| TERM_PREFIXES.iter().any(|prefix| text.starts_with(prefix))
+		|| (!text.starts_with(['/', '~', '.']) && attribute_name_len(first_word).is_some())
+}
+
+// Length of the attribute name at the start of a comparison like "due<2026-12-01"
+fn attribute_name_len(word: &str) -> Option<usize> {
+	let end = word.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-' || c == '.'))?;
+	if end > 0 && word[end..].starts_with(OPERATOR_CHARS) {
+		Some(end)
+	} else {
+		None
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+	And,
+	Or,
+	Not,
+	Open,
+	Close,
+	Term(String, String),
+	Compare(String, String, String, bool),
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, QueryError> {
+	let mut tokens = Vec::new();
+	let mut chars = input.chars().peekable();
+
+	while let Some(&c) = chars.peek() {
+		if c.is_whitespace() {
+			chars.next();
+			continue;
+		}
+		if c == '(' {
+			chars.next();
+			tokens.push(Token::Open);
+			continue;
+		}
+		if c == ')' {
+			chars.next();
+			tokens.push(Token::Close);
+			continue;
+		}
+
+		// Read a word, which ends at whitespace or a parenthesis outside of quotes
+		let mut word = String::new();
+		let mut quoted = false;
+		let mut in_quotes = false;
+		while let Some(&c) = chars.peek() {
+			if c == '"' {
+				in_quotes = !in_quotes;
+				quoted = true;
+			} else if !in_quotes && (c.is_whitespace() || c == '(' || c == ')') {
+				break;
+			} else {
+				word.push(c);
+			}
+			chars.next();
+		}
+		if in_quotes {
+			return error(format!("Unterminated quote in \"{word}\""));
+		}
+
+		let token = match word.as_str() {
+			"AND" | "and" if !quoted => Token::And,
+			"OR" | "or" if !quoted => Token::Or,
+			"NOT" | "not" if !quoted => Token::Not,
+			_ => match (attribute_name_len(&word), word.split_once(':')) {
+				(Some(end), _) => {
+					let rest = &word[end..];
+					let op_len = rest.find(|c: char| !OPERATOR_CHARS.contains(&c)).unwrap_or(rest.len());
+					Token::Compare(word[..end].to_string(), rest[..op_len].to_string(), rest[op_len..].to_string(), quoted)
+				},
+				(None, Some((prefix, value))) => Token::Term(prefix.to_lowercase(), value.trim().to_string()),
+				(None, None) => return error(format!("Expected a term such as tag:name, found \"{word}\"")),
+			},
+		};
+		tokens.push(token);
+	}
+
+	Ok(tokens)
+}
+
+struct Parser {
+	tokens: Vec<Token>,
+	pos: usize,
+}
+
+impl Parser {
+	fn peek(&self) -> Option<&Token> {
+		self.tokens.get(self.pos)
+	}
+
+	fn next(&mut self) -> Option<Token> {
+		let token = self.tokens.get(self.pos).cloned();
+		self.pos += 1;
+		token
+	}
+
+	// or_expr := and_expr ("OR" and_expr)*
+	fn parse_or(&mut self) -> Result<Query, QueryError> {
+		let mut query = self.parse_and()?;
+		while self.peek() == Some(&Token::Or) {
+			self.next();
+			query = Query::Or(Box::new(query), Box::new(self.parse_and()?));
+		}
+		Ok(query)
+	}
+
+	// and_expr := not_expr (["AND"] not_expr)*
+	fn parse_and(&mut self) -> Result<Query, QueryError> {
+		let mut query = self.parse_not()?;
+		loop {
+			match self.peek() {
+				Some(Token::And) => {
+					self.next();
+				},
+				Some(Token::Not) | Some(Token::Open) | Some(Token::Term(..)) | Some(Token::Compare(..)) => {},
+				_ => break,
+			}
+			query = Query::And(Box::new(query), Box::new(self.parse_not()?));
+		}
+		Ok(query)
+	}
+
+	// not_expr := "NOT" not_expr | "(" or_expr ")" | term
+	fn parse_not(&mut self) -> Result<Query, QueryError> {
+		match self.next() {
+			Some(Token::Not) => Ok(Query::Not(Box::new(self.parse_not()?))),
+			Some(Token::Open) => {
+				let query = self.parse_or()?;
+				match self.next() {
+					Some(Token::Close) => Ok(query),
+					_ => error("Missing closing parenthesis".to_string()),
+				}
+			},
+			Some(Token::Term(prefix, value)) => parse_term(&prefix, value),
+			Some(Token::Compare(name, op, value, quoted)) => parse_comparison(name, &op, &value, quoted),
+			Some(token) => error(format!("Unexpected {token:?}")),
+			None => error("Unexpected end of query".to_string()),
+		}
+	}
+}
+
+fn parse_term(prefix: &str, value: String) -> Result<Query, QueryError> {
+	if value.is_empty() {
+		return error(format!("Missing value after {prefix}:"));
+	}
+
+	match prefix {
+		"tag" => Ok(Query::Tag(crate::normalize_tag(&value))),
+		"note" if crate::fts_query(&value).is_empty() => error("Missing words after note:".to_string()),
+		"note" => Ok(Query::Note(value)),
+		"modified" | "changed" => {
+			let field = TimeField::parse(prefix).unwrap_or(TimeField::Modified);
+			if let Some(period) = Period::parse(&value.to_lowercase()) {
+				Ok(Query::Within(field, period))
+			} else if is_valid_date(&value) {
+				Ok(Query::Date(field, CompareOp::Eq, value))
+			} else {
+				error(format!("Expected a date or one of today, yesterday, last-week, last-month, last-year after {prefix}:"))
+			}
+		},
+		"type" => parse_type(&value.to_lowercase()),
+		"mime" => Ok(Query::Mime(value.to_lowercase())),
+		_ => error(format!("Unknown search prefix {prefix}:")),
+	}
+}
+
+// type: takes either a kind of entry or a MIME type
+fn parse_type(value: &str) -> Result<Query, QueryError> {
+	if let Some(file_type) = FileType::parse(value) {
+		Ok(Query::Type(file_type))
+	} else if value.contains('/') {
+		Ok(Query::Mime(value.to_string()))
+	} else if MIME_CATEGORIES.contains(&value) {
+		Ok(Query::Mime(format!("{value}/*")))
+	} else {
+		error(format!("Unknown type {value}, expected file, dir, symlink, other or a MIME type such as image"))
+	}
+}
+
+// Comparisons against the cached metadata rather than an attribute
+fn parse_metadata_comparison(name: &str, op: CompareOp, value: &str) -> Option<Result<Query, QueryError>> {
+	let query = match name {
+		"size" => match parse_size(value) {
+			Some(size) => Ok(Query::Size(op, size)),
+			None => error(format!("Expected a size such as 10MB after {name}, found \"{value}\"")),
+		},
+		"modified" | "changed" => {
+			let field = TimeField::parse(name)?;
+			if is_valid_date(value) {
+				Ok(Query::Date(field, op, value.to_string()))
+			} else {
+				error(format!("Expected a date such as 2026-01-31 after {name}, found \"{value}\""))
+			}
+		},
+		"mode" => match u32::from_str_radix(value, 8) {
+			Ok(mode) if mode <= 0o7777 => Ok(Query::Permissions(op, mode)),
+			_ => error(format!("Expected octal permissions such as 644 after {name}, found \"{value}\"")),
+		},
+		_ => return None,
+	};
+	Some(query)
+}
+
+fn parse_comparison(name: String, op: &str, value: &str, quoted: bool) -> Result<Query, QueryError> {
+	let op = match CompareOp::parse(op) {
+		Some(op) => op,
+		None => return error(format!("Unknown comparison {op} after {name}")),
+	};
+
+	if value.is_empty() {
+		return error(format!("Missing value after {name}"));
+	}
+	if let Some(query) = parse_metadata_comparison(&name, op, value) {
+		return query;
+	}
+
+	let value = if quoted {
+		AttrValue::Text(value.to_string())
+	} else {
+		AttrValue::parse(value)
+	};
+
+	Ok(Query::Attribute(name, op, value))
+}
+
+pub fn parse(input: &str) -> Result<Query, QueryError> {
+	let mut parser = Parser { tokens: tokenize(input)?, pos: 0 };
+	if parser.tokens.is_empty() {
+		return error("Empty query".to_string());
+	}
+
+	let query = parser.parse_or()?;
+	match parser.next() {
+		None => Ok(query),
+		Some(token) => error(format!("Unexpected {token:?}")),
+	}
+}
+
+impl Query {
+	// Compile into an SQL condition over a row of file_notes, pushing bound values onto params
+	pub fn to_sql(&self, params: &mut Vec<Value>) -> String {
+		match self {
+			// Matches the tag itself and every tag below it in the hierarchy
+			Query::Tag(tag) => {
+				params.push(Value::Text(tag.clone()));
+				format!(
+					"EXISTS (WITH RECURSIVE subtree(id) AS (
+						SELECT id FROM file_tags WHERE tag_name=?{}
+						UNION
+						SELECT file_tags.id FROM file_tags INNER JOIN subtree ON file_tags.parent_id=subtree.id
+					)
+					SELECT 1 FROM tag_relations
+					WHERE tag_relations.file_id=file_notes.id AND tag_relations.tag_id IN subtree)",
+					params.len()
+				)
+			},
+			Query::Note(text) => {
+				params.push(Value::Text(crate::fts_query(text)));
+				format!("file_notes.id IN (SELECT rowid FROM notes_fts WHERE notes_fts MATCH ?{})", params.len())
+			},
+			Query::Attribute(name, op, value) => {
+				params.push(Value::Text(name.clone()));
+				params.push(value.to_sql_value());
+				format!(
+					"EXISTS (SELECT 1 FROM file_attributes
+					WHERE file_attributes.file_id=file_notes.id AND file_attributes.name=?{}
+					AND file_attributes.kind IN {} AND file_attributes.value {} ?{})",
+					params.len() - 1, value.comparable_kinds(), op.sql(), params.len()
+				)
+			},
+			Query::Size(op, size) => {
+				params.push(Value::Integer(*size as i64));
+				format!("file_notes.size {} ?{}", op.sql(), params.len())
+			},
+			Query::Date(field, op, date) => {
+				params.push(Value::Text(date.clone()));
+				format!("date({} / 1000000000, 'unixepoch', 'localtime') {} ?{}", field.column(), op.sql(), params.len())
+			},
+			Query::Within(field, period) => {
+				let local_date = format!("date({} / 1000000000, 'unixepoch', 'localtime')", field.column());
+				let since = |days: u32| {
+					format!("{} >= CAST(strftime('%s', 'now', '-{days} days') AS INTEGER) * 1000000000", field.column())
+				};
+				match period {
+					Period::Today => format!("{local_date} = date('now', 'localtime')"),
+					Period::Yesterday => format!("{local_date} = date('now', 'localtime', '-1 day')"),
+					Period::LastWeek => since(7),
+					Period::LastMonth => since(30),
+					Period::LastYear => since(365),
+				}
+			},
+			Query::Type(file_type) => {
+				params.push(Value::Text(file_type.as_str().to_string()));
+				format!("file_notes.file_type = ?{}", params.len())
+			},
+			Query::Mime(pattern) => {
+				params.push(Value::Text(pattern.clone()));
+				format!("file_notes.mime_type GLOB ?{}", params.len())
+			},
+			Query::Permissions(op, mode) => {
+				params.push(Value::Integer(*mode as i64));
+				format!("file_notes.permissions {} ?{}", op.sql(), params.len())
+			},
+			Query::And(a, b) => format!("({} AND {})", a.to_sql(params), b.to_sql(params)),
+			Query::Or(a, b) => format!("({} OR {})", a.to_sql(params), b.to_sql(params)),
+			Query::Not(a) => format!("(NOT {})", a.to_sql(params)),
+		}
+	}
+}
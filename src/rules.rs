@@ -0,0 +1,356 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+/* Automatic tagging rules
+ *
+ * A rule pairs conditions on a file with actions to take on it. Rules are written in TOML,
+ * by default in $XDG_CONFIG_HOME/filechest/rules.toml:
+ *
+ *     [[rule]]
+ *     name = "Invoices"
+ *     path_prefix = "~/Downloads"
+ *     glob = "*invoice*.pdf"
+ *     tags = ["invoice"]
+ *     attributes = { source = "download" }
+ *
+ * Conditions, every one given must hold:
+ *     glob         pattern for the file name, or for the whole path if it contains a /
+ *     mime         pattern for the MIME type, e.g. "video*"
+ *     min_size, max_size
+ *                  bytes, or a size with a unit such as "10MB"
+ *     path_prefix  directory the file must be below
+ *     name_regex   regular expression searched for in the file name
+ *
+ * Actions:
+ *     tags         added to the file's tags
+ *     attributes   set unless the file already has them, so values changed by hand are kept.
+ *                  Values are read as numbers or dates like in searches
+ *     note         appended to the file's note, unless the note already contains it
+ *
+ * Rules only apply to regular files. A file which no rule matches is left without a record.
+ * Tags and notes added by rules are mirrored to extended attributes and sidecars like any other.
+ */
+
+use crate::attributes::{is_valid_date, AttrValue};
+use crate::index::{scan, FileType, IndexOptions, IndexReport, BATCH_SIZE};
+use crate::metadata::FileMetadata;
+use crate::query::parse_size;
+use crate::{FileChestError, FileRef, NotesDB, RecordStatus, FC_DIR};
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use glob::{MatchOptions, Pattern};
+use regex::Regex;
+use serde::Deserialize;
+
+pub const RULES_FILE: &str = "rules.toml";
+
+// $XDG_CONFIG_HOME/filechest/rules.toml
+pub fn default_rules_path() -> Option<PathBuf> {
+	dirs::config_dir().map(|dir| dir.join(FC_DIR).join(RULES_FILE))
+}
+
+// A rule as written in the rules file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleDef {
+	name: String,
+	glob: Option<String>,
+	mime: Option<String>,
+	min_size: Option<toml::Value>,
+	max_size: Option<toml::Value>,
+	path_prefix: Option<String>,
+	name_regex: Option<String>,
+	#[serde(default)]
+	tags: Vec<String>,
+	#[serde(default)]
+	attributes: BTreeMap<String, toml::Value>,
+	note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+	#[serde(default)]
+	rule: Vec<RuleDef>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+	pub name: String,
+	// The pattern, and whether it is matched against the whole path rather than the name
+	glob: Option<(Pattern, bool)>,
+	mime: Option<Pattern>,
+	min_size: Option<u64>,
+	max_size: Option<u64>,
+	path_prefix: Option<PathBuf>,
+	name_regex: Option<Regex>,
+	pub tags: Vec<String>,
+	pub attributes: Vec<(String, AttrValue)>,
+	pub note: Option<String>,
+}
+
+impl Rule {
+	fn from_def(def: RuleDef) -> Result<Self, FileChestError> {
+		let invalid = |message: String| FileChestError::InvalidInput(format!("Rule \"{}\": {message}", def.name));
+
+		let glob = match &def.glob {
+			Some(glob) => {
+				let glob = expand_home(glob);
+				let pattern = Pattern::new(&glob).map_err(|e| invalid(format!("invalid glob {glob}: {e}")))?;
+				Some((pattern, glob.contains('/')))
+			},
+			None => None,
+		};
+		let mime = match &def.mime {
+			Some(mime) => Some(Pattern::new(&mime.to_lowercase()).map_err(|e| invalid(format!("invalid MIME pattern {mime}: {e}")))?),
+			None => None,
+		};
+		let size = |value: &Option<toml::Value>| match value {
+			Some(value) => size_value(value).map(Some).ok_or_else(|| invalid(format!("expected a size such as 10MB, found {value}"))),
+			None => Ok(None),
+		};
+		let (min_size, max_size) = (size(&def.min_size)?, size(&def.max_size)?);
+		let path_prefix = match &def.path_prefix {
+			Some(prefix) => {
+				let prefix = PathBuf::from(expand_home(prefix));
+				if !prefix.is_absolute() {
+					return Err(invalid(format!("path_prefix {} is not an absolute path", prefix.display())));
+				}
+				Some(prefix)
+			},
+			None => None,
+		};
+		let name_regex = match &def.name_regex {
+			Some(regex) => Some(Regex::new(regex).map_err(|e| invalid(format!("invalid name_regex: {e}")))?),
+			None => None,
+		};
+		let attributes = def.attributes.iter()
+			.map(|(name, value)| match attr_value(value) {
+				Some(value) => Ok((name.clone(), value)),
+				None => Err(invalid(format!("attribute {name} must be a string, number or date"))),
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+
+		let rule = Rule {
+			name: def.name.clone(),
+			glob,
+			mime,
+			min_size,
+			max_size,
+			path_prefix,
+			name_regex,
+			tags: def.tags.clone(),
+			attributes,
+			note: def.note.clone().filter(|note| !note.trim().is_empty()),
+		};
+
+		let has_condition = rule.glob.is_some() || rule.mime.is_some() || rule.min_size.is_some()
+			|| rule.max_size.is_some() || rule.path_prefix.is_some() || rule.name_regex.is_some();
+		if !has_condition {
+			return Err(invalid("needs at least one condition".to_string()));
+		}
+		if rule.tags.is_empty() && rule.attributes.is_empty() && rule.note.is_none() {
+			return Err(invalid("needs at least one of tags, attributes or note".to_string()));
+		}
+		Ok(rule)
+	}
+
+	// Whether the file at path, described by metadata, meets every condition of the rule
+	pub fn matches(&self, path: &Path, metadata: &FileMetadata) -> bool {
+		// path_prefix is absolute, so relative paths are made absolute against the working directory
+		let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+		let path = absolute.as_path();
+		let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+
+		if let Some((pattern, whole_path)) = &self.glob {
+			let options = MatchOptions { require_literal_separator: true, ..MatchOptions::new() };
+			let matched = if *whole_path {
+				pattern.matches_path_with(path, options)
+			} else {
+				pattern.matches_with(&name, options)
+			};
+			if !matched {
+				return false;
+			}
+		}
+		if let Some(pattern) = &self.mime {
+			if !metadata.mime_type.as_deref().is_some_and(|mime| pattern.matches(mime)) {
+				return false;
+			}
+		}
+		if self.min_size.is_some_and(|min| metadata.size < min) || self.max_size.is_some_and(|max| metadata.size > max) {
+			return false;
+		}
+		if let Some(prefix) = &self.path_prefix {
+			if !path.starts_with(prefix) {
+				return false;
+			}
+		}
+		if let Some(regex) = &self.name_regex {
+			if !regex.is_match(&name) {
+				return false;
+			}
+		}
+		true
+	}
+}
+
+// Replace a leading ~/ with the home directory
+fn expand_home(text: &str) -> String {
+	match (text.strip_prefix("~/"), dirs::home_dir()) {
+		(Some(rest), Some(home)) => home.join(rest).to_string_lossy().to_string(),
+		_ => text.to_string(),
+	}
+}
+
+fn size_value(value: &toml::Value) -> Option<u64> {
+	match value {
+		toml::Value::Integer(bytes) => u64::try_from(*bytes).ok(),
+		toml::Value::String(text) => parse_size(text.trim()),
+		_ => None,
+	}
+}
+
+fn attr_value(value: &toml::Value) -> Option<AttrValue> {
+	match value {
+		toml::Value::String(text) => Some(AttrValue::parse(text)),
+		toml::Value::Integer(i) => Some(AttrValue::Integer(*i)),
+		toml::Value::Float(r) => Some(AttrValue::Real(*r)),
+		// Only plain dates, as attributes have no time of day
+		toml::Value::Datetime(date) => Some(date.to_string()).filter(|date| is_valid_date(date)).map(AttrValue::Date),
+		_ => None,
+	}
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+	rules: Vec<Rule>,
+}
+
+impl RuleSet {
+	pub fn parse(text: &str) -> Result<Self, FileChestError> {
+		let file: RulesFile = toml::from_str(text).map_err(|e| FileChestError::InvalidInput(format!("Invalid rules: {e}")))?;
+		let rules = file.rule.into_iter().map(Rule::from_def).collect::<Result<_, _>>()?;
+		Ok(RuleSet { rules })
+	}
+
+	pub fn load(path: &Path) -> Result<Self, FileChestError> {
+		let text = std::fs::read_to_string(path)?;
+		Self::parse(&text).map_err(|e| FileChestError::InvalidInput(format!("{}: {e}", path.display())))
+	}
+
+	// The rules in the default location, none if the file does not exist
+	pub fn load_default() -> Result<Self, FileChestError> {
+		match default_rules_path() {
+			Some(path) if path.exists() => Self::load(&path),
+			_ => Ok(RuleSet::default()),
+		}
+	}
+
+	pub fn rules(&self) -> &[Rule] {
+		&self.rules
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.rules.is_empty()
+	}
+}
+
+impl NotesDB {
+	// Rules applied by index, by the watcher and by apply_rules
+	pub fn set_rules(&mut self, rules: RuleSet) {
+		self.rules = rules;
+	}
+
+	pub fn rules(&self) -> &RuleSet {
+		&self.rules
+	}
+
+	// Run the actions of every rule matching the file at its current path.
+	// Returns the number of rules which matched.
+	pub fn apply_rules(&self, file_ref: &FileRef) -> Result<usize, FileChestError> {
+		if self.rules.is_empty() {
+			return Ok(0);
+		}
+		let m = std::fs::symlink_metadata(&file_ref.file_path)?;
+		let metadata = FileMetadata::from_metadata(&file_ref.file_path, &m);
+		self.atomic(|| self.apply_rules_to(file_ref, &metadata))
+	}
+
+	// Apply the rules to every file below root, skipping the same files as index.
+	// Unlike index, only files which match a rule are given a record, and added and updated
+	// are left at 0.
+	pub fn apply_rules_below<P: AsRef<Path>>(&self, root: P, options: &IndexOptions) -> Result<IndexReport, FileChestError> {
+		let mut report = IndexReport::default();
+		if self.rules.is_empty() {
+			return Ok(report);
+		}
+
+		let found = scan(root.as_ref(), options, &mut report, &mut |_| {})?;
+		for batch in found.chunks(BATCH_SIZE) {
+			self.atomic(|| {
+				for (file_ref, metadata) in batch {
+					if metadata.file_type != FileType::File {
+						continue;
+					}
+					report.checked += 1;
+					if self.record_status(file_ref)? == RecordStatus::Stale {
+						report.stale.push(file_ref.file_path.clone());
+					} else if self.apply_rules_to(file_ref, metadata)? > 0 {
+						report.matched_rules += 1;
+					}
+				}
+				Ok(())
+			})?;
+		}
+		Ok(report)
+	}
+
+	// Run the actions of the rules matching a file, which must not be stale, then store its metadata
+	pub(crate) fn apply_rules_to(&self, file_ref: &FileRef, metadata: &FileMetadata) -> Result<usize, FileChestError> {
+		if metadata.file_type != FileType::File {
+			return Ok(0);
+		}
+
+		let mut matched = 0;
+		for rule in self.rules.rules.iter().filter(|rule| rule.matches(&file_ref.file_path, metadata)) {
+			for tag in &rule.tags {
+				self.add_tag(file_ref, tag)?;
+			}
+			for (name, value) in &rule.attributes {
+				if self.get_attribute(file_ref, name)?.is_none() {
+					self.set_attribute(file_ref, name, value)?;
+				}
+			}
+			if let Some(text) = &rule.note {
+				let note = self.stored_note(file_ref)?.unwrap_or_default();
+				if !note.contains(text.as_str()) {
+					let separator = if note.is_empty() || note.ends_with('\n') { "" } else { "\n" };
+					self.set_note(file_ref, &format!("{note}{separator}{text}"))?;
+				}
+			}
+			matched += 1;
+		}
+
+		if matched > 0 {
+			self.set_file_metadata(file_ref, metadata)?;
+		}
+		Ok(matched)
+	}
+}
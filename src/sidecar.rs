@@ -0,0 +1,315 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+/* Sidecar manifests: notes and tags stored next to the files they describe
+ *
+ * Every directory may hold a .filechest.toml manifest for the files directly inside it,
+ * keyed by file name, so notes travel with the files on network drives and in git:
+ *
+ *     version = 1
+ *
+ *     [files."budget.ods"]
+ *     note = "Q3 budget draft"
+ *     tags = ["finance", "project/alpha"]
+ *     changed = 1700000000
+ *
+ * changed is when the note or tags last changed, in seconds since the unix epoch. Sync uses it
+ * to decide which side is newer; entries edited by hand should have it removed or raised.
+ * An entry without it counts as changed when the manifest was last modified.
+ * Files whose names are not valid UTF-8 can not be listed and are left out.
+ */
+
+use crate::export::{ImportMode, ImportReport};
+use crate::{normalize_tag, FileChestError, FileRef, NotesDB, RecordStatus};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+
+pub const SIDECAR_FILE: &str = ".filechest.toml";
+pub const SIDECAR_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+	version: u32,
+	#[serde(default)]
+	files: BTreeMap<String, SidecarEntry>,
+}
+
+impl Default for Manifest {
+	fn default() -> Self {
+		Self {
+			version: SIDECAR_VERSION,
+			files: BTreeMap::new(),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct SidecarEntry {
+	#[serde(default, skip_serializing_if = "String::is_empty")]
+	note: String,
+	#[serde(default)]
+	tags: Vec<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	changed: Option<i64>,
+}
+
+impl SidecarEntry {
+	fn is_empty(&self) -> bool {
+		self.note.is_empty() && self.tags.is_empty()
+	}
+
+	// Whether both entries hold the same note and tags, whatever the tags' order or spelling
+	fn same_content(&self, other: &SidecarEntry) -> bool {
+		let tags = |entry: &SidecarEntry| entry.tags.iter().map(|t| normalize_tag(t)).collect::<BTreeSet<_>>();
+		self.note == other.note && tags(self) == tags(other)
+	}
+}
+
+// Result of NotesDB::sync_sidecar
+#[derive(Debug, Default, Clone)]
+pub struct SidecarReport {
+	// Files whose manifest entry was written from the database
+	pub exported: Vec<PathBuf>,
+	// Files whose record was updated from the manifest
+	pub imported: Vec<PathBuf>,
+	// Manifest entries for files which do not exist
+	pub skipped: Vec<PathBuf>,
+}
+
+fn manifest_path(dir: &Path) -> PathBuf {
+	dir.join(SIDECAR_FILE)
+}
+
+// Read a directory's manifest, or an empty one if there is none.
+// Also returns the manifest's modification time, used for entries without a changed time.
+fn read_manifest(dir: &Path) -> Result<(Manifest, i64), FileChestError> {
+	let path = manifest_path(dir);
+	let text = match fs::read_to_string(&path) {
+		Ok(text) => text,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((Manifest::default(), 0)),
+		Err(e) => return Err(e.into()),
+	};
+
+	let manifest: Manifest = toml::from_str(&text)
+		.map_err(|e| FileChestError::InvalidInput(format!("Could not read {}: {e}", path.display())))?;
+	if manifest.version > SIDECAR_VERSION {
+		return Err(FileChestError::InvalidInput(format!(
+			"{} has version {}, newer than the supported version {SIDECAR_VERSION}", path.display(), manifest.version
+		)));
+	}
+
+	let modified = fs::metadata(&path)?.modified()?
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |d| d.as_secs() as i64);
+	Ok((manifest, modified))
+}
+
+// Replace a directory's manifest, going through a temporary file so readers never see half of it.
+// An empty manifest is removed.
+fn write_manifest(dir: &Path, manifest: &Manifest) -> Result<(), FileChestError> {
+	let path = manifest_path(dir);
+	if manifest.files.is_empty() {
+		return match fs::remove_file(&path) {
+			Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+			_ => Ok(()),
+		};
+	}
+
+	let text = toml::to_string_pretty(manifest)
+		.map_err(|e| FileChestError::InvalidInput(format!("Could not write {}: {e}", path.display())))?;
+	let temp = dir.join(format!("{SIDECAR_FILE}.tmp"));
+	fs::write(&temp, text)?;
+	fs::rename(&temp, &path)?;
+	Ok(())
+}
+
+// The directory and name a file is listed under in a manifest
+fn split_path(path: &Path) -> Option<(&Path, &str)> {
+	Some((path.parent()?, path.file_name()?.to_str()?))
+}
+
+impl NotesDB {
+	// Keep the sidecar manifest of each file's directory up to date whenever set_tags or set_note runs
+	pub fn set_sidecar_sync(&mut self, enabled: bool) {
+		self.sidecar_sync = enabled;
+	}
+
+	pub fn sidecar_sync(&self) -> bool {
+		self.sidecar_sync
+	}
+
+	// The note and tags stored for a file, None if there are none
+	fn sidecar_entry(&self, file_ref: &FileRef) -> Result<Option<SidecarEntry>, FileChestError> {
+		if self.record_status(file_ref)? != RecordStatus::Current {
+			return Ok(None);
+		}
+
+		let (note, changed) = self.conn.query_row(
+			"SELECT note, changed_at FROM file_notes WHERE device=?1 AND inode=?2",
+			(file_ref.device, file_ref.inode),
+			|row| Ok((row.get::<usize, Option<String>>(0)?, row.get::<usize, Option<i64>>(1)?))
+		).optional()?.unwrap_or_default();
+
+		let mut tags = self.get_tags(file_ref)?;
+		tags.sort();
+
+		let entry = SidecarEntry { note: note.unwrap_or_default(), tags, changed };
+		Ok((!entry.is_empty()).then_some(entry))
+	}
+
+	// Called after the database has been updated. Like extended attributes, a manifest which
+	// can not be written is only a warning.
+	pub(crate) fn sync_sidecar_entry(&self, file_ref: &FileRef) {
+		if !self.sidecar_sync {
+			return;
+		}
+		if let Err(e) = self.write_sidecar_entry(file_ref) {
+			self.warn(&format!("Could not update the sidecar manifest for {}: {e}", file_ref.file_path.display()));
+		}
+	}
+
+	fn write_sidecar_entry(&self, file_ref: &FileRef) -> Result<(), FileChestError> {
+		let (dir, name) = match split_path(&file_ref.file_path) {
+			Some(split) => split,
+			None => return Ok(()),
+		};
+
+		let (mut manifest, _) = read_manifest(dir)?;
+		match self.sidecar_entry(file_ref)? {
+			Some(entry) => manifest.files.insert(name.to_string(), entry),
+			None => manifest.files.remove(name),
+		};
+		write_manifest(dir, &manifest)
+	}
+
+	// Write the notes and tags of every recorded file directly inside dir to its manifest.
+	// Entries for other files already in the manifest are kept.
+	pub fn export_sidecar<P: AsRef<Path>>(&self, dir: P) -> Result<Vec<PathBuf>, FileChestError> {
+		let dir = dir.as_ref();
+		let (mut manifest, _) = read_manifest(dir)?;
+
+		let mut exported = Vec::new();
+		for file_ref in self.files_in_dir(dir)? {
+			if let (Some(entry), Some((_, name))) = (self.sidecar_entry(&file_ref)?, split_path(&file_ref.file_path)) {
+				manifest.files.insert(name.to_string(), entry);
+				exported.push(file_ref.file_path);
+			}
+		}
+
+		write_manifest(dir, &manifest)?;
+		Ok(exported)
+	}
+
+	// Load dir's manifest into the database. Tags are added to the stored ones, and notes which
+	// differ from the stored ones are reported and replaced only in Overwrite mode.
+	pub fn import_sidecar<P: AsRef<Path>>(&self, dir: P, mode: ImportMode) -> Result<ImportReport, FileChestError> {
+		let dir = dir.as_ref();
+		let (manifest, _) = read_manifest(dir)?;
+
+		self.atomic(|| {
+			let mut report = ImportReport::default();
+			for (name, entry) in &manifest.files {
+				let file_ref = match FileRef::from_pathbuf(&dir.join(name)) {
+					Ok(file_ref) => file_ref,
+					Err(_) => continue,
+				};
+				if !self.begin_import(&file_ref, mode, &mut report)? {
+					continue;
+				}
+
+				for tag in &entry.tags {
+					self.add_tag(&file_ref, tag)?;
+				}
+				if !entry.note.is_empty() {
+					self.import_note(&file_ref, &entry.note, mode, &mut report)?;
+				}
+			}
+			Ok(report)
+		})
+	}
+
+	// Two way sync between the database and dir's manifest. For each file the side which changed
+	// last wins and replaces the note and tags on the other side.
+	pub fn sync_sidecar<P: AsRef<Path>>(&self, dir: P) -> Result<SidecarReport, FileChestError> {
+		let dir = dir.as_ref();
+		let (mut manifest, manifest_modified) = read_manifest(dir)?;
+
+		let mut names: BTreeSet<String> = manifest.files.keys().cloned().collect();
+		for file_ref in self.files_in_dir(dir)? {
+			if let Some((_, name)) = split_path(&file_ref.file_path) {
+				names.insert(name.to_string());
+			}
+		}
+
+		let mut report = SidecarReport::default();
+		self.atomic(|| {
+			for name in names {
+				let path = dir.join(&name);
+				let file_ref = match FileRef::from_pathbuf(&path) {
+					Ok(file_ref) => file_ref,
+					Err(_) => {
+						report.skipped.push(path);
+						continue;
+					},
+				};
+
+				let stored = self.sidecar_entry(&file_ref)?;
+				let listed = manifest.files.get(&name).cloned();
+				let import = match (&stored, &listed) {
+					(Some(stored), Some(listed)) if stored.same_content(listed) => continue,
+					(Some(stored), Some(listed)) => listed.changed.unwrap_or(manifest_modified) > stored.changed.unwrap_or(0),
+					(None, Some(_)) => true,
+					(Some(_), None) => false,
+					(None, None) => continue,
+				};
+
+				if import {
+					let listed = listed.unwrap_or_default();
+					if self.record_status(&file_ref)? == RecordStatus::Stale {
+						self.forget_record(&file_ref)?;
+					}
+					self.set_note(&file_ref, &listed.note)?;
+					self.set_tags(&file_ref, listed.tags.iter().map(|t| t.as_str()).collect())?;
+					report.imported.push(path);
+				} else if let Some(stored) = stored {
+					manifest.files.insert(name, stored);
+					report.exported.push(path);
+				}
+			}
+			Ok(())
+		})?;
+
+		if !report.exported.is_empty() {
+			write_manifest(dir, &manifest)?;
+		}
+		Ok(report)
+	}
+
+	// Records whose known path is directly inside dir
+	fn files_in_dir(&self, dir: &Path) -> Result<Vec<FileRef>, FileChestError> {
+		Ok(self.get_all_files()?
+			.into_iter()
+			.filter(|f| f.file_path.parent() == Some(dir))
+			.collect())
+	}
+}
@@ -0,0 +1,283 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+/* Live watching of directory trees
+ *
+ * Subscribes to inotify events below a set of roots and keeps records current while other
+ * programs rename, move and delete files: moves update known_path, and deletions start the
+ * record's missing grace period (see gc.rs) rather than removing it straight away.
+ * If the kernel drops events the roots are reconciled instead. Files which appear or are written
+ * have the automatic tagging rules applied to them (see rules.rs).
+ *
+ * The roots are stored in the database, so the GTK app and the file_chest_watch daemon share them.
+ */
+
+use crate::gc::{is_present, now};
+use crate::{file_ref_from_row, path_bytes, path_from_sql, FileChestError, FileRef, NotesDB, RecordStatus, FILE_REF_COLUMNS};
+
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use notify::event::{AccessKind, AccessMode, EventKind, ModifyKind, RenameMode};
+use notify::{RecursiveMode, Watcher as _};
+
+// How long NotesDB::watch waits for events before checking whether it should stop
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// A change below a watched root which may affect stored records
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchEvent {
+	// A file or directory was renamed or moved within the watched roots
+	Moved { from: PathBuf, to: PathBuf },
+	// Something was created or moved in from elsewhere
+	Appeared(PathBuf),
+	// Something was deleted or moved out of the watched roots
+	Removed(PathBuf),
+	// A file was closed after being written, so its contents are complete
+	Written(PathBuf),
+	// Events were lost, so the roots have to be searched
+	Rescan,
+}
+
+impl WatchEvent {
+	fn from_notify(event: notify::Result<notify::Event>) -> Option<Self> {
+		let mut event = match event {
+			Ok(event) => event,
+			Err(_) => return Some(WatchEvent::Rescan),
+		};
+		if event.need_rescan() {
+			return Some(WatchEvent::Rescan);
+		}
+
+		match event.kind {
+			EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+				let to = event.paths.pop()?;
+				let from = event.paths.pop()?;
+				Some(WatchEvent::Moved { from, to })
+			},
+			EventKind::Modify(ModifyKind::Name(RenameMode::To)) | EventKind::Create(_) => {
+				Some(WatchEvent::Appeared(event.paths.pop()?))
+			},
+			EventKind::Modify(ModifyKind::Name(RenameMode::From)) | EventKind::Remove(_) => {
+				Some(WatchEvent::Removed(event.paths.pop()?))
+			},
+			EventKind::Access(AccessKind::Close(AccessMode::Write)) => Some(WatchEvent::Written(event.paths.pop()?)),
+			_ => None,
+		}
+	}
+}
+
+// Receives events for every file below a set of roots until it is dropped
+pub struct Watcher {
+	roots: Vec<PathBuf>,
+	events: Receiver<notify::Result<notify::Event>>,
+	// Kept alive for as long as events are wanted
+	_watcher: notify::RecommendedWatcher,
+}
+
+impl Watcher {
+	pub fn new<P: AsRef<Path>>(roots: &[P]) -> Result<Self, FileChestError> {
+		let (sender, events) = channel();
+		let mut watcher = notify::recommended_watcher(sender)?;
+
+		let roots: Vec<PathBuf> = roots.iter().map(|root| root.as_ref().to_path_buf()).collect();
+		for root in &roots {
+			watcher.watch(root, RecursiveMode::Recursive)?;
+		}
+
+		Ok(Watcher { roots, events, _watcher: watcher })
+	}
+
+	pub fn roots(&self) -> &[PathBuf] {
+		&self.roots
+	}
+
+	// Wait up to timeout for events, then return them along with any others already waiting.
+	// Events which can not affect records, such as reads, are left out.
+	pub fn events(&self, timeout: Duration) -> Vec<WatchEvent> {
+		let mut found = Vec::new();
+		match self.events.recv_timeout(timeout) {
+			Ok(event) => found.extend(WatchEvent::from_notify(event)),
+			Err(RecvTimeoutError::Timeout) => return found,
+			// The watcher thread died, so nothing can be trusted any more
+			Err(RecvTimeoutError::Disconnected) => return vec![WatchEvent::Rescan],
+		}
+
+		found.extend(self.events.try_iter().filter_map(WatchEvent::from_notify));
+		found
+	}
+}
+
+impl NotesDB {
+	// Remember a directory tree to watch. Roots are stored as given, so pass absolute paths.
+	pub fn add_watch_root<P: AsRef<Path>>(&self, root: P) -> Result<(), FileChestError> {
+		self.conn.execute(
+			"INSERT OR IGNORE INTO watch_roots(path) VALUES(?1);",
+			(root.as_ref().as_os_str().as_bytes(),)
+		)?;
+		Ok(())
+	}
+
+	pub fn remove_watch_root<P: AsRef<Path>>(&self, root: P) -> Result<(), FileChestError> {
+		let removed = self.conn.execute(
+			"DELETE FROM watch_roots WHERE path=?1;",
+			(root.as_ref().as_os_str().as_bytes(),)
+		)?;
+		if removed == 0 {
+			return Err(FileChestError::NotFound(format!("Watched directory {}", root.as_ref().display())));
+		}
+		Ok(())
+	}
+
+	pub fn watch_roots(&self) -> Result<Vec<PathBuf>, FileChestError> {
+		let mut stmt = self.conn.prepare("SELECT path FROM watch_roots ORDER BY path;")?;
+		let roots = stmt.query_map((), |row| Ok(path_from_sql(row.get_ref(0)?)?))?;
+
+		Ok(roots.collect::<Result<_, _>>()?)
+	}
+
+	// Watch roots and apply every event until stop is set. Each event is committed on its own,
+	// so stopping by killing the process loses nothing.
+	pub fn watch(&self, roots: &[PathBuf], stop: &AtomicBool) -> Result<(), FileChestError> {
+		let watcher = Watcher::new(roots)?;
+		while !stop.load(Ordering::Relaxed) {
+			self.apply_watch_events(&watcher.events(POLL_INTERVAL), watcher.roots());
+		}
+		Ok(())
+	}
+
+	// Apply events in order the way watch does. An event which fails is passed to the warning
+	// handler and skipped, and the roots are rescanned afterwards to catch up with whatever it missed.
+	// Returns the number of records changed.
+	pub fn apply_watch_events(&self, events: &[WatchEvent], roots: &[PathBuf]) -> usize {
+		let mut changed = 0;
+		let mut rescan = false;
+		for event in events {
+			match self.apply_watch_event(event, roots) {
+				Ok(n) => changed += n,
+				Err(e) => {
+					self.warn(&format!("Could not apply {event:?}: {e}"));
+					rescan |= *event != WatchEvent::Rescan;
+				},
+			}
+		}
+
+		if rescan {
+			match self.apply_watch_event(&WatchEvent::Rescan, roots) {
+				Ok(n) => changed += n,
+				Err(e) => self.warn(&format!("Could not rescan the watched directories: {e}")),
+			}
+		}
+		changed
+	}
+
+	// Update the records affected by an event. roots are searched on Rescan.
+	// Returns the number of records changed.
+	pub fn apply_watch_event(&self, event: &WatchEvent, roots: &[PathBuf]) -> Result<usize, FileChestError> {
+		self.atomic(|| {
+			match event {
+				WatchEvent::Moved { from, to } => {
+					let mut changed = 0;
+					for record in self.records_below(from)? {
+						if let Ok(rest) = record.file_path.strip_prefix(from) {
+							let moved = if rest.as_os_str().is_empty() { to.clone() } else { to.join(rest) };
+							changed += self.set_known_path(&record, &moved)?;
+						}
+					}
+					// The record may not have been known by its old path, and the new one may match a rule
+					Ok(changed.max(self.file_appeared(to)?))
+				},
+				WatchEvent::Appeared(path) | WatchEvent::Written(path) => self.file_appeared(path),
+				WatchEvent::Removed(path) => {
+					let mut changed = 0;
+					for record in self.records_below(path)? {
+						if !is_present(&record) {
+							changed += self.mark_missing(&record)?;
+						}
+					}
+					Ok(changed)
+				},
+				WatchEvent::Rescan => {
+					let report = self.reconcile(roots)?;
+					for record in &report.missing {
+						self.mark_missing(record)?;
+					}
+					Ok(report.moved.len())
+				},
+			}
+		})
+	}
+
+	// A file which turned up at path may be one with a record, moved in from elsewhere,
+	// or a new file matching a rule
+	fn file_appeared(&self, path: &Path) -> Result<usize, FileChestError> {
+		let file_ref = match FileRef::from_pathbuf(&path.to_path_buf()) {
+			Ok(file_ref) => file_ref,
+			Err(_) => return Ok(0),
+		};
+		let status = self.record_status(&file_ref)?;
+		if status == RecordStatus::Stale {
+			return Ok(0);
+		}
+
+		let mut changed = 0;
+		if status == RecordStatus::Current {
+			self.refresh_fingerprint(&file_ref)?;
+			self.refresh_metadata_if_present(&file_ref)?;
+			changed = 1;
+		}
+		match self.apply_rules(&file_ref) {
+			Ok(matched) if matched > 0 => Ok(1),
+			// The file may already be gone again
+			Ok(_) | Err(FileChestError::Io(_)) => Ok(changed),
+			Err(e) => Err(e),
+		}
+	}
+
+	// Start the grace period of a record whose file is gone, unless it already started
+	fn mark_missing(&self, record: &FileRef) -> Result<usize, FileChestError> {
+		Ok(self.conn.execute(
+			"UPDATE file_notes SET missing_since=?3 WHERE device=?1 AND inode=?2 AND missing_since IS NULL",
+			(record.device, record.inode, now())
+		)?)
+	}
+
+	fn set_known_path(&self, record: &FileRef, path: &Path) -> Result<usize, FileChestError> {
+		Ok(self.conn.execute(
+			"UPDATE file_notes SET known_path=?3, missing_since=NULL WHERE device=?1 AND inode=?2",
+			(record.device, record.inode, path_bytes(path)?)
+		)?)
+	}
+
+	// Records known to be at path or anywhere below it
+	fn records_below(&self, path: &Path) -> Result<Vec<FileRef>, FileChestError> {
+		let exact = path.as_os_str().as_bytes();
+		let mut prefix = exact.to_vec();
+		prefix.push(b'/');
+
+		let mut stmt = self.conn.prepare(&format!(
+			"SELECT {FILE_REF_COLUMNS} FROM file_notes
+			WHERE known_path = ?1 OR substr(known_path, 1, ?3) = ?2"
+		))?;
+		let records = stmt.query_map((exact, &prefix, prefix.len()), file_ref_from_row)?;
+
+		Ok(records.collect::<Result<_, _>>()?)
+	}
+}
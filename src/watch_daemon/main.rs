@@ -0,0 +1,99 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+/* Background daemon keeping known paths current while files are moved and deleted.
+ *
+ *     file_chest_watch [--db <path>]               watch every configured directory
+ *     file_chest_watch [--db <path>] add <dir>     add a directory to watch
+ *     file_chest_watch [--db <path>] remove <dir>  stop watching a directory
+ *     file_chest_watch [--db <path>] list          list the watched directories
+ *     file_chest_watch [--db <path>] apply <dir>   apply the tagging rules to the files below a directory
+ *
+ * Tagging rules are read from rules.toml in the configuration directory, see rules.rs.
+ */
+
+use file_chest::{FileChestError, NotesDB};
+use file_chest::index::IndexOptions;
+use file_chest::rules::RuleSet;
+
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::atomic::AtomicBool;
+
+fn run(db_path: Option<PathBuf>, command: &[OsString]) -> Result<(), FileChestError> {
+	let mut db = match db_path {
+		Some(path) => NotesDB::open(path),
+		None => NotesDB::build(),
+	}?;
+	db.set_warning_handler(|warning| eprintln!("Warning: {warning}"));
+	db.set_rules(RuleSet::load_default()?);
+
+	match command {
+		[] => {
+			let roots = db.watch_roots()?;
+			if roots.is_empty() {
+				return Err(FileChestError::InvalidInput("No directories to watch, add one with: file_chest_watch add <dir>".to_string()));
+			}
+			db.watch(&roots, &AtomicBool::new(false))
+		},
+		[cmd, dir] if cmd == "add" => db.add_watch_root(std::fs::canonicalize(dir)?),
+		[cmd, dir] if cmd == "remove" => db.remove_watch_root(std::fs::canonicalize(dir).unwrap_or_else(|_| PathBuf::from(dir))),
+		[cmd, dir] if cmd == "apply" => {
+			if db.rules().is_empty() {
+				return Err(FileChestError::InvalidInput(format!(
+					"No tagging rules, add some to {}", file_chest::rules::default_rules_path().unwrap_or_default().display()
+				)));
+			}
+			let report = db.apply_rules_below(std::fs::canonicalize(dir)?, &IndexOptions::default())?;
+			println!("{} of {} files matched a rule", report.matched_rules, report.checked);
+			Ok(())
+		},
+		[cmd] if cmd == "list" => {
+			for root in db.watch_roots()? {
+				println!("{}", root.display());
+			}
+			Ok(())
+		},
+		_ => Err(FileChestError::InvalidInput("Usage: file_chest_watch [--db <path>] [add <dir> | remove <dir> | list | apply <dir>]".to_string())),
+	}
+}
+
+fn main() -> ExitCode {
+	let mut db_path = None;
+	let mut command = Vec::new();
+	// Arguments are not required to be UTF-8, as paths need not be
+	let mut args = std::env::args_os().skip(1);
+	while let Some(arg) = args.next() {
+		if arg == "--db" {
+			db_path = args.next().map(PathBuf::from);
+		} else if let Some(path) = arg.as_bytes().strip_prefix(b"--db=") {
+			db_path = Some(PathBuf::from(OsStr::from_bytes(path)));
+		} else {
+			command.push(arg);
+		}
+	}
+
+	match run(db_path, &command) {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(e) => {
+			eprintln!("{e}");
+			ExitCode::FAILURE
+		},
+	}
+}
@@ -0,0 +1,125 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+/* Mirroring tags and notes into extended attributes
+ *
+ * Uses the freedesktop names: user.xdg.tags holds a comma separated list of tags and
+ * user.xdg.comment holds the note. With sync enabled they are rewritten whenever a file's note or
+ * tags change, including through renaming, merging or deleting tags, so they survive copies
+ * with `cp --preserve=xattr` and can be read by other software such as getfattr. The database
+ * is the source of truth: it is updated even if writing the attributes fails, which is only
+ * passed to the warning handler.
+ */
+
+use crate::export::{ImportMode, ImportReport};
+use crate::{walk_dir, FileChestError, FileRef, NotesDB};
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+pub const TAGS_XATTR: &str = "user.xdg.tags";
+pub const COMMENT_XATTR: &str = "user.xdg.comment";
+
+// Set an attribute, removing it when the value is empty
+fn write_xattr(path: &Path, name: &str, value: &str) -> std::io::Result<()> {
+	if !value.is_empty() {
+		xattr::set(path, name, value.as_bytes())
+	} else if xattr::get(path, name)?.is_some() {
+		xattr::remove(path, name)
+	} else {
+		Ok(())
+	}
+}
+
+fn read_xattr(path: &Path, name: &str) -> std::io::Result<Option<String>> {
+	Ok(xattr::get(path, name)?.map(|value| String::from_utf8_lossy(&value).to_string()))
+}
+
+// Split a user.xdg.tags value into tags
+pub fn parse_tags_xattr(value: &str) -> Vec<&str> {
+	value.split(',').map(|tag| tag.trim()).filter(|tag| !tag.is_empty()).collect()
+}
+
+// Files which can not carry user attributes: symbolic links, and files which are gone
+fn can_hold_xattrs(path: &Path) -> bool {
+	fs::symlink_metadata(path).is_ok_and(|m| !m.file_type().is_symlink())
+}
+
+impl NotesDB {
+	// Write tags and notes to extended attributes as well as the database
+	pub fn set_xattr_sync(&mut self, enabled: bool) {
+		self.xattr_sync = enabled;
+	}
+
+	pub fn xattr_sync(&self) -> bool {
+		self.xattr_sync
+	}
+
+	// Called after the database has been updated. Filesystems without extended attributes are skipped.
+	pub(crate) fn sync_xattr(&self, file_ref: &FileRef, name: &str, value: &str) {
+		if !self.xattr_sync || !can_hold_xattrs(&file_ref.file_path) {
+			return;
+		}
+
+		match write_xattr(&file_ref.file_path, name, value) {
+			Err(e) if e.kind() != ErrorKind::Unsupported => {
+				self.warn(&format!("Could not write {name} to {}: {e}", file_ref.file_path.display()));
+			},
+			_ => (),
+		}
+	}
+
+	// Read user.xdg.tags and user.xdg.comment from every file below root into the database.
+	// Tags are added to the stored ones. A comment which differs from a stored note is reported,
+	// and replaces it only in Overwrite mode.
+	pub fn import_xattrs<P: AsRef<Path>>(&self, root: P, mode: ImportMode) -> Result<ImportReport, FileChestError> {
+		let mut found = Vec::new();
+		walk_dir(root.as_ref(), &mut |path, m| {
+			if m.file_type().is_symlink() {
+				return;
+			}
+			let tags = read_xattr(path, TAGS_XATTR).ok().flatten();
+			let comment = read_xattr(path, COMMENT_XATTR).ok().flatten();
+			if tags.is_some() || comment.is_some() {
+				found.push((path.to_path_buf(), tags, comment));
+			}
+		});
+
+		self.atomic(|| {
+			let mut report = ImportReport::default();
+			for (path, tags, comment) in found {
+				// Skip files which disappeared since the walk
+				let file_ref = match FileRef::from_pathbuf(&path) {
+					Ok(file_ref) => file_ref,
+					Err(_) => continue,
+				};
+				if !self.begin_import(&file_ref, mode, &mut report)? {
+					continue;
+				}
+
+				for tag in parse_tags_xattr(tags.as_deref().unwrap_or("")) {
+					self.add_tag(&file_ref, tag)?;
+				}
+				if let Some(comment) = comment.filter(|c| !c.is_empty()) {
+					self.import_note(&file_ref, &comment, mode, &mut report)?;
+				}
+			}
+			Ok(report)
+		})
+	}
+}
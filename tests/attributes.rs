@@ -0,0 +1,84 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+use file_chest::{FileChestError, FileRef, NotesDB};
+use file_chest::attributes::{is_valid_date, AttrValue};
+
+use std::path::PathBuf;
+
+fn file_ref(inode: u64, path: &str) -> FileRef {
+	FileRef { file_path: PathBuf::from(path), device: 7, inode, fingerprint: None }
+}
+
+#[test]
+fn values_are_typed() {
+	assert_eq!(AttrValue::parse(" 42 "), AttrValue::Integer(42));
+	assert_eq!(AttrValue::parse("4.5"), AttrValue::Real(4.5));
+	assert_eq!(AttrValue::parse("2024-02-29"), AttrValue::Date("2024-02-29".to_string()));
+	assert_eq!(AttrValue::parse("2023-02-29"), AttrValue::Text("2023-02-29".to_string()));
+	assert_eq!(AttrValue::parse("inf"), AttrValue::Text("inf".to_string()));
+	assert_eq!(AttrValue::parse("Lisbon"), AttrValue::Text("Lisbon".to_string()));
+
+	assert!(is_valid_date("2000-02-29"));
+	assert!(!is_valid_date("1900-02-29"));
+	assert!(!is_valid_date("2026-13-01"));
+	assert!(!is_valid_date("2026-1-01"));
+}
+
+#[test]
+fn set_get_and_delete() {
+	let db = NotesDB::open_in_memory().unwrap();
+	let file = file_ref(1, "/invoice.pdf");
+
+	db.set_attribute(&file, "rating", &AttrValue::Integer(4)).unwrap();
+	db.set_attribute(&file, " due ", &AttrValue::Date("2026-11-01".to_string())).unwrap();
+	db.set_attribute(&file, "amount", &AttrValue::Real(129.5)).unwrap();
+	db.set_attribute(&file, "client", &AttrValue::Text("ACME".to_string())).unwrap();
+
+	// Kinds survive the round trip, and attributes are listed by name
+	assert_eq!(db.get_attributes(&file).unwrap(), vec![
+		("amount".to_string(), AttrValue::Real(129.5)),
+		("client".to_string(), AttrValue::Text("ACME".to_string())),
+		("due".to_string(), AttrValue::Date("2026-11-01".to_string())),
+		("rating".to_string(), AttrValue::Integer(4)),
+	]);
+
+	// Setting an attribute again replaces its value and kind
+	db.set_attribute(&file, "rating", &AttrValue::Text("good".to_string())).unwrap();
+	assert_eq!(db.get_attribute(&file, "rating").unwrap(), Some(AttrValue::Text("good".to_string())));
+
+	db.delete_attribute(&file, "rating").unwrap();
+	assert_eq!(db.get_attribute(&file, "rating").unwrap(), None);
+	assert_eq!(db.get_attributes(&file).unwrap().len(), 3);
+
+	// Attributes belong to one file only
+	assert!(db.get_attributes(&file_ref(2, "/other.pdf")).unwrap().is_empty());
+}
+
+#[test]
+fn invalid_attributes() {
+	let db = NotesDB::open_in_memory().unwrap();
+	let file = file_ref(1, "/invoice.pdf");
+
+	assert!(matches!(db.set_attribute(&file, "  ", &AttrValue::Integer(1)), Err(FileChestError::InvalidInput(_))));
+	assert!(matches!(
+		db.set_attribute(&file, "due", &AttrValue::Date("tomorrow".to_string())),
+		Err(FileChestError::InvalidInput(_))
+	));
+	// Nothing was stored for the rejected attributes, not even the file
+	assert!(db.get_all_files().unwrap().is_empty());
+}
@@ -0,0 +1,97 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+use file_chest::{FileChestError, FileRef, NotesDB};
+
+use std::path::PathBuf;
+
+fn file_ref(inode: u64) -> FileRef {
+	FileRef { file_path: PathBuf::from(format!("/file{inode}")), device: 7, inode, fingerprint: None }
+}
+
+fn tag_names(db: &NotesDB) -> Vec<String> {
+	db.list_tags().unwrap().into_iter().map(|t| t.name).collect()
+}
+
+fn failure() -> FileChestError {
+	FileChestError::InvalidInput("stop".to_string())
+}
+
+#[test]
+fn failed_batch_keeps_nothing() {
+	let db = NotesDB::open_in_memory().unwrap();
+	db.set_note(&file_ref(1), "kept").unwrap();
+
+	let result: Result<(), _> = db.batch(|db| {
+		db.set_note(&file_ref(1), "changed")?;
+		db.set_tags(&file_ref(1), vec!["work/reports", "urgent"])?;
+		for inode in 2..10 {
+			db.add_tag(&file_ref(inode), "bulk")?;
+		}
+		Err(failure())
+	});
+	assert!(matches!(result, Err(FileChestError::InvalidInput(_))));
+
+	assert_eq!(db.get_note_no_update(&file_ref(1)).unwrap(), "kept");
+	assert!(db.get_tags(&file_ref(1)).unwrap().is_empty());
+	assert!(db.get_note_no_update(&file_ref(2)).is_err());
+	assert!(tag_names(&db).is_empty());
+}
+
+#[test]
+fn successful_batch_keeps_everything() {
+	let db = NotesDB::open_in_memory().unwrap();
+	let count = db.batch(|db| {
+		for inode in 1..=3 {
+			db.set_tags(&file_ref(inode), vec!["a", "b"])?;
+		}
+		Ok(3)
+	}).unwrap();
+
+	assert_eq!(count, 3);
+	assert_eq!(db.get_files_by_tag("b").unwrap().len(), 3);
+}
+
+#[test]
+fn nested_batches() {
+	let db = NotesDB::open_in_memory().unwrap();
+
+	db.batch(|db| {
+		db.add_tag(&file_ref(1), "outer")?;
+
+		// An inner failure only undoes the inner batch once the error is handled
+		let inner: Result<(), _> = db.batch(|db| {
+			db.add_tag(&file_ref(2), "inner")?;
+			Err(failure())
+		});
+		assert!(inner.is_err());
+
+		db.batch(|db| db.add_tag(&file_ref(3), "second"))?;
+		Ok(())
+	}).unwrap();
+
+	assert_eq!(tag_names(&db), vec!["outer", "second"]);
+	assert!(db.get_note_no_update(&file_ref(2)).is_err());
+
+	// An outer failure undoes inner batches which succeeded
+	let outer: Result<(), _> = db.batch(|db| {
+		db.batch(|db| db.add_tag(&file_ref(4), "lost"))?;
+		Err(failure())
+	});
+	assert!(outer.is_err());
+	assert_eq!(tag_names(&db), vec!["outer", "second"]);
+}
@@ -0,0 +1,83 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+// Fixtures shared by the integration tests. Each test file uses a different part of them.
+#![allow(dead_code)]
+
+use file_chest::{FileRef, NotesDB};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// A directory for a test holding the given files, deleted once the test is done with it.
+// Parent directories of the files are created as needed.
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+	pub fn new(name: &str, files: &[(&str, &str)]) -> Self {
+		Self::in_dir(&std::env::temp_dir(), name, files)
+	}
+
+	// Made below base instead of the system's temporary directory
+	pub fn in_dir(base: &Path, name: &str, files: &[(&str, &str)]) -> Self {
+		let dir = base.join(format!("filechest-{}-{name}", std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+		let dir = TempDir(dir);
+		for (path, contents) in files {
+			dir.write(path, contents.as_bytes());
+		}
+		dir
+	}
+
+	// Made in the working directory, and known by a relative path
+	pub fn relative(name: &str, files: &[(&str, &str)]) -> Self {
+		Self::in_dir(Path::new(""), name, files)
+	}
+
+	pub fn write(&self, path: &str, contents: &[u8]) -> PathBuf {
+		let path = self.0.join(path);
+		fs::create_dir_all(path.parent().unwrap()).unwrap();
+		fs::write(&path, contents).unwrap();
+		path
+	}
+
+	pub fn file_ref(&self, path: &str) -> FileRef {
+		FileRef::from_pathbuf(&self.0.join(path)).unwrap()
+	}
+
+	// A new file holding its own name
+	pub fn file(&self, name: &str) -> FileRef {
+		let path = self.write(name, name.as_bytes());
+		FileRef::from_pathbuf(&path).unwrap()
+	}
+}
+
+impl Drop for TempDir {
+	fn drop(&mut self) {
+		let _ = fs::remove_dir_all(&self.0);
+	}
+}
+
+// Keep the warnings db passes to its handler, to be checked by the test
+pub fn collect_warnings(db: &mut NotesDB) -> Arc<Mutex<Vec<String>>> {
+	let warnings = Arc::new(Mutex::new(Vec::new()));
+	let collected = warnings.clone();
+	db.set_warning_handler(move |warning| collected.lock().unwrap().push(warning.to_string()));
+	warnings
+}
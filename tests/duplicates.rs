@@ -0,0 +1,161 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+
+mod common;
+use common::TempDir;
+
+use file_chest::{FileChestError, FileRef, NotesDB};
+use file_chest::duplicates::find_hard_links;
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+#[test]
+fn groups_identical_files() {
+	let dir = TempDir::new("groups", &[
+		("photos/a.jpg", "same picture"),
+		("backup/a copy.jpg", "same picture"),
+		("other.jpg", "same size!!!"),
+		("empty1", ""),
+		("empty2", ""),
+	]);
+	fs::hard_link(dir.0.join("photos/a.jpg"), dir.0.join("photos/link.jpg")).unwrap();
+
+	let db = NotesDB::open_in_memory().unwrap();
+	let groups = db.find_duplicates(&[&dir.0]).unwrap();
+	assert_eq!(groups.len(), 1);
+	assert_eq!(groups[0].size, 12);
+	assert_eq!(groups[0].files.len(), 2);
+	assert!(groups[0].files.iter().any(|f| f.file_path == dir.0.join("backup/a copy.jpg")));
+	assert_eq!(groups[0].hash, blake3_hex("same picture"));
+}
+
+#[test]
+fn hash_is_cached_until_file_changes() {
+	let dir = TempDir::new("cache", &[("a.txt", "first")]);
+	let path = dir.0.join("a.txt");
+	let db = NotesDB::open_in_memory().unwrap();
+
+	let first = db.content_hash(&FileRef::from_pathbuf(&path).unwrap()).unwrap();
+	assert_eq!(first, blake3_hex("first"));
+
+	// Same size and mtime, so the cached hash is still used
+	let mtime = fs::metadata(&path).unwrap().modified().unwrap();
+	write_keeping_mtime(&path, "fir5t", mtime);
+	assert_eq!(db.content_hash(&FileRef::from_pathbuf(&path).unwrap()).unwrap(), first);
+
+	fs::write(&path, "second").unwrap();
+	assert_eq!(db.content_hash(&FileRef::from_pathbuf(&path).unwrap()).unwrap(), blake3_hex("second"));
+}
+
+#[test]
+fn hash_follows_the_file_not_the_file_ref() {
+	let dir = TempDir::new("fresh", &[("a.txt", "first")]);
+	let path = dir.0.join("a.txt");
+	let db = NotesDB::open_in_memory().unwrap();
+
+	let file = FileRef::from_pathbuf(&path).unwrap();
+	assert_eq!(db.content_hash(&file).unwrap(), blake3_hex("first"));
+
+	// The FileRef still describes the old contents, but the file is looked at again
+	fs::write(&path, "second").unwrap();
+	assert_eq!(db.content_hash(&file).unwrap(), blake3_hex("second"));
+
+	// A different file at the path is not hashed in its place
+	fs::write(dir.0.join("b.txt"), "third").unwrap();
+	fs::rename(dir.0.join("b.txt"), &path).unwrap();
+	assert!(matches!(db.content_hash(&file), Err(FileChestError::NotFound(_))));
+}
+
+#[test]
+fn copy_and_merge_metadata() {
+	let dir = TempDir::new("merge", &[("a.pdf", "invoice"), ("b.pdf", "invoice"), ("c.pdf", "invoice"), ("other.pdf", "receipt")]);
+	let [a, b, c, other] = ["a.pdf", "b.pdf", "c.pdf", "other.pdf"].map(|name| FileRef::from_pathbuf(&dir.0.join(name)).unwrap());
+
+	let db = NotesDB::open_in_memory().unwrap();
+	db.set_note(&a, "Paid in March").unwrap();
+	db.set_tags(&a, vec!["invoice"]).unwrap();
+	db.set_tags(&b, vec!["2023"]).unwrap();
+
+	assert_eq!(db.copy_metadata(&a, std::slice::from_ref(&b)).unwrap(), 1);
+	assert_eq!(db.get_note(&b).unwrap(), "Paid in March");
+	assert_eq!(sorted(db.get_tags(&b).unwrap()), vec!["2023", "invoice"]);
+
+	db.set_note(&c, "Scanned copy").unwrap();
+	assert_eq!(db.merge_metadata(&[a.clone(), b.clone(), c.clone()]).unwrap(), 3);
+	for file in [&a, &b, &c] {
+		assert_eq!(db.get_note(file).unwrap(), "Paid in March\n\nScanned copy");
+		assert_eq!(sorted(db.get_tags(file).unwrap()), vec!["2023", "invoice"]);
+	}
+	assert_eq!(db.merge_metadata(&[a.clone(), b.clone(), c.clone()]).unwrap(), 0);
+
+	// Different content is refused
+	assert!(matches!(db.copy_metadata(&a, std::slice::from_ref(&other)), Err(FileChestError::InvalidInput(_))));
+	assert!(db.get_tags(&other).unwrap().is_empty());
+}
+
+#[test]
+fn hard_links_are_found() {
+	let dir = TempDir::new("links", &[("a.txt", "linked"), ("sub/other.txt", "linked")]);
+	fs::hard_link(dir.0.join("a.txt"), dir.0.join("sub/b.txt")).unwrap();
+
+	let a = FileRef::from_pathbuf(&dir.0.join("a.txt")).unwrap();
+	assert_eq!(a.link_count().unwrap(), 2);
+	assert_eq!(find_hard_links(&a, &[&dir.0]).unwrap(), vec![dir.0.join("a.txt"), dir.0.join("sub/b.txt")]);
+
+	// A directory's link count comes from its subdirectories, not from other paths to it
+	fs::create_dir_all(dir.0.join("sub/deeper")).unwrap();
+	let sub = FileRef::from_pathbuf(&dir.0.join("sub")).unwrap();
+	assert_eq!(sub.link_count().unwrap(), 1);
+	assert!(find_hard_links(&sub, &[&dir.0]).unwrap().is_empty());
+}
+
+#[test]
+fn relative_roots() {
+	let dir = TempDir::relative("duplicates-relative", &[("a.txt", "copy"), ("b.txt", "copy")]);
+	fs::hard_link(dir.0.join("a.txt"), dir.0.join("link.txt")).unwrap();
+	let absolute = std::env::current_dir().unwrap().join(&dir.0);
+
+	let db = NotesDB::open_in_memory().unwrap();
+	let groups = db.find_duplicates(&[&dir.0]).unwrap();
+	assert_eq!(groups.len(), 1);
+	let files = &groups[0].files;
+	assert!(files.iter().all(|f| f.file_path.starts_with(&absolute)));
+
+	db.add_tag(&files[0], "copy").unwrap();
+	assert_eq!(db.merge_metadata(files).unwrap(), 1);
+	assert_eq!(db.get_tags(&files[1]).unwrap(), vec!["copy"]);
+
+	let a = FileRef::from_pathbuf(&dir.0.join("a.txt")).unwrap();
+	assert_eq!(find_hard_links(&a, &[&dir.0]).unwrap(), vec![absolute.join("a.txt"), absolute.join("link.txt")]);
+}
+
+fn sorted(mut tags: Vec<String>) -> Vec<String> {
+	tags.sort();
+	tags
+}
+
+fn write_keeping_mtime(path: &PathBuf, contents: &str, mtime: SystemTime) {
+	fs::write(path, contents).unwrap();
+	fs::File::options().write(true).open(path).unwrap().set_modified(mtime).unwrap();
+}
+
+fn blake3_hex(contents: &str) -> String {
+	blake3::hash(contents.as_bytes()).to_hex().to_string()
+}
@@ -0,0 +1,90 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+use file_chest::{FileChestError, FileRef, Fingerprint, NotesDB};
+use file_chest::attributes::AttrValue;
+
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+fn file_ref(path: &str, fingerprint: Option<Fingerprint>) -> FileRef {
+	FileRef { file_path: PathBuf::from(path), device: 7, inode: 43, fingerprint }
+}
+
+#[test]
+fn non_utf8_path_round_trips() {
+	let db = NotesDB::open_in_memory().unwrap();
+	let path = PathBuf::from(OsStr::from_bytes(b"/archive/caf\xe9.txt"));
+	db.set_note(&FileRef { file_path: path.clone(), device: 7, inode: 43, fingerprint: None }, "latin-1 name").unwrap();
+
+	let files = db.get_all_files().unwrap();
+	assert_eq!(files[0].file_path, path);
+}
+
+#[test]
+fn relative_paths_are_refused() {
+	let db = NotesDB::open_in_memory().unwrap();
+	let relative = file_ref("notes/todo.txt", None);
+
+	assert!(matches!(db.set_note(&relative, "lost"), Err(FileChestError::InvalidPath(p)) if p == relative.file_path));
+	assert!(matches!(db.add_tag(&relative, "todo"), Err(FileChestError::InvalidPath(_))));
+	assert!(db.get_all_files().unwrap().is_empty());
+
+	// Paths looked up on disk are made absolute
+	let file = FileRef::from_pathbuf(&PathBuf::from("Cargo.toml")).unwrap();
+	assert_eq!(file.file_path, std::env::current_dir().unwrap().join("Cargo.toml"));
+}
+
+#[test]
+fn stale_records() {
+	let db = NotesDB::open_in_memory().unwrap();
+	let fingerprint = |birth_time| Some(Fingerprint { birth_time: Some(birth_time), size: 1, mtime: 1, ctime: Some(1) });
+	db.set_note(&file_ref("/old.txt", fingerprint(1)), "old note").unwrap();
+
+	let reused = file_ref("/new.txt", fingerprint(2));
+	assert!(matches!(db.get_note(&reused), Err(FileChestError::StaleRecord(p)) if p == reused.file_path));
+	assert!(matches!(db.set_note(&reused, "new note"), Err(FileChestError::StaleRecord(_))));
+	assert!(matches!(db.set_tags(&reused, vec!["new"]), Err(FileChestError::StaleRecord(_))));
+	assert!(matches!(db.get_tags(&reused), Err(FileChestError::StaleRecord(_))));
+}
+
+#[test]
+fn missing_things() {
+	let db = NotesDB::open_in_memory().unwrap();
+	let file = file_ref("/a.txt", None);
+
+	assert!(matches!(db.get_note_no_update(&file), Err(FileChestError::NotFound(_))));
+	assert!(matches!(db.rename_tag("nothing", "something"), Err(FileChestError::NotFound(_))));
+	assert!(matches!(db.restore_note_revision(&file, 99), Err(FileChestError::NotFound(_))));
+}
+
+#[test]
+fn invalid_input() {
+	let db = NotesDB::open_in_memory().unwrap();
+	let file = file_ref("/a.txt", None);
+	db.add_tag(&file, "proj").unwrap();
+	db.add_tag(&file, "project").unwrap();
+
+	assert!(matches!(db.rename_tag("proj", "project"), Err(FileChestError::InvalidInput(_))));
+	assert!(matches!(db.rename_tag("proj", "proj/sub"), Err(FileChestError::InvalidInput(_))));
+	assert!(matches!(db.set_attribute(&file, "", &AttrValue::Text("x".into())), Err(FileChestError::InvalidInput(_))));
+
+	let error = db.query("tag:\"open").unwrap_err();
+	assert!(matches!(error, FileChestError::Query(_)));
+	assert_eq!(error.to_string(), "Invalid query: Unterminated quote in \"tag:open\"");
+}
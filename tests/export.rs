@@ -0,0 +1,84 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+use file_chest::{FileRef, NotesDB};
+use file_chest::attributes::AttrValue;
+use file_chest::export::ImportMode;
+
+use std::path::PathBuf;
+
+// Paths which do not exist, so imports keep the exported device and inode
+fn file_ref(inode: u64, path: &str) -> FileRef {
+	FileRef { file_path: PathBuf::from(path), device: 7, inode, fingerprint: None }
+}
+
+fn sample_export() -> Vec<u8> {
+	let db = NotesDB::open_in_memory().unwrap();
+	let budget = file_ref(1001, "/nonexistent/budget.ods");
+	db.set_note(&budget, "Q3 budget draft").unwrap();
+	db.set_tags(&budget, vec!["finance", "project/alpha"]).unwrap();
+	db.set_attribute(&budget, "due", &AttrValue::Date("2023-02-01".to_string())).unwrap();
+	db.add_tag(&file_ref(1002, "/nonexistent/invoice.pdf"), "invoice").unwrap();
+
+	let mut json = Vec::new();
+	db.export_json(&mut json).unwrap();
+	json
+}
+
+#[test]
+fn round_trip() {
+	let json = sample_export();
+
+	let db = NotesDB::open_in_memory().unwrap();
+	let report = db.import_json(&json[..], ImportMode::Merge).unwrap();
+	assert_eq!((report.added, report.merged), (2, 0));
+	assert!(report.conflicts.is_empty());
+
+	let mut again = Vec::new();
+	db.export_json(&mut again).unwrap();
+	assert_eq!(json, again);
+}
+
+#[test]
+fn merge_reports_conflicts() {
+	let json = sample_export();
+	let budget = file_ref(1001, "/nonexistent/budget.ods");
+
+	let db = NotesDB::open_in_memory().unwrap();
+	db.set_note(&budget, "Q4 budget").unwrap();
+	db.add_tag(&budget, "draft").unwrap();
+
+	let report = db.import_json(&json[..], ImportMode::Merge).unwrap();
+	assert_eq!((report.added, report.merged), (1, 1));
+	assert_eq!(report.conflicts.len(), 1);
+	assert_eq!(report.conflicts[0].field, "note");
+	assert_eq!(db.get_note(&budget).unwrap(), "Q4 budget");
+
+	let mut tags = db.get_tags(&budget).unwrap();
+	tags.sort();
+	assert_eq!(tags, vec!["draft", "finance", "project/alpha"]);
+
+	db.import_json(&json[..], ImportMode::Overwrite).unwrap();
+	assert_eq!(db.get_note(&budget).unwrap(), "Q3 budget draft");
+}
+
+#[test]
+fn refuse_newer_format() {
+	let db = NotesDB::open_in_memory().unwrap();
+	let json = br#"{"format": "filechest", "version": 99, "files": []}"#;
+	assert!(db.import_json(&json[..], ImportMode::Merge).is_err());
+}
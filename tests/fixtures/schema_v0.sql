@@ -0,0 +1,33 @@
+-- Schema written by File Chest 0.0.3, before PRAGMA user_version was tracked
+CREATE TABLE file_notes (
+	inode INTEGER PRIMARY KEY,
+	known_path TEXT,
+	note TEXT
+);
+
+CREATE TABLE file_tags (
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	tag_name VARCHAR(255) UNIQUE
+);
+
+CREATE TABLE tag_relations (
+	tag_id INTEGER NOT NULL,
+	file_id INTEGER NOT NULL,
+
+	FOREIGN KEY(tag_id) REFERENCES file_tags(id),
+	FOREIGN KEY(file_id) REFERENCES file_notes(inode),
+
+	CONSTRAINT uc_tfid UNIQUE (tag_id, file_id)
+);
+
+INSERT INTO file_notes(inode, known_path, note) VALUES(1001, '/home/user/budget.ods', 'Q3 budget draft');
+INSERT INTO file_notes(inode, known_path, note) VALUES(1002, '/home/user/invoice.pdf', NULL);
+
+INSERT INTO file_tags(tag_name) VALUES('finance');
+INSERT INTO file_tags(tag_name) VALUES('invoice');
+INSERT INTO file_tags(tag_name) VALUES('archive/2023');
+
+INSERT INTO tag_relations(tag_id, file_id) VALUES(1, 1001);
+INSERT INTO tag_relations(tag_id, file_id) VALUES(1, 1002);
+INSERT INTO tag_relations(tag_id, file_id) VALUES(2, 1002);
+INSERT INTO tag_relations(tag_id, file_id) VALUES(3, 1002);
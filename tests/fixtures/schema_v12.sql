@@ -0,0 +1,120 @@
+-- Schema version 12: watched directories, no content hashes
+PRAGMA user_version = 12;
+
+CREATE TABLE file_notes (
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	device INTEGER NOT NULL,
+	inode INTEGER NOT NULL,
+	known_path TEXT,
+	note TEXT,
+	birth_time INTEGER,
+	size INTEGER,
+	mtime INTEGER,
+	missing_since INTEGER,
+	changed_at INTEGER,
+	file_type TEXT,
+
+	CONSTRAINT uc_devino UNIQUE (device, inode)
+);
+
+CREATE TABLE file_tags (
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	tag_name VARCHAR(255) UNIQUE,
+	parent_id INTEGER REFERENCES file_tags(id)
+);
+
+CREATE TABLE file_attributes (
+	file_id INTEGER NOT NULL,
+	name TEXT NOT NULL,
+	kind TEXT NOT NULL,
+	value,
+
+	FOREIGN KEY(file_id) REFERENCES file_notes(id),
+
+	CONSTRAINT uc_fileattr UNIQUE (file_id, name)
+);
+
+CREATE VIRTUAL TABLE notes_fts USING fts5(note, content='file_notes', content_rowid='id');
+
+CREATE TRIGGER file_notes_fts_insert AFTER INSERT ON file_notes BEGIN
+	INSERT INTO notes_fts(rowid, note) VALUES (new.id, new.note);
+END;
+
+CREATE TRIGGER file_notes_fts_delete AFTER DELETE ON file_notes BEGIN
+	INSERT INTO notes_fts(notes_fts, rowid, note) VALUES ('delete', old.id, old.note);
+END;
+
+CREATE TRIGGER file_notes_fts_update AFTER UPDATE OF note ON file_notes BEGIN
+	INSERT INTO notes_fts(notes_fts, rowid, note) VALUES ('delete', old.id, old.note);
+	INSERT INTO notes_fts(rowid, note) VALUES (new.id, new.note);
+END;
+
+CREATE TABLE note_revisions (
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	file_id INTEGER NOT NULL,
+	note TEXT,
+	saved_at INTEGER NOT NULL,
+
+	FOREIGN KEY(file_id) REFERENCES file_notes(id)
+);
+
+CREATE TRIGGER file_notes_revision_insert AFTER INSERT ON file_notes
+WHEN new.note IS NOT NULL BEGIN
+	INSERT INTO note_revisions(file_id, note, saved_at)
+	VALUES (new.id, new.note, CAST(strftime('%s', 'now') AS INTEGER));
+END;
+
+CREATE TRIGGER file_notes_revision_update AFTER UPDATE OF note ON file_notes
+WHEN new.note IS NOT old.note BEGIN
+	INSERT INTO note_revisions(file_id, note, saved_at)
+	VALUES (new.id, new.note, CAST(strftime('%s', 'now') AS INTEGER));
+END;
+
+CREATE TABLE tag_relations (
+	tag_id INTEGER NOT NULL,
+	file_id INTEGER NOT NULL,
+
+	FOREIGN KEY(tag_id) REFERENCES file_tags(id),
+	FOREIGN KEY(file_id) REFERENCES file_notes(id),
+
+	CONSTRAINT uc_tfid UNIQUE (tag_id, file_id)
+);
+
+CREATE TRIGGER file_notes_changed_insert AFTER INSERT ON file_notes
+WHEN new.note IS NOT NULL BEGIN
+	UPDATE file_notes SET changed_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE id = new.id;
+END;
+
+CREATE TRIGGER file_notes_changed_update AFTER UPDATE OF note ON file_notes
+WHEN new.note IS NOT old.note BEGIN
+	UPDATE file_notes SET changed_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE id = new.id;
+END;
+
+CREATE TRIGGER tag_relations_changed_insert AFTER INSERT ON tag_relations BEGIN
+	UPDATE file_notes SET changed_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE id = new.file_id;
+END;
+
+CREATE TRIGGER tag_relations_changed_delete AFTER DELETE ON tag_relations BEGIN
+	UPDATE file_notes SET changed_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE id = old.file_id;
+END;
+
+CREATE TABLE watch_roots (path BLOB NOT NULL UNIQUE);
+
+INSERT INTO file_notes(device, inode, known_path, note) VALUES(0, 1001, CAST('/home/user/budget.ods' AS BLOB), 'Q3 budget draft');
+INSERT INTO file_notes(device, inode, known_path, note, birth_time, size, mtime)
+	VALUES(0, 1002, CAST('/home/user/invoice.pdf' AS BLOB), NULL, 1672531200000000000, 48213, 1672531200000000000);
+
+INSERT INTO file_tags(tag_name) VALUES('finance');
+INSERT INTO file_tags(tag_name) VALUES('invoice');
+INSERT INTO file_tags(tag_name) VALUES('archive/2023');
+INSERT INTO file_tags(tag_name) VALUES('archive');
+UPDATE file_tags SET parent_id=4 WHERE id=3;
+
+INSERT INTO tag_relations(tag_id, file_id) VALUES(1, 1);
+INSERT INTO tag_relations(tag_id, file_id) VALUES(1, 2);
+INSERT INTO tag_relations(tag_id, file_id) VALUES(2, 2);
+INSERT INTO tag_relations(tag_id, file_id) VALUES(3, 2);
+
+INSERT INTO file_attributes(file_id, name, kind, value) VALUES(2, 'due', 'date', '2023-02-01');
+
+INSERT INTO watch_roots(path) VALUES(CAST('/home/user' AS BLOB));
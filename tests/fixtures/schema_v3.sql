@@ -0,0 +1,43 @@
+-- Schema version 3: file fingerprints, flat tags
+PRAGMA user_version = 3;
+
+CREATE TABLE file_notes (
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	device INTEGER NOT NULL,
+	inode INTEGER NOT NULL,
+	known_path TEXT,
+	note TEXT,
+	birth_time INTEGER,
+	size INTEGER,
+	mtime INTEGER,
+
+	CONSTRAINT uc_devino UNIQUE (device, inode)
+);
+
+CREATE TABLE file_tags (
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	tag_name VARCHAR(255) UNIQUE
+);
+
+CREATE TABLE tag_relations (
+	tag_id INTEGER NOT NULL,
+	file_id INTEGER NOT NULL,
+
+	FOREIGN KEY(tag_id) REFERENCES file_tags(id),
+	FOREIGN KEY(file_id) REFERENCES file_notes(id),
+
+	CONSTRAINT uc_tfid UNIQUE (tag_id, file_id)
+);
+
+INSERT INTO file_notes(device, inode, known_path, note) VALUES(0, 1001, '/home/user/budget.ods', 'Q3 budget draft');
+INSERT INTO file_notes(device, inode, known_path, note, birth_time, size, mtime)
+	VALUES(0, 1002, '/home/user/invoice.pdf', NULL, 1672531200000000000, 48213, 1672531200000000000);
+
+INSERT INTO file_tags(tag_name) VALUES('finance');
+INSERT INTO file_tags(tag_name) VALUES('invoice');
+INSERT INTO file_tags(tag_name) VALUES('archive/2023');
+
+INSERT INTO tag_relations(tag_id, file_id) VALUES(1, 1);
+INSERT INTO tag_relations(tag_id, file_id) VALUES(1, 2);
+INSERT INTO tag_relations(tag_id, file_id) VALUES(2, 2);
+INSERT INTO tag_relations(tag_id, file_id) VALUES(3, 2);
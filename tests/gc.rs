@@ -0,0 +1,122 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+mod common;
+use common::TempDir;
+
+use file_chest::{FileRef, NotesDB};
+use file_chest::gc::GcOptions;
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn paths(files: &[FileRef]) -> Vec<&PathBuf> {
+	files.iter().map(|f| &f.file_path).collect()
+}
+
+fn preview(grace_period: Duration) -> GcOptions {
+	GcOptions { dry_run: true, grace_period }
+}
+
+#[test]
+fn deleted_after_grace_period() {
+	let dir = TempDir::new("grace", &[]);
+	let db = NotesDB::open_in_memory().unwrap();
+	let deleted = dir.file("deleted.txt");
+	let kept = dir.file("kept.txt");
+	db.set_note(&deleted, "gone soon").unwrap();
+	db.add_tag(&deleted, "old").unwrap();
+	db.add_tag(&kept, "current").unwrap();
+	fs::remove_file(&deleted.file_path).unwrap();
+
+	// The first look only starts the grace period, even without removing anything
+	let report = db.gc(&preview(Duration::from_secs(1))).unwrap();
+	assert_eq!(paths(&report.missing_files), vec![&deleted.file_path]);
+	assert!(report.is_empty());
+
+	std::thread::sleep(Duration::from_millis(1100));
+
+	let report = db.gc(&preview(Duration::from_secs(1))).unwrap();
+	assert_eq!(paths(&report.expired_files), vec![&deleted.file_path]);
+	assert_eq!(report.unused_tags, vec!["old"]);
+	assert!(!report.is_empty());
+	assert_eq!(db.get_all_files().unwrap().len(), 2);
+
+	let report = db.gc(&GcOptions { dry_run: false, grace_period: Duration::from_secs(1) }).unwrap();
+	assert_eq!(paths(&report.expired_files), vec![&deleted.file_path]);
+	assert_eq!(paths(&db.get_all_files().unwrap()), vec![&kept.file_path]);
+	assert_eq!(db.list_tags().unwrap().len(), 1);
+
+	// Nothing is left to remove
+	assert!(db.gc(&GcOptions::default()).unwrap().is_empty());
+}
+
+#[test]
+fn returning_file_restarts_grace_period() {
+	let dir = TempDir::new("return", &[]);
+	let db = NotesDB::open_in_memory().unwrap();
+	let file = dir.file("away.txt");
+	let elsewhere = dir.0.join("elsewhere.txt");
+	db.set_note(&file, "back soon").unwrap();
+
+	fs::rename(&file.file_path, &elsewhere).unwrap();
+	assert_eq!(db.gc(&preview(Duration::from_secs(1))).unwrap().missing_files.len(), 1);
+	fs::rename(&elsewhere, &file.file_path).unwrap();
+	assert!(db.gc(&preview(Duration::from_secs(1))).unwrap().missing_files.is_empty());
+
+	std::thread::sleep(Duration::from_millis(1100));
+
+	// Gone again, but counted from now rather than from the first time
+	fs::rename(&file.file_path, &elsewhere).unwrap();
+	let report = db.gc(&GcOptions { dry_run: false, grace_period: Duration::from_secs(1) }).unwrap();
+	assert!(report.expired_files.is_empty());
+	assert_eq!(report.missing_files.len(), 1);
+	assert_eq!(db.get_note_no_update(&file).unwrap(), "back soon");
+}
+
+#[test]
+fn only_what_was_previewed_is_removed() {
+	let dir = TempDir::new("previewed", &[]);
+	let db = NotesDB::open_in_memory().unwrap();
+	let (previewed, returns, later) = (dir.file("previewed.txt"), dir.file("returns.txt"), dir.file("later.txt"));
+	for (file, tag) in [(&previewed, "old"), (&returns, "back"), (&later, "later")] {
+		db.add_tag(file, tag).unwrap();
+	}
+	fs::remove_file(&previewed.file_path).unwrap();
+	fs::rename(&returns.file_path, dir.0.join("away.txt")).unwrap();
+
+	let preview = db.gc(&preview(Duration::ZERO)).unwrap();
+	assert_eq!(paths(&preview.expired_files), vec![&previewed.file_path, &returns.file_path]);
+	assert_eq!(preview.unused_tags, vec!["back", "old"]);
+
+	// While the preview is shown, one file comes back with a previewed tag, another goes, and a
+	// tag nobody uses is left behind
+	fs::rename(dir.0.join("away.txt"), &returns.file_path).unwrap();
+	fs::remove_file(&later.file_path).unwrap();
+	db.set_tags(&returns, vec!["old"]).unwrap();
+	db.add_tag(&later, "new").unwrap();
+	db.set_tags(&later, vec!["later"]).unwrap();
+
+	let report = db.gc_previewed(&preview).unwrap();
+	assert_eq!(paths(&report.expired_files), vec![&previewed.file_path]);
+	assert_eq!(report.unused_tags, vec!["back"]);
+	assert_eq!(paths(&db.get_all_files().unwrap()).len(), 2);
+	let mut tags: Vec<String> = db.list_tags().unwrap().into_iter().map(|t| t.name).collect();
+	tags.sort();
+	assert_eq!(tags, vec!["later", "new", "old"]);
+}
@@ -0,0 +1,79 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+use file_chest::{FileChestError, FileRef, NotesDB};
+use file_chest::history::{diff_lines, DiffLine};
+
+use std::path::PathBuf;
+
+fn file_ref(inode: u64, path: &str) -> FileRef {
+	FileRef { file_path: PathBuf::from(path), device: 7, inode, fingerprint: None }
+}
+
+fn notes(db: &NotesDB, file: &FileRef) -> Vec<String> {
+	db.note_history(file).unwrap().into_iter().map(|r| r.note).collect()
+}
+
+#[test]
+fn diff() {
+	use DiffLine::*;
+	let line = |s: &str| s.to_string();
+
+	assert_eq!(diff_lines("a\nb\nc", "a\nc\nd"), vec![Same(line("a")), Removed(line("b")), Same(line("c")), Added(line("d"))]);
+	assert_eq!(diff_lines("", "new"), vec![Added(line("new"))]);
+	assert_eq!(diff_lines("old", ""), vec![Removed(line("old"))]);
+	assert_eq!(diff_lines("same\n", "same"), vec![Same(line("same"))]);
+	assert_eq!(diff_lines("x\ny", "y\nx"), vec![Removed(line("x")), Same(line("y")), Added(line("x"))]);
+}
+
+#[test]
+fn every_version_is_kept() {
+	let db = NotesDB::open_in_memory().unwrap();
+	let file = file_ref(1, "/budget.ods");
+
+	db.set_note(&file, "first draft").unwrap();
+	db.set_note(&file, "second draft").unwrap();
+	// Saving the same text again is not a new version
+	db.set_note(&file, "second draft").unwrap();
+	db.set_note(&file, "final").unwrap();
+
+	assert_eq!(notes(&db, &file), vec!["final", "second draft", "first draft"]);
+	assert!(db.note_history(&file_ref(2, "/other")).unwrap().is_empty());
+	// Tagging a file without a note records no version
+	db.add_tag(&file_ref(3, "/tagged"), "finance").unwrap();
+	assert!(db.note_history(&file_ref(3, "/tagged")).unwrap().is_empty());
+}
+
+#[test]
+fn restore_is_a_new_version() {
+	let db = NotesDB::open_in_memory().unwrap();
+	let file = file_ref(1, "/budget.ods");
+	db.set_note(&file, "first draft").unwrap();
+	db.set_note(&file, "mistake").unwrap();
+
+	let first = db.note_history(&file).unwrap().pop().unwrap();
+	db.restore_note_revision(&file, first.id).unwrap();
+	assert_eq!(db.get_note_no_update(&file).unwrap(), "first draft");
+	assert_eq!(notes(&db, &file), vec!["first draft", "mistake", "first draft"]);
+
+	// Revisions of other files can not be restored onto this one
+	let other = file_ref(2, "/other");
+	db.set_note(&other, "other note").unwrap();
+	let other_revision = db.note_history(&other).unwrap()[0].id;
+	assert!(matches!(db.restore_note_revision(&file, other_revision), Err(FileChestError::NotFound(_))));
+	assert_eq!(db.get_note_no_update(&file).unwrap(), "first draft");
+}
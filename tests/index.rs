@@ -0,0 +1,91 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+
+mod common;
+use common::TempDir;
+
+use file_chest::{FileRef, NotesDB};
+use file_chest::index::{FileType, IndexOptions, IndexProgress};
+
+use std::path::PathBuf;
+
+fn indexed_paths(db: &NotesDB, root: &PathBuf) -> Vec<String> {
+	let mut paths: Vec<String> = db.get_all_files().unwrap().into_iter()
+		.map(|f| f.file_path.strip_prefix(root).unwrap().to_string_lossy().to_string())
+		.collect();
+	paths.sort();
+	paths
+}
+
+#[test]
+fn skips_hidden_and_ignored() {
+	let tree = TempDir::new("rules", &[
+		("a.txt", "a"),
+		("docs/b.md", "bb"),
+		(".hidden/c.txt", "c"),
+		("build/out.o", "o"),
+		("notes.tmp", "t"),
+		(".gitignore", "build/\n"),
+		(".filechestignore", "*.tmp\n"),
+	]);
+
+	let db = NotesDB::open_in_memory().unwrap();
+	let mut last = None;
+	let report = db.index(&tree.0, &IndexOptions::default(), &mut |p| last = Some(p.clone())).unwrap();
+	assert_eq!((report.added, report.updated), (2, 0));
+	assert_eq!(last, Some(IndexProgress::Indexing { done: 2, total: 2 }));
+	assert_eq!(indexed_paths(&db, &tree.0), vec!["a.txt", "docs/b.md"]);
+
+	let b = FileRef::from_pathbuf(&tree.0.join("docs/b.md")).unwrap();
+	assert_eq!(db.get_file_type(&b).unwrap(), Some(FileType::File));
+	assert_eq!(db.get_all_files().unwrap().iter().find(|f| f.inode == b.inode).unwrap().fingerprint.as_ref().unwrap().size, 2);
+
+	// Indexing again only refreshes the records
+	let report = db.index(&tree.0, &IndexOptions::default(), &mut |_| {}).unwrap();
+	assert_eq!((report.added, report.updated), (0, 2));
+}
+
+#[test]
+fn options_include_everything() {
+	let tree = TempDir::new("options", &[
+		("a.txt", "a"),
+		(".hidden/c.txt", "c"),
+		("build/out.o", "o"),
+		(".gitignore", "build/\n"),
+	]);
+
+	let db = NotesDB::open_in_memory().unwrap();
+	let options = IndexOptions { include_hidden: true, respect_ignore_files: false, include_dirs: true };
+	db.index(&tree.0, &options, &mut |_| {}).unwrap();
+	assert_eq!(indexed_paths(&db, &tree.0), vec![".gitignore", ".hidden", ".hidden/c.txt", "a.txt", "build", "build/out.o"]);
+
+	let build = FileRef::from_pathbuf(&tree.0.join("build")).unwrap();
+	assert_eq!(db.get_file_type(&build).unwrap(), Some(FileType::Dir));
+}
+
+#[test]
+fn relative_root() {
+	let tree = TempDir::relative("index-relative", &[("a.txt", "a"), ("docs/b.md", "bb")]);
+	assert!(tree.0.is_relative());
+
+	let db = NotesDB::open_in_memory().unwrap();
+	let report = db.index(&tree.0, &IndexOptions::default(), &mut |_| {}).unwrap();
+	assert_eq!(report.added, 2);
+	let root = std::env::current_dir().unwrap().join(&tree.0);
+	assert_eq!(indexed_paths(&db, &root), vec!["a.txt", "docs/b.md"]);
+}
@@ -0,0 +1,158 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+mod common;
+use common::TempDir;
+
+use file_chest::{FileChestError, FileRef, Fingerprint, NotesDB, RecordStatus};
+use file_chest::index::{FileType, IndexOptions};
+use file_chest::metadata::{SortKey, SortOrder};
+use file_chest::query::{self, parse_size};
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+// A directory holding files of the given sizes
+fn sized_files(name: &str, files: &[(&str, usize)]) -> TempDir {
+	let dir = TempDir::new(name, &[]);
+	for (path, size) in files {
+		dir.write(path, &vec![b'x'; *size]);
+	}
+	dir
+}
+
+fn names(files: &[FileRef], root: &Path) -> Vec<String> {
+	files.iter().map(|f| f.file_path.strip_prefix(root).unwrap().to_string_lossy().to_string()).collect()
+}
+
+#[test]
+fn sizes_with_units() {
+	assert_eq!(parse_size("512"), Some(512));
+	assert_eq!(parse_size("10MB"), Some(10_000_000));
+	assert_eq!(parse_size("1.5gb"), Some(1_500_000_000));
+	assert_eq!(parse_size("4KiB"), Some(4096));
+	assert_eq!(parse_size("10 parsecs"), None);
+
+	assert!(query::parse("size>10furlongs").is_err());
+	assert!(query::parse("modified:someday").is_err());
+	assert!(query::parse("mode=999").is_err());
+	assert!(query::looks_like_query("modified:last-week"));
+}
+
+#[test]
+fn indexed_metadata_is_searchable() {
+	let tree = sized_files("search", &[("small.txt", 10), ("big.png", 20_000), ("docs/report.pdf", 5_000)]);
+	fs::set_permissions(tree.0.join("big.png"), fs::Permissions::from_mode(0o600)).unwrap();
+
+	let db = NotesDB::open_in_memory().unwrap();
+	db.index(&tree.0, &IndexOptions { include_dirs: true, ..IndexOptions::default() }, &mut |_| {}).unwrap();
+
+	let big = FileRef::from_pathbuf(&tree.0.join("big.png")).unwrap();
+	let metadata = db.get_metadata(&big).unwrap().unwrap();
+	assert_eq!((metadata.size, metadata.file_type, metadata.permissions), (20_000, FileType::File, 0o600));
+	assert_eq!(metadata.mime_type.as_deref(), Some("image/png"));
+
+	assert_eq!(names(&db.query("size>4KB AND NOT type:dir").unwrap(), &tree.0), vec!["big.png", "docs/report.pdf"]);
+	assert_eq!(names(&db.query("size>=10KiB AND mode=600").unwrap(), &tree.0), vec!["big.png"]);
+	assert_eq!(names(&db.query("type:dir").unwrap(), &tree.0), vec!["docs"]);
+	assert_eq!(names(&db.query("mime:image/*").unwrap(), &tree.0), vec!["big.png"]);
+	assert_eq!(db.query("modified:last-week AND type:file").unwrap().len(), 3);
+	assert_eq!(db.query("modified:today").unwrap().len(), 4);
+	assert!(db.query("modified<2000-01-01").unwrap().is_empty());
+
+	let by_size = db.query_sorted("type:file", SortOrder { key: SortKey::Size, descending: true }).unwrap();
+	assert_eq!(names(&by_size, &tree.0), vec!["big.png", "docs/report.pdf", "small.txt"]);
+}
+
+#[test]
+fn refreshed_on_access() {
+	let tree = sized_files("access", &[("a.txt", 1)]);
+	let db = NotesDB::open_in_memory().unwrap();
+
+	let file = FileRef::from_pathbuf(&tree.0.join("a.txt")).unwrap();
+	db.set_note(&file, "first draft").unwrap();
+	assert_eq!(db.get_metadata(&file).unwrap(), None);
+
+	// Looking the note up fills the cache in
+	db.get_note(&file).unwrap();
+	let metadata = db.get_metadata(&file).unwrap().unwrap();
+	assert_eq!((metadata.size, metadata.mime_type.as_deref()), (1, Some("text/plain")));
+}
+
+#[test]
+fn stale_record_is_not_refreshed() {
+	let tree = sized_files("stale", &[("new.txt", 3)]);
+	let db = NotesDB::open_in_memory().unwrap();
+
+	// A record left by an earlier file with the same inode, changed after the new one was
+	let file = FileRef::from_pathbuf(&tree.0.join("new.txt")).unwrap();
+	let earlier = Fingerprint { birth_time: Some(1), size: 100, mtime: 1, ctime: Some(i64::MAX) };
+	db.set_note(&FileRef { fingerprint: Some(earlier), ..file.clone() }, "old note").unwrap();
+	assert_eq!(db.record_status(&file).unwrap(), RecordStatus::Stale);
+
+	assert!(matches!(db.refresh_metadata(&file), Err(FileChestError::StaleRecord(_))));
+	assert_eq!(db.record_status(&file).unwrap(), RecordStatus::Stale);
+	assert_eq!(db.get_metadata(&file).unwrap(), None);
+}
+
+#[test]
+fn mime_types_from_contents_and_names() {
+	let tree = sized_files("mime", &[("empty", 0)]);
+	let files: &[(&str, &[u8])] = &[
+		("photo.dat", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+		("scan", b"%PDF-1.7\n"),
+		("plugin.jar", b"PK\x03\x04\x14\0\0\0"),
+		("README", "caf\u{e9} notes\n".as_bytes()),
+		("blob", b"\0\x01\x02\x03"),
+	];
+	for (name, contents) in files {
+		fs::write(tree.0.join(name), contents).unwrap();
+	}
+
+	let db = NotesDB::open_in_memory().unwrap();
+	db.index(&tree.0, &IndexOptions::default(), &mut |_| {}).unwrap();
+
+	let mime_of = |name: &str| {
+		let file = FileRef::from_pathbuf(&tree.0.join(name)).unwrap();
+		db.get_metadata(&file).unwrap().unwrap().mime_type.unwrap()
+	};
+	assert_eq!(mime_of("photo.dat"), "image/png");
+	assert_eq!(mime_of("scan"), "application/pdf");
+	assert_eq!(mime_of("plugin.jar"), "application/java-archive");
+	assert_eq!(mime_of("README"), "text/plain");
+	assert_eq!(mime_of("blob"), "application/octet-stream");
+	assert_eq!(mime_of("empty"), "application/x-zerosize");
+
+	assert_eq!(names(&db.query("type:image").unwrap(), &tree.0), vec!["photo.dat"]);
+	assert_eq!(names(&db.query("type:application/pdf OR type:text").unwrap(), &tree.0), vec!["README", "scan"]);
+	assert!(query::parse("type:spreadsheet").is_err());
+
+	// An unchanged file keeps its stored type without being read again
+	let scan = FileRef::from_pathbuf(&tree.0.join("scan")).unwrap();
+	let m = fs::symlink_metadata(&scan.file_path).unwrap();
+	assert_eq!(db.current_metadata(&scan, &m).unwrap().mime_type.as_deref(), Some("application/pdf"));
+
+	// Files the database knows nothing about are only typed by name
+	let current_mime = |name: &str, contents: &[u8]| {
+		let file = FileRef::from_pathbuf(&tree.write(name, contents)).unwrap();
+		let m = fs::symlink_metadata(&file.file_path).unwrap();
+		db.current_metadata(&file, &m).unwrap().mime_type
+	};
+	assert_eq!(current_mime("new.pdf", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").as_deref(), Some("application/pdf"));
+	assert_eq!(current_mime("new", b"%PDF-1.7\n"), None);
+}
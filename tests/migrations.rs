@@ -0,0 +1,324 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+mod common;
+use common::TempDir;
+
+use file_chest::{legacy_db_paths, FileChestError, FileRef, NotesDB, SCHEMA_VERSION};
+use file_chest::attributes::AttrValue;
+use file_chest::gc::GcOptions;
+
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use rusqlite::Connection;
+
+// Create a fresh database file for a test, loaded with the given SQL
+fn fixture_db(name: &str, sql: &str) -> PathBuf {
+	let path = std::env::temp_dir().join(format!("filechest-{}-{name}.db", std::process::id()));
+	let _ = std::fs::remove_file(&path);
+	load_db(&path, sql);
+	path
+}
+
+// Load the given SQL into a database at path, whose directories must exist
+fn load_db(path: &PathBuf, sql: &str) {
+	let conn = Connection::open(path).expect("Could not create fixture database");
+	conn.execute_batch(sql).expect("Could not load fixture");
+}
+
+fn file_ref(device: u64, inode: u64, path: &str) -> FileRef {
+	FileRef { file_path: PathBuf::from(path), device, inode, fingerprint: None }
+}
+
+#[test]
+fn new_database_is_current() {
+	let db = NotesDB::open_in_memory().unwrap();
+	assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+}
+
+// Each migration is checked against the oldest schema, upgraded all the way in one go.
+// Databases of every later shipped schema are upgraded below, and what each feature does on a
+// current database is tested in its own file.
+#[test]
+fn upgrade_from_v0() {
+	let path = fixture_db("v0", include_str!("fixtures/schema_v0.sql"));
+	let db = NotesDB::open(&path).unwrap();
+	assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+
+	// The fixture's paths do not exist, so its files end up on device 0
+	let budget = file_ref(0, 1001, "/home/user/budget.ods");
+	let invoice = file_ref(0, 1002, "/home/user/invoice.pdf");
+	assert_eq!(db.get_note_no_update(&budget).unwrap(), "Q3 budget draft");
+	assert_eq!(db.get_tags(&budget).unwrap(), vec!["finance"]);
+	let paths: Vec<PathBuf> = db.get_all_files().unwrap().into_iter().map(|f| f.file_path).collect();
+	assert!(paths.contains(&PathBuf::from("/home/user/budget.ods")));
+
+	// Flat tags containing a slash gain their ancestors
+	assert_eq!(db.get_tags_with_ancestors(&invoice).unwrap(), vec!["archive", "archive/2023", "finance", "invoice"]);
+	let archived: Vec<u64> = db.get_files_by_tag("archive").unwrap().iter().map(|f| f.inode).collect();
+	assert_eq!(archived, vec![1002]);
+
+	// Existing notes are searchable and become the first revision
+	let found: Vec<u64> = db.search_notes("budget").unwrap().iter().map(|m| m.file.inode).collect();
+	assert_eq!(found, vec![1001]);
+	let history: Vec<String> = db.note_history(&budget).unwrap().into_iter().map(|r| r.note).collect();
+	assert_eq!(history, vec!["Q3 budget draft"]);
+	assert!(db.note_history(&invoice).unwrap().is_empty());
+
+	// Tables added later start out empty, and records without fingerprints or metadata fill them in later
+	assert_eq!(db.get_attributes(&budget).unwrap(), vec![]);
+	db.set_attribute(&budget, "quarter", &AttrValue::Integer(3)).unwrap();
+	assert!(db.watch_roots().unwrap().is_empty());
+	assert_eq!(db.get_metadata(&invoice).unwrap(), None);
+	assert_eq!(db.get_file_type(&invoice).unwrap(), None);
+	db.set_tags(&budget, vec!["finance", "2023"]).unwrap();
+
+	// The files are missing, so gc starts their grace period but keeps them
+	let report = db.gc(&GcOptions::default()).unwrap();
+	assert_eq!(report.missing_files.len(), 2);
+	assert!(report.expired_files.is_empty() && report.unused_tags.is_empty());
+
+	drop(db);
+	std::fs::remove_file(&path).unwrap();
+}
+
+// Upgrade a database of an intermediate shipped schema and check the data every fixture holds.
+// The fixtures' paths do not exist, so their files end up on device 0.
+fn upgrade(name: &str, sql: &str) -> (NotesDB, TempDir) {
+	let dir = TempDir::new(&format!("upgrade-{name}"), &[]);
+	let path = dir.0.join("chest.db");
+	load_db(&path, sql);
+	let db = NotesDB::open(&path).unwrap();
+	assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+
+	let budget = file_ref(0, 1001, "/home/user/budget.ods");
+	assert_eq!(db.get_note_no_update(&budget).unwrap(), "Q3 budget draft");
+	assert_eq!(db.get_tags(&budget).unwrap(), vec!["finance"]);
+	let invoices: Vec<u64> = db.get_files_by_tag("invoice").unwrap().iter().map(|f| f.inode).collect();
+	assert_eq!(invoices, vec![1002]);
+
+	(db, dir)
+}
+
+#[test]
+fn upgrade_from_v1() {
+	upgrade("v1", include_str!("fixtures/schema_v1.sql"));
+}
+
+#[test]
+fn upgrade_from_v2() {
+	upgrade("v2", include_str!("fixtures/schema_v2.sql"));
+}
+
+#[test]
+fn upgrade_from_v3() {
+	let (db, _dir) = upgrade("v3", include_str!("fixtures/schema_v3.sql"));
+
+	let invoice = file_ref(0, 1002, "/home/user/invoice.pdf");
+	assert_eq!(db.get_tags_with_ancestors(&invoice).unwrap(), vec!["archive", "archive/2023", "finance", "invoice"]);
+}
+
+#[test]
+fn upgrade_from_v4() {
+	let (db, _dir) = upgrade("v4", include_str!("fixtures/schema_v4.sql"));
+
+	let found: Vec<u64> = db.search_notes("budget").unwrap().iter().map(|m| m.file.inode).collect();
+	assert_eq!(found, vec![1001]);
+}
+
+#[test]
+fn upgrade_from_v5() {
+	let (db, _dir) = upgrade("v5", include_str!("fixtures/schema_v5.sql"));
+
+	let budget = file_ref(0, 1001, "/home/user/budget.ods");
+	assert_eq!(db.get_attributes(&budget).unwrap(), vec![]);
+	db.set_attribute(&budget, "quarter", &AttrValue::Integer(3)).unwrap();
+	assert_eq!(db.search_notes("budget").unwrap().len(), 1);
+}
+
+#[test]
+fn upgrade_from_v6() {
+	let (db, _dir) = upgrade("v6", include_str!("fixtures/schema_v6.sql"));
+
+	let invoice = file_ref(0, 1002, "/home/user/invoice.pdf");
+	assert_eq!(db.get_attribute(&invoice, "due").unwrap(), Some(AttrValue::Date("2023-02-01".to_string())));
+	let budget = file_ref(0, 1001, "/home/user/budget.ods");
+	let history: Vec<String> = db.note_history(&budget).unwrap().into_iter().map(|r| r.note).collect();
+	assert_eq!(history, vec!["Q3 budget draft"]);
+}
+
+#[test]
+fn upgrade_from_v7() {
+	let (db, _dir) = upgrade("v7", include_str!("fixtures/schema_v7.sql"));
+
+	// Paths stored as text read back the same after becoming bytes
+	let paths: Vec<PathBuf> = db.get_all_files().unwrap().into_iter().map(|f| f.file_path).collect();
+	assert!(paths.contains(&PathBuf::from("/home/user/budget.ods")));
+}
+
+#[test]
+fn upgrade_from_v8() {
+	let (db, _dir) = upgrade("v8", include_str!("fixtures/schema_v8.sql"));
+
+	let report = db.gc(&GcOptions::default()).unwrap();
+	assert_eq!(report.missing_files.len(), 2);
+	assert!(report.expired_files.is_empty() && report.unused_tags.is_empty());
+	let report = db.gc(&GcOptions { dry_run: true, grace_period: Duration::ZERO }).unwrap();
+	assert_eq!(report.expired_files.len(), 2);
+}
+
+#[test]
+fn upgrade_from_v9() {
+	let (db, _dir) = upgrade("v9", include_str!("fixtures/schema_v9.sql"));
+
+	// Change times start out unknown, and are set by the next edit
+	let budget = file_ref(0, 1001, "/home/user/budget.ods");
+	db.set_tags(&budget, vec!["finance", "2023"]).unwrap();
+	assert_eq!(db.get_tags(&budget).unwrap().len(), 2);
+}
+
+#[test]
+fn upgrade_from_v10() {
+	let (db, _dir) = upgrade("v10", include_str!("fixtures/schema_v10.sql"));
+
+	let budget = file_ref(0, 1001, "/home/user/budget.ods");
+	assert_eq!(db.get_file_type(&budget).unwrap(), None);
+}
+
+#[test]
+fn upgrade_from_v11() {
+	let (db, _dir) = upgrade("v11", include_str!("fixtures/schema_v11.sql"));
+
+	assert!(db.watch_roots().unwrap().is_empty());
+	db.add_watch_root("/home/user").unwrap();
+	assert_eq!(db.watch_roots().unwrap(), vec![PathBuf::from("/home/user")]);
+}
+
+#[test]
+fn upgrade_from_v12() {
+	let (db, _dir) = upgrade("v12", include_str!("fixtures/schema_v12.sql"));
+
+	assert_eq!(db.watch_roots().unwrap(), vec![PathBuf::from("/home/user")]);
+	assert!(db.find_duplicates(&["/nonexistent"]).unwrap().is_empty());
+}
+
+#[test]
+fn upgrade_from_v13() {
+	let (db, _dir) = upgrade("v13", include_str!("fixtures/schema_v13.sql"));
+
+	// Size and mtime were already stored as the fingerprint, the rest is filled in later
+	let invoice = file_ref(0, 1002, "/home/user/invoice.pdf");
+	assert_eq!(db.get_metadata(&invoice).unwrap(), None);
+	assert_eq!(db.query("size>40KB").unwrap()[0].inode, 1002);
+}
+
+#[test]
+fn device_recovered_when_file_returns() {
+	let dir = std::env::temp_dir().join(format!("filechest-returns-{}", std::process::id()));
+	let _ = std::fs::remove_dir_all(&dir);
+	std::fs::create_dir_all(&dir).unwrap();
+	let path = dir.join("report.txt");
+	let away = dir.join("report.txt.away");
+	std::fs::write(&path, "contents").unwrap();
+	let inode = std::fs::metadata(&path).unwrap().ino();
+
+	// The file is elsewhere while the database is upgraded, so its row gets device 0
+	std::fs::rename(&path, &away).unwrap();
+	let db_path = fixture_db("returns", &format!(
+		"CREATE TABLE file_notes (inode INTEGER PRIMARY KEY, known_path TEXT, note TEXT);
+		CREATE TABLE file_tags (id INTEGER PRIMARY KEY AUTOINCREMENT, tag_name VARCHAR(255) UNIQUE);
+		CREATE TABLE tag_relations (tag_id INTEGER NOT NULL, file_id INTEGER NOT NULL);
+		INSERT INTO file_notes(inode, known_path, note) VALUES({inode}, '{}', 'quarterly report');",
+		path.display()
+	));
+	let db = NotesDB::open(&db_path).unwrap();
+	assert_eq!(db.get_all_files().unwrap()[0].device, 0);
+
+	// Once it is back the row is found under the file's real device, and gc no longer counts it as missing
+	std::fs::rename(&away, &path).unwrap();
+	let file = FileRef::from_pathbuf(&path).unwrap();
+	assert_eq!(db.get_note(&file).unwrap(), "quarterly report");
+	assert_eq!(db.get_all_files().unwrap()[0].device, file.device);
+	assert!(db.gc(&GcOptions::default()).unwrap().missing_files.is_empty());
+
+	drop(db);
+	let _ = std::fs::remove_file(&db_path);
+	let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn missing_device_recovered_by_gc() {
+	let dir = std::env::temp_dir().join(format!("filechest-returns-gc-{}", std::process::id()));
+	let _ = std::fs::remove_dir_all(&dir);
+	std::fs::create_dir_all(&dir).unwrap();
+	let path = dir.join("notes.txt");
+	std::fs::write(&path, "contents").unwrap();
+	let m = std::fs::metadata(&path).unwrap();
+
+	let db = NotesDB::open_in_memory().unwrap();
+	db.set_note(&FileRef { file_path: path.clone(), device: 0, inode: m.ino(), fingerprint: None }, "kept").unwrap();
+
+	// Nothing has looked the file up, but gc and reconcile find it at its known path
+	let report = db.gc(&GcOptions::default()).unwrap();
+	assert!(report.missing_files.is_empty() && report.expired_files.is_empty());
+	assert_eq!(db.get_all_files().unwrap()[0].device, m.dev());
+
+	let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn reopen_keeps_data() {
+	let path = fixture_db("reopen", "");
+
+	let db = NotesDB::open(&path).unwrap();
+	let file = file_ref(7, 42, "/tmp/answer.txt");
+	db.set_note(&file, "hello").unwrap();
+	drop(db);
+
+	let db = NotesDB::open(&path).unwrap();
+	assert_eq!(db.get_note_no_update(&file).unwrap(), "hello");
+
+	drop(db);
+	std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn refuse_newer_schema() {
+	let path = fixture_db("newer", &format!("PRAGMA user_version = {};", SCHEMA_VERSION + 1));
+
+	assert!(matches!(NotesDB::open(&path), Err(FileChestError::SchemaVersion { .. })));
+
+	std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn legacy_database_below_unexpanded_home() {
+	// Release builds wrote to ~/.filechest without expanding the ~, so below the working directory
+	let legacy = PathBuf::from("~/.filechest/test.db");
+	assert!(legacy_db_paths().contains(&legacy));
+
+	let dir = TempDir::new("legacy", &[]);
+	let path = dir.0.join(&legacy);
+	std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+	load_db(&path, include_str!("fixtures/schema_v0.sql"));
+
+	let db = NotesDB::open(&path).unwrap();
+	assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+	assert_eq!(db.get_note_no_update(&file_ref(0, 1001, "/home/user/budget.ods")).unwrap(), "Q3 budget draft");
+}
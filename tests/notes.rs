@@ -0,0 +1,67 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+use file_chest::{fts_query, query, FileRef, NotesDB};
+
+use std::path::PathBuf;
+
+fn file_ref(inode: u64, path: &str) -> FileRef {
+	FileRef { file_path: PathBuf::from(path), device: 7, inode, fingerprint: None }
+}
+
+fn found(db: &NotesDB, text: &str) -> Vec<u64> {
+	db.search_notes(text).unwrap().iter().map(|m| m.file.inode).collect()
+}
+
+#[test]
+fn full_text_search() {
+	let db = NotesDB::open_in_memory().unwrap();
+	db.set_note(&file_ref(1, "/budget.ods"), "Q3 budget draft, see the finance meeting").unwrap();
+	db.set_note(&file_ref(2, "/minutes.txt"), "Meeting minutes: budget budget budget").unwrap();
+	db.set_note(&file_ref(3, "/photo.jpg"), "Beach in Lisbon").unwrap();
+
+	// Every word must appear, in any order and case
+	assert_eq!(found(&db, "MEETING finance"), vec![1]);
+	assert_eq!(found(&db, "lisbon beach"), vec![3]);
+	// The note mentioning the word most often ranks first
+	assert_eq!(found(&db, "budget"), vec![2, 1]);
+	// A trailing * matches the start of words
+	assert_eq!(found(&db, "fin*"), vec![1]);
+	// Punctuation is searched for rather than read as syntax
+	assert_eq!(found(&db, "minutes: \"budget"), vec![2]);
+
+	let matches = db.search_notes("lisbon").unwrap();
+	assert_eq!(matches[0].snippet, "Beach in [Lisbon]");
+	assert_eq!(matches[0].file.file_path, PathBuf::from("/photo.jpg"));
+
+	// Changed notes are searched by their new text only
+	db.set_note(&file_ref(3, "/photo.jpg"), "Mountains near Porto").unwrap();
+	assert!(found(&db, "lisbon").is_empty());
+	assert_eq!(found(&db, "porto"), vec![3]);
+}
+
+#[test]
+fn empty_search() {
+	let db = NotesDB::open_in_memory().unwrap();
+	db.set_note(&file_ref(1, "/a"), "anything").unwrap();
+
+	assert_eq!(fts_query("  * "), "");
+	assert!(db.search_notes("").unwrap().is_empty());
+	assert!(db.search_notes("   ").unwrap().is_empty());
+	assert!(db.search_notes("*").unwrap().is_empty());
+	assert!(query::parse("note:*").is_err());
+}
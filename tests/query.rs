@@ -0,0 +1,155 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+use file_chest::{FileRef, NotesDB};
+use file_chest::attributes::{AttrValue, CompareOp};
+use file_chest::index::FileType;
+use file_chest::query::{self, looks_like_query, Period, Query, TimeField};
+
+use std::path::PathBuf;
+
+fn tag(name: &str) -> Box<Query> {
+	Box::new(Query::Tag(name.to_string()))
+}
+
+fn error(text: &str) -> String {
+	query::parse(text).unwrap_err().to_string()
+}
+
+fn file_ref(inode: u64, path: &str) -> FileRef {
+	FileRef { file_path: PathBuf::from(path), device: 7, inode, fingerprint: None }
+}
+
+fn paths(db: &NotesDB, text: &str) -> Vec<String> {
+	db.query(text).unwrap().iter().map(|f| f.file_path.to_string_lossy().to_string()).collect()
+}
+
+#[test]
+fn precedence() {
+	// NOT binds tighter than AND, which binds tighter than OR
+	assert_eq!(
+		query::parse("tag:a OR tag:b AND NOT tag:c").unwrap(),
+		Query::Or(tag("a"), Box::new(Query::And(tag("b"), Box::new(Query::Not(tag("c"))))))
+	);
+	assert_eq!(
+		query::parse("(tag:a OR tag:b) AND tag:c").unwrap(),
+		Query::And(Box::new(Query::Or(tag("a"), tag("b"))), tag("c"))
+	);
+
+	// Terms next to each other are joined with AND, and keywords may be lower case
+	assert_eq!(query::parse("tag:a tag:b").unwrap(), query::parse("tag:a AND tag:b").unwrap());
+	assert_eq!(query::parse("tag:a or not tag:b").unwrap(), Query::Or(tag("a"), Box::new(Query::Not(tag("b")))));
+	assert_eq!(query::parse("NOT NOT tag:a").unwrap(), Query::Not(Box::new(Query::Not(tag("a")))));
+}
+
+#[test]
+fn quoting() {
+	assert_eq!(query::parse("tag:\"tax return\"").unwrap(), Query::Tag("tax return".to_string()));
+	assert_eq!(query::parse("tag:\"a (b)\" OR tag:c").unwrap(), Query::Or(tag("a (b)"), tag("c")));
+	// Tag paths are tidied up the same way as when tagging
+	assert_eq!(query::parse("tag:\" project / alpha/\"").unwrap(), Query::Tag("project/alpha".to_string()));
+
+	// Quoted values are always text, unquoted ones are read as numbers or dates when possible
+	assert_eq!(query::parse("rating>=4").unwrap(), Query::Attribute("rating".to_string(), CompareOp::Ge, AttrValue::Integer(4)));
+	assert_eq!(query::parse("rating=\"4\"").unwrap(), Query::Attribute("rating".to_string(), CompareOp::Eq, AttrValue::Text("4".to_string())));
+	assert_eq!(
+		query::parse("due<2026-12-01").unwrap(),
+		Query::Attribute("due".to_string(), CompareOp::Lt, AttrValue::Date("2026-12-01".to_string()))
+	);
+	// A quoted keyword is a value, not an operator
+	assert!(query::parse("tag:a \"AND\" tag:b").is_err());
+}
+
+#[test]
+fn metadata_terms() {
+	assert_eq!(query::parse("size>10MB").unwrap(), Query::Size(CompareOp::Gt, 10_000_000));
+	assert_eq!(query::parse("size<=4KiB").unwrap(), Query::Size(CompareOp::Le, 4096));
+	assert_eq!(
+		query::parse("modified<2026-01-01").unwrap(),
+		Query::Date(TimeField::Modified, CompareOp::Lt, "2026-01-01".to_string())
+	);
+	assert_eq!(query::parse("changed:today").unwrap(), Query::Within(TimeField::Changed, Period::Today));
+	assert_eq!(query::parse("modified:Last-Week").unwrap(), Query::Within(TimeField::Modified, Period::LastWeek));
+	assert_eq!(
+		query::parse("modified:2026-03-04").unwrap(),
+		Query::Date(TimeField::Modified, CompareOp::Eq, "2026-03-04".to_string())
+	);
+	assert_eq!(query::parse("mode=644").unwrap(), Query::Permissions(CompareOp::Eq, 0o644));
+	assert_eq!(query::parse("type:dir").unwrap(), Query::Type(FileType::Dir));
+	assert_eq!(query::parse("type:image").unwrap(), Query::Mime("image/*".to_string()));
+	assert_eq!(query::parse("type:Application/PDF").unwrap(), Query::Mime("application/pdf".to_string()));
+	assert_eq!(query::parse("mime:video*").unwrap(), Query::Mime("video*".to_string()));
+}
+
+#[test]
+fn error_messages() {
+	assert_eq!(error(""), "Invalid query: Empty query");
+	assert_eq!(error("tag:a AND"), "Invalid query: Unexpected end of query");
+	assert_eq!(error("(tag:a OR tag:b"), "Invalid query: Missing closing parenthesis");
+	assert_eq!(error("tag:\"open"), "Invalid query: Unterminated quote in \"tag:open\"");
+	assert_eq!(error("invoice"), "Invalid query: Expected a term such as tag:name, found \"invoice\"");
+	assert_eq!(error("tag:"), "Invalid query: Missing value after tag:");
+	assert_eq!(error("colour:red"), "Invalid query: Unknown search prefix colour:");
+	assert_eq!(error("rating=>4"), "Invalid query: Unknown comparison => after rating");
+	assert_eq!(error("rating>"), "Invalid query: Missing value after rating");
+	assert_eq!(error("size>big"), "Invalid query: Expected a size such as 10MB after size, found \"big\"");
+	assert_eq!(error("mode=9"), "Invalid query: Expected octal permissions such as 644 after mode, found \"9\"");
+	assert!(error("type:spreadsheet").starts_with("Invalid query: Unknown type spreadsheet"));
+	assert!(error("modified:someday").contains("Expected a date or one of today"));
+	assert!(error("tag:a )").starts_with("Invalid query: Unexpected"));
+}
+
+#[test]
+fn queries_or_paths() {
+	assert!(looks_like_query("tag:invoice"));
+	assert!(looks_like_query("(tag:a OR tag:b)"));
+	assert!(looks_like_query("NOT tag:paid"));
+	assert!(looks_like_query("rating>=4"));
+	assert!(!looks_like_query("/home/user"));
+	assert!(!looks_like_query("~/Documents"));
+	assert!(!looks_like_query("./a=b"));
+}
+
+#[test]
+fn evaluation() {
+	let db = NotesDB::open_in_memory().unwrap();
+	let (a, b, c) = (file_ref(1, "/a"), file_ref(2, "/b"), file_ref(3, "/c"));
+	db.set_tags(&a, vec!["invoice", "year/2023"]).unwrap();
+	db.set_tags(&b, vec!["invoice", "year/2024", "paid"]).unwrap();
+	db.set_tags(&c, vec!["receipt"]).unwrap();
+	db.set_note(&c, "Hotel stay in Lisbon").unwrap();
+	db.set_attribute(&a, "rating", &AttrValue::Integer(5)).unwrap();
+	db.set_attribute(&b, "rating", &AttrValue::Integer(2)).unwrap();
+	db.set_attribute(&c, "rating", &AttrValue::Text("5".to_string())).unwrap();
+	db.set_attribute(&a, "due", &AttrValue::Date("2026-11-01".to_string())).unwrap();
+
+	assert_eq!(paths(&db, "tag:invoice AND NOT tag:paid"), vec!["/a"]);
+	assert_eq!(paths(&db, "tag:receipt OR tag:invoice AND tag:paid"), vec!["/b", "/c"]);
+	assert_eq!(paths(&db, "(tag:receipt OR tag:invoice) AND NOT tag:paid"), vec!["/a", "/c"]);
+	// A tag matches the tags below it
+	assert_eq!(paths(&db, "tag:year"), vec!["/a", "/b"]);
+	assert_eq!(paths(&db, "tag:year/2024"), vec!["/b"]);
+	assert_eq!(paths(&db, "note:lisbon"), vec!["/c"]);
+	assert_eq!(paths(&db, "note:\"hotel lisbon\" OR rating<3"), vec!["/b", "/c"]);
+
+	// Numbers are only compared with numbers, and text with text
+	assert_eq!(paths(&db, "rating>=4"), vec!["/a"]);
+	assert_eq!(paths(&db, "rating=\"5\""), vec!["/c"]);
+	assert_eq!(paths(&db, "rating!=5"), vec!["/b"]);
+	assert_eq!(paths(&db, "due<2026-12-01"), vec!["/a"]);
+	assert!(paths(&db, "due<2026-01-01").is_empty());
+}
@@ -0,0 +1,172 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+mod common;
+use common::TempDir;
+
+use file_chest::{FileChestError, FileRef, Fingerprint, NotesDB, RecordStatus};
+use file_chest::watch::WatchEvent;
+
+use std::fs;
+use std::path::PathBuf;
+
+fn fingerprint(birth_time: Option<i64>, size: u64, time: i64) -> Fingerprint {
+	Fingerprint { birth_time, size, mtime: time, ctime: Some(time) }
+}
+
+fn file_ref(fingerprint: Fingerprint) -> FileRef {
+	FileRef { file_path: PathBuf::from("/home/user/budget.ods"), device: 7, inode: 42, fingerprint: Some(fingerprint) }
+}
+
+#[test]
+fn edited_file_keeps_its_record() {
+	let dir = TempDir::new("edit", &[]);
+	let path = dir.0.join("draft.txt");
+	fs::write(&path, "first").unwrap();
+
+	let db = NotesDB::open_in_memory().unwrap();
+	db.set_note(&FileRef::from_pathbuf(&path).unwrap(), "keep me").unwrap();
+
+	fs::write(&path, "a much longer second version").unwrap();
+	let edited = FileRef::from_pathbuf(&path).unwrap();
+	assert_eq!(db.record_status(&edited).unwrap(), RecordStatus::Current);
+	assert_eq!(db.get_note(&edited).unwrap(), "keep me");
+
+	// The watcher takes the edit as the file's new fingerprint
+	fs::write(&path, "third").unwrap();
+	assert_eq!(db.apply_watch_event(&WatchEvent::Written(path.clone()), &[]).unwrap(), 1);
+	let stored = db.get_all_files().unwrap().remove(0).fingerprint.unwrap();
+	assert_eq!(stored.size, 5);
+}
+
+#[test]
+fn without_birth_time_nothing_is_stale() {
+	let db = NotesDB::open_in_memory().unwrap();
+	db.set_note(&file_ref(fingerprint(None, 100, 1_000)), "Q3 budget draft").unwrap();
+
+	// Size, mtime and ctime changed, as any write changes them
+	let edited = file_ref(fingerprint(None, 250, 2_000));
+	assert_eq!(db.record_status(&edited).unwrap(), RecordStatus::Current);
+	assert_eq!(db.get_note(&edited).unwrap(), "Q3 budget draft");
+
+	// A new file which reused the inode looks just the same, so it is taken for the old one
+	let reused = file_ref(fingerprint(None, 40, 500));
+	assert_eq!(db.record_status(&reused).unwrap(), RecordStatus::Current);
+
+	// Only one birth time known is no better than none
+	assert!(fingerprint(Some(1_000), 100, 1_000).matches(&fingerprint(None, 40, 500)));
+	assert!(fingerprint(None, 100, 1_000).matches(&fingerprint(Some(5_000), 40, 5_000)));
+}
+
+#[test]
+fn reused_inode_is_stale() {
+	let db = NotesDB::open_in_memory().unwrap();
+	db.set_note(&file_ref(fingerprint(Some(1_000), 100, 1_000)), "Q3 budget draft").unwrap();
+	db.add_tag(&file_ref(fingerprint(Some(1_000), 100, 1_000)), "finance").unwrap();
+
+	// The file was deleted and a new one created with the same inode
+	let reused = file_ref(fingerprint(Some(5_000), 100, 5_000));
+	assert_eq!(db.record_status(&reused).unwrap(), RecordStatus::Stale);
+	assert!(matches!(db.get_note(&reused), Err(FileChestError::StaleRecord(_))));
+	assert!(matches!(db.set_note(&reused, "new"), Err(FileChestError::StaleRecord(_))));
+
+	// Edits never change the birth time
+	let edited = file_ref(fingerprint(Some(1_000), 300, 9_000));
+	assert_eq!(db.record_status(&edited).unwrap(), RecordStatus::Current);
+
+	// Accepting the new file keeps the note, forgetting the record drops everything
+	db.refresh_fingerprint(&reused).unwrap();
+	assert_eq!(db.get_note(&reused).unwrap(), "Q3 budget draft");
+	db.forget_record(&reused).unwrap();
+	assert_eq!(db.record_status(&reused).unwrap(), RecordStatus::Missing);
+	assert!(db.get_tags(&reused).unwrap().is_empty());
+}
+
+#[test]
+fn reconcile_finds_moved_files() {
+	let dir = TempDir::new("reconcile", &[]);
+	fs::create_dir_all(dir.0.join("old")).unwrap();
+	fs::create_dir_all(dir.0.join("new/deeper")).unwrap();
+	let (moved, stayed, deleted) = (dir.0.join("old/moved.txt"), dir.0.join("old/stayed.txt"), dir.0.join("old/deleted.txt"));
+	for path in [&moved, &stayed, &deleted] {
+		fs::write(path, "contents").unwrap();
+	}
+
+	let db = NotesDB::open_in_memory().unwrap();
+	for (path, note) in [(&moved, "moved"), (&stayed, "stayed"), (&deleted, "deleted")] {
+		db.set_note(&FileRef::from_pathbuf(path).unwrap(), note).unwrap();
+	}
+
+	let destination = dir.0.join("new/deeper/renamed.txt");
+	fs::rename(&moved, &destination).unwrap();
+	fs::remove_file(&deleted).unwrap();
+
+	let report = db.reconcile(&[&dir.0]).unwrap();
+	assert_eq!(report.moved.len(), 1);
+	assert_eq!(report.moved[0].0, moved);
+	assert_eq!(report.moved[0].1.file_path, destination);
+	let missing: Vec<&PathBuf> = report.missing.iter().map(|f| &f.file_path).collect();
+	assert_eq!(missing, vec![&deleted]);
+
+	// The moved file's record now has its new path, and keeps its note
+	let found = FileRef::from_pathbuf(&destination).unwrap();
+	assert_eq!(db.get_note_no_update(&found).unwrap(), "moved");
+	assert!(db.get_all_files().unwrap().iter().any(|f| f.file_path == destination));
+
+	// Nothing is left to find a second time
+	let report = db.reconcile(&[&dir.0]).unwrap();
+	assert!(report.moved.is_empty());
+	assert_eq!(report.missing.len(), 1);
+}
+
+#[test]
+fn reconcile_outside_roots_reports_missing() {
+	let dir = TempDir::new("reconcile-roots", &[]);
+	fs::create_dir_all(dir.0.join("a")).unwrap();
+	fs::create_dir_all(dir.0.join("b")).unwrap();
+	let path = dir.0.join("a/file.txt");
+	fs::write(&path, "contents").unwrap();
+
+	let db = NotesDB::open_in_memory().unwrap();
+	db.set_note(&FileRef::from_pathbuf(&path).unwrap(), "note").unwrap();
+	fs::rename(&path, dir.0.join("b/file.txt")).unwrap();
+
+	// Only a is searched, so the file is not found
+	let report = db.reconcile(&[dir.0.join("a")]).unwrap();
+	assert!(report.moved.is_empty());
+	assert_eq!(report.missing[0].file_path, path);
+
+	let report = db.reconcile(&[dir.0.join("a"), dir.0.join("b")]).unwrap();
+	assert_eq!(report.moved[0].1.file_path, dir.0.join("b/file.txt"));
+	assert!(report.missing.is_empty());
+}
+
+#[test]
+fn reconcile_relative_root() {
+	let dir = TempDir::relative("records-relative", &[("a/file.txt", "contents")]);
+	let absolute = std::env::current_dir().unwrap().join(&dir.0);
+
+	let db = NotesDB::open_in_memory().unwrap();
+	db.set_note(&dir.file_ref("a/file.txt"), "note").unwrap();
+	dir.write("b/.keep", b"");
+	fs::rename(dir.0.join("a/file.txt"), dir.0.join("b/file.txt")).unwrap();
+
+	let report = db.reconcile(&[&dir.0]).unwrap();
+	assert_eq!(report.moved[0].1.file_path, absolute.join("b/file.txt"));
+	assert!(report.missing.is_empty());
+	assert_eq!(db.get_note_no_update(&dir.file_ref("b/file.txt")).unwrap(), "note");
+}
@@ -0,0 +1,156 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+mod common;
+use common::TempDir;
+
+use file_chest::{FileChestError, FileRef, NotesDB};
+use file_chest::attributes::AttrValue;
+use file_chest::index::IndexOptions;
+use file_chest::metadata::FileMetadata;
+use file_chest::rules::RuleSet;
+use file_chest::sidecar::SIDECAR_FILE;
+use file_chest::watch::WatchEvent;
+
+use std::fs;
+use std::path::Path;
+
+fn invoice_rules(root: &Path) -> RuleSet {
+	RuleSet::parse(&format!(r#"
+		[[rule]]
+		name = "Invoices"
+		path_prefix = "{}"
+		glob = "*invoice*.pdf"
+		mime = "application/pdf"
+		tags = ["invoice", "finance/unpaid"]
+		attributes = {{ source = "download", received = 2026-10-01 }}
+		note = "Filed automatically"
+
+		[[rule]]
+		name = "Large"
+		min_size = "1KB"
+		name_regex = "^scan-[0-9]+"
+		tags = ["large"]
+	"#, root.join("Downloads").display())).unwrap()
+}
+
+#[test]
+fn invalid_rules_are_rejected() {
+	let no_condition = "[[rule]]\nname = \"All\"\ntags = [\"x\"]";
+	assert!(matches!(RuleSet::parse(no_condition), Err(FileChestError::InvalidInput(_))));
+	let no_action = "[[rule]]\nname = \"Nothing\"\nglob = \"*\"";
+	assert!(RuleSet::parse(no_action).is_err());
+	assert!(RuleSet::parse("[[rule]]\nname = \"Bad\"\nname_regex = \"(\"\ntags = [\"x\"]").is_err());
+	assert!(RuleSet::parse("[[rule]]\nname = \"Typo\"\nglobb = \"*\"\ntags = [\"x\"]").is_err());
+	assert!(RuleSet::parse("").unwrap().is_empty());
+}
+
+#[test]
+fn applied_on_demand_and_by_index() {
+	let tree = TempDir::new("demand", &[
+		("Downloads/invoice-0042.pdf", "%PDF-1.7\n"),
+		("Downloads/invoice-notes.txt", "not a pdf"),
+		("Documents/invoice-0001.pdf", "%PDF-1.7\n"),
+		("Documents/scan-17.png", &"x".repeat(2000)),
+	]);
+
+	let mut db = NotesDB::open_in_memory().unwrap();
+	db.set_rules(invoice_rules(&tree.0));
+	let report = db.apply_rules_below(&tree.0, &IndexOptions::default()).unwrap();
+	assert_eq!((report.checked, report.matched_rules), (4, 2));
+	// Files no rule matched are left alone
+	assert_eq!(db.get_all_files().unwrap().len(), 2);
+
+	let invoice = FileRef::from_pathbuf(&tree.0.join("Downloads/invoice-0042.pdf")).unwrap();
+	let mut tags = db.get_tags(&invoice).unwrap();
+	tags.sort();
+	assert_eq!(tags, vec!["finance/unpaid", "invoice"]);
+	assert_eq!(db.get_attribute(&invoice, "received").unwrap(), Some(AttrValue::Date("2026-10-01".to_string())));
+	assert_eq!(db.get_metadata(&invoice).unwrap().unwrap().mime_type.as_deref(), Some("application/pdf"));
+
+	// Running the rules again does not repeat the note
+	db.set_note(&invoice, "Paid by card").unwrap();
+	db.apply_rules_below(&tree.0, &IndexOptions::default()).unwrap();
+	db.apply_rules(&invoice).unwrap();
+	assert_eq!(db.get_note(&invoice).unwrap(), "Paid by card\nFiled automatically");
+
+	let scan = FileRef::from_pathbuf(&tree.0.join("Documents/scan-17.png")).unwrap();
+	assert_eq!(db.get_tags(&scan).unwrap(), vec!["large"]);
+
+	// The indexer applies them too
+	let mut db = NotesDB::open_in_memory().unwrap();
+	db.set_rules(invoice_rules(&tree.0));
+	let report = db.index(&tree.0, &IndexOptions::default(), &mut |_| {}).unwrap();
+	assert_eq!((report.added, report.matched_rules), (4, 2));
+	assert_eq!(db.query("tag:invoice").unwrap().len(), 1);
+}
+
+#[test]
+fn relative_root() {
+	let tree = TempDir::relative("rules-relative", &[("Downloads/invoice-3.pdf", "%PDF-1.7\n")]);
+	let absolute = std::env::current_dir().unwrap().join(&tree.0);
+	let mut db = NotesDB::open_in_memory().unwrap();
+	db.set_rules(invoice_rules(&absolute));
+
+	let relative = tree.0.join("Downloads/invoice-3.pdf");
+	let metadata = FileMetadata::from_metadata(&relative, &fs::metadata(&relative).unwrap());
+	assert!(db.rules().rules()[0].matches(&relative, &metadata));
+
+	let report = db.apply_rules_below(&tree.0, &IndexOptions::default()).unwrap();
+	assert_eq!((report.checked, report.matched_rules), (1, 1));
+	assert_eq!(db.query("tag:invoice").unwrap()[0].file_path, absolute.join("Downloads/invoice-3.pdf"));
+}
+
+#[test]
+fn hand_edits_are_kept() {
+	let tree = TempDir::new("edits", &[("Downloads/invoice-1.pdf", "%PDF-1.7\n")]);
+	let mut db = NotesDB::open_in_memory().unwrap();
+	db.set_rules(invoice_rules(&tree.0));
+	db.set_sidecar_sync(true);
+
+	let invoice = FileRef::from_pathbuf(&tree.0.join("Downloads/invoice-1.pdf")).unwrap();
+	db.apply_rules(&invoice).unwrap();
+	db.set_attribute(&invoice, "source", &AttrValue::Text("email".to_string())).unwrap();
+	db.delete_attribute(&invoice, "received").unwrap();
+
+	// Attributes are only set when missing
+	db.apply_rules_below(&tree.0, &IndexOptions::default()).unwrap();
+	assert_eq!(db.get_attribute(&invoice, "source").unwrap(), Some(AttrValue::Text("email".to_string())));
+	assert_eq!(db.get_attribute(&invoice, "received").unwrap(), Some(AttrValue::Date("2026-10-01".to_string())));
+
+	// The tags the rule added reached the sidecar
+	let manifest = fs::read_to_string(tree.0.join("Downloads").join(SIDECAR_FILE)).unwrap();
+	assert!(manifest.contains("\"finance/unpaid\"") && manifest.contains("\"invoice\""), "{manifest}");
+}
+
+#[test]
+fn applied_to_files_which_appear() {
+	let tree = TempDir::new("appear", &[("Downloads/.keep", "")]);
+	let mut db = NotesDB::open_in_memory().unwrap();
+	db.set_rules(invoice_rules(&tree.0));
+
+	// A download which is renamed into place once it is complete
+	let partial = tree.0.join("Downloads/invoice-7.pdf.part");
+	let done = tree.0.join("Downloads/invoice-7.pdf");
+	fs::write(&partial, "%PDF-1.4\n").unwrap();
+	assert_eq!(db.apply_watch_event(&WatchEvent::Written(partial.clone()), &[]).unwrap(), 0);
+
+	fs::rename(&partial, &done).unwrap();
+	let moved = WatchEvent::Moved { from: partial, to: done.clone() };
+	assert_eq!(db.apply_watch_event(&moved, &[]).unwrap(), 1);
+	assert_eq!(db.get_tags(&FileRef::from_pathbuf(&done).unwrap()).unwrap().len(), 2);
+}
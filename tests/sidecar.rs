@@ -0,0 +1,94 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+mod common;
+use common::TempDir;
+
+use file_chest::NotesDB;
+use file_chest::export::ImportMode;
+use file_chest::sidecar::SIDECAR_FILE;
+
+use std::fs;
+
+#[test]
+fn export_then_import() {
+	let dir = TempDir::new("round-trip", &[("budget.ods", ""), ("invoice.pdf", "")]);
+	let budget = dir.file_ref("budget.ods");
+
+	let db = NotesDB::open_in_memory().unwrap();
+	db.set_note(&budget, "Q3 budget draft").unwrap();
+	db.set_tags(&budget, vec!["finance", "project/alpha"]).unwrap();
+	db.add_tag(&dir.file_ref("invoice.pdf"), "invoice").unwrap();
+	assert_eq!(db.export_sidecar(&dir.0).unwrap().len(), 2);
+
+	let other = NotesDB::open_in_memory().unwrap();
+	let report = other.import_sidecar(&dir.0, ImportMode::Merge).unwrap();
+	assert_eq!((report.added, report.merged), (2, 0));
+	assert_eq!(other.get_note(&budget).unwrap(), "Q3 budget draft");
+
+	let mut tags = other.get_tags(&budget).unwrap();
+	tags.sort();
+	assert_eq!(tags, vec!["finance", "project/alpha"]);
+}
+
+#[test]
+fn sync_on_write() {
+	let dir = TempDir::new("sync-on-write", &[("notes.txt", "")]);
+	let file = dir.file_ref("notes.txt");
+
+	let mut db = NotesDB::open_in_memory().unwrap();
+	db.set_sidecar_sync(true);
+	db.set_note(&file, "kept next to the file").unwrap();
+	let manifest = fs::read_to_string(dir.0.join(SIDECAR_FILE)).unwrap();
+	assert!(manifest.contains("kept next to the file"));
+
+	// A manifest with no entries left is removed
+	db.set_note(&file, "").unwrap();
+	assert!(!dir.0.join(SIDECAR_FILE).exists());
+}
+
+#[test]
+fn sync_newer_side_wins() {
+	let dir = TempDir::new("sync", &[("a.txt", ""), ("b.txt", "")]);
+	fs::write(dir.0.join(SIDECAR_FILE), concat!(
+		"version = 1\n",
+		"[files.\"a.txt\"]\n",
+		"note = \"from the manifest\"\n",
+		"tags = [\"shared\"]\n",
+		"changed = 4102444800\n",
+		"[files.\"gone.txt\"]\n",
+		"note = \"deleted file\"\n",
+	)).unwrap();
+
+	let db = NotesDB::open_in_memory().unwrap();
+	db.set_note(&dir.file_ref("a.txt"), "from the database").unwrap();
+	db.set_note(&dir.file_ref("b.txt"), "only in the database").unwrap();
+
+	let report = db.sync_sidecar(&dir.0).unwrap();
+	assert_eq!(report.imported, vec![dir.0.join("a.txt")]);
+	assert_eq!(report.exported, vec![dir.0.join("b.txt")]);
+	assert_eq!(report.skipped, vec![dir.0.join("gone.txt")]);
+
+	assert_eq!(db.get_note(&dir.file_ref("a.txt")).unwrap(), "from the manifest");
+	assert_eq!(db.get_tags(&dir.file_ref("a.txt")).unwrap(), vec!["shared"]);
+	let manifest = fs::read_to_string(dir.0.join(SIDECAR_FILE)).unwrap();
+	assert!(manifest.contains("only in the database") && manifest.contains("deleted file"));
+
+	// Nothing left to do the second time
+	let report = db.sync_sidecar(&dir.0).unwrap();
+	assert!(report.imported.is_empty() && report.exported.is_empty());
+}
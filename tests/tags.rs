@@ -0,0 +1,122 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+use file_chest::{normalize_tag, FileRef, NotesDB};
+
+use std::path::PathBuf;
+
+fn file_ref(inode: u64, path: &str) -> FileRef {
+	FileRef { file_path: PathBuf::from(path), device: 7, inode, fingerprint: None }
+}
+
+fn inodes(files: Vec<FileRef>) -> Vec<u64> {
+	let mut inodes: Vec<u64> = files.iter().map(|f| f.inode).collect();
+	inodes.sort();
+	inodes
+}
+
+fn sorted(mut tags: Vec<String>) -> Vec<String> {
+	tags.sort();
+	tags
+}
+
+#[test]
+fn hierarchical_tags() {
+	assert_eq!(normalize_tag(" project / alpha//design/ "), "project/alpha/design");
+
+	let db = NotesDB::open_in_memory().unwrap();
+	let (design, alpha, beta) = (file_ref(1, "/design.svg"), file_ref(2, "/alpha.txt"), file_ref(3, "/beta.txt"));
+	db.add_tag(&design, "project / alpha/design").unwrap();
+	db.add_tag(&alpha, "project/alpha").unwrap();
+	db.add_tag(&beta, "project/beta").unwrap();
+
+	// Files keep the tag they were given, and report its ancestors separately
+	assert_eq!(db.get_tags(&design).unwrap(), vec!["project/alpha/design"]);
+	assert_eq!(db.get_tags_with_ancestors(&design).unwrap(), vec!["project", "project/alpha", "project/alpha/design"]);
+
+	// Searching for a tag finds the files tagged with anything below it
+	assert_eq!(inodes(db.get_files_by_tag("project").unwrap()), vec![1, 2, 3]);
+	assert_eq!(inodes(db.get_files_by_tag("project/alpha").unwrap()), vec![1, 2]);
+	assert_eq!(inodes(db.get_files_by_tag("project/alpha/design").unwrap()), vec![1]);
+	assert!(db.get_files_by_tag("project/gamma").unwrap().is_empty());
+
+	// Ancestors exist as tags of their own without being on any file
+	let names: Vec<String> = db.list_tags().unwrap().into_iter().map(|t| t.name).collect();
+	assert_eq!(names, vec!["project", "project/alpha", "project/alpha/design", "project/beta"]);
+	assert_eq!(sorted(db.get_tags(&alpha).unwrap()), vec!["project/alpha"]);
+}
+
+#[test]
+fn rename_moves_subtree() {
+	let db = NotesDB::open_in_memory().unwrap();
+	let (a, b) = (file_ref(1, "/a"), file_ref(2, "/b"));
+	db.set_tags(&a, vec!["proj/alpha/design", "misc"]).unwrap();
+	db.set_tags(&b, vec!["proj"]).unwrap();
+
+	db.rename_tag("proj", "work/project").unwrap();
+	assert_eq!(sorted(db.get_tags(&a).unwrap()), vec!["misc", "work/project/alpha/design"]);
+	assert_eq!(db.get_tags(&b).unwrap(), vec!["work/project"]);
+	assert_eq!(inodes(db.get_files_by_tag("work").unwrap()), vec![1, 2]);
+	assert!(db.get_files_by_tag("proj").unwrap().is_empty());
+
+	// Renaming onto itself does nothing, onto a taken name or into its own subtree fails
+	db.rename_tag("misc", " misc/ ").unwrap();
+	assert_eq!(sorted(db.get_tags(&a).unwrap()), vec!["misc", "work/project/alpha/design"]);
+	assert!(db.rename_tag("misc", "work/project").is_err());
+	assert!(db.rename_tag("work", "work/inner").is_err());
+	assert!(db.rename_tag("missing", "other").is_err());
+	assert!(db.rename_tag("misc", "").is_err());
+	assert_eq!(db.get_tags(&b).unwrap(), vec!["work/project"]);
+}
+
+#[test]
+fn merge_into_existing_tag() {
+	let db = NotesDB::open_in_memory().unwrap();
+	let (a, b, c) = (file_ref(1, "/a"), file_ref(2, "/b"), file_ref(3, "/c"));
+	db.set_tags(&a, vec!["invoices", "invoices/2023"]).unwrap();
+	db.set_tags(&b, vec!["bills/2023"]).unwrap();
+	db.set_tags(&c, vec!["bills", "invoices"]).unwrap();
+
+	// Files already carrying the target keep it once, and subtrees merge into matching subtrees
+	db.merge_tags("invoices", "bills").unwrap();
+	assert_eq!(sorted(db.get_tags(&a).unwrap()), vec!["bills", "bills/2023"]);
+	assert_eq!(db.get_tags(&b).unwrap(), vec!["bills/2023"]);
+	assert_eq!(db.get_tags(&c).unwrap(), vec!["bills"]);
+
+	let tags: Vec<(String, usize)> = db.list_tags().unwrap().into_iter().map(|t| (t.name, t.file_count)).collect();
+	assert_eq!(tags, vec![("bills".to_string(), 2), ("bills/2023".to_string(), 2)]);
+
+	assert!(db.merge_tags("bills", "bills").is_err());
+	assert!(db.merge_tags("bills", "bills/2023").is_err());
+	assert!(db.merge_tags("invoices", "bills").is_err());
+}
+
+#[test]
+fn delete_removes_subtree() {
+	let db = NotesDB::open_in_memory().unwrap();
+	let a = file_ref(1, "/a");
+	db.set_tags(&a, vec!["archive/2023/q1", "archive", "keep"]).unwrap();
+
+	db.delete_tag("archive/2023").unwrap();
+	assert_eq!(sorted(db.get_tags(&a).unwrap()), vec!["archive", "keep"]);
+	let names: Vec<String> = db.list_tags().unwrap().into_iter().map(|t| t.name).collect();
+	assert_eq!(names, vec!["archive", "keep"]);
+
+	db.delete_tag("archive").unwrap();
+	assert_eq!(db.get_tags(&a).unwrap(), vec!["keep"]);
+	assert!(db.delete_tag("archive").is_err());
+}
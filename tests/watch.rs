@@ -0,0 +1,116 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+
+mod common;
+use common::{collect_warnings, TempDir};
+
+use file_chest::{FileRef, NotesDB};
+use file_chest::watch::{WatchEvent, Watcher};
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+fn known_path(db: &NotesDB, file: &FileRef) -> PathBuf {
+	db.get_all_files().unwrap().into_iter().find(|f| f.inode == file.inode).unwrap().file_path
+}
+
+#[test]
+fn moved_directory_updates_paths() {
+	let dir = TempDir::new("move", &[("old/a.txt", "old/a.txt"), ("old/sub/b.txt", "old/sub/b.txt"), ("older.txt", "older.txt")]);
+	let db = NotesDB::open_in_memory().unwrap();
+	let a = FileRef::from_pathbuf(&dir.0.join("old/a.txt")).unwrap();
+	let b = FileRef::from_pathbuf(&dir.0.join("old/sub/b.txt")).unwrap();
+	let older = FileRef::from_pathbuf(&dir.0.join("older.txt")).unwrap();
+	for file in [&a, &b, &older] {
+		db.set_note(file, "note").unwrap();
+	}
+
+	fs::rename(dir.0.join("old"), dir.0.join("new")).unwrap();
+	let event = WatchEvent::Moved { from: dir.0.join("old"), to: dir.0.join("new") };
+	assert_eq!(db.apply_watch_event(&event, &[]).unwrap(), 2);
+
+	assert_eq!(known_path(&db, &a), dir.0.join("new/a.txt"));
+	assert_eq!(known_path(&db, &b), dir.0.join("new/sub/b.txt"));
+	// Only whole path components are matched
+	assert_eq!(known_path(&db, &older), dir.0.join("older.txt"));
+}
+
+#[test]
+fn removed_file_starts_grace_period() {
+	let dir = TempDir::new("remove", &[("a.txt", "a.txt"), ("b.txt", "b.txt")]);
+	let db = NotesDB::open_in_memory().unwrap();
+	for name in ["a.txt", "b.txt"] {
+		db.set_note(&FileRef::from_pathbuf(&dir.0.join(name)).unwrap(), "note").unwrap();
+	}
+
+	fs::remove_file(dir.0.join("a.txt")).unwrap();
+	assert_eq!(db.apply_watch_event(&WatchEvent::Removed(dir.0.join("a.txt")), &[]).unwrap(), 1);
+	// A file which is still there is left alone
+	assert_eq!(db.apply_watch_event(&WatchEvent::Removed(dir.0.join("b.txt")), &[]).unwrap(), 0);
+
+	let report = db.gc(&Default::default()).unwrap();
+	assert_eq!(report.missing_files.len(), 1);
+	assert!(report.expired_files.is_empty());
+}
+
+#[test]
+fn watcher_reports_renames() {
+	let dir = TempDir::new("live", &[("a.txt", "a.txt")]);
+	let watcher = Watcher::new(&[&dir.0]).unwrap();
+
+	fs::rename(dir.0.join("a.txt"), dir.0.join("b.txt")).unwrap();
+	let expected = WatchEvent::Moved { from: dir.0.join("a.txt"), to: dir.0.join("b.txt") };
+
+	let deadline = Instant::now() + Duration::from_secs(5);
+	let mut events = Vec::new();
+	while Instant::now() < deadline && !events.contains(&expected) {
+		events.extend(watcher.events(Duration::from_millis(100)));
+	}
+	assert!(events.contains(&expected), "{events:?}");
+}
+
+#[test]
+fn failed_event_is_skipped() {
+	let dir = TempDir::new("failure", &[("b.txt", "b.txt"), ("c.txt", "c.txt")]);
+	let mut db = NotesDB::open_in_memory().unwrap();
+	let warnings = collect_warnings(&mut db);
+	let b = FileRef::from_pathbuf(&dir.0.join("b.txt")).unwrap();
+	let c = FileRef::from_pathbuf(&dir.0.join("c.txt")).unwrap();
+	db.set_note(&b, "note").unwrap();
+	db.set_note(&c, "note").unwrap();
+
+	// A relative path can not be stored, but the events after it are still applied
+	fs::rename(dir.0.join("c.txt"), dir.0.join("d.txt")).unwrap();
+	let events = [
+		WatchEvent::Moved { from: dir.0.join("b.txt"), to: PathBuf::from("e.txt") },
+		WatchEvent::Moved { from: dir.0.join("c.txt"), to: dir.0.join("d.txt") },
+	];
+	assert_eq!(db.apply_watch_events(&events, &[]), 1);
+	assert_eq!(known_path(&db, &b), dir.0.join("b.txt"));
+	assert_eq!(known_path(&db, &c), dir.0.join("d.txt"));
+	let reported = warnings.lock().unwrap().clone();
+	assert_eq!(reported.len(), 1, "{reported:?}");
+	assert!(reported[0].starts_with("Could not apply Moved"), "{reported:?}");
+
+	// What a failed event missed is picked up by rescanning the roots
+	fs::rename(dir.0.join("b.txt"), dir.0.join("e.txt")).unwrap();
+	let events = [WatchEvent::Moved { from: dir.0.join("b.txt"), to: PathBuf::from("e.txt") }];
+	assert_eq!(db.apply_watch_events(&events, std::slice::from_ref(&dir.0)), 1);
+	assert_eq!(known_path(&db, &b), dir.0.join("e.txt"));
+}
@@ -0,0 +1,97 @@
+/* Copyright (c) 2023 Daniel Ragsdale <DanJeffRags@gmail.com>
+ *
+ * This program is free software; you can redistribute it and/or modify it
+ * under the terms of the GNU General Public License as published by the Free
+ * Software Foundation; either version 2 of the License, or (at your option)
+ * any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+ * FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+ * more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program; if not, write to the Free Software Foundation, Inc., 59 Temple
+ * Place, Suite 330, Boston, MA  02111-1307  USA
+ */
+
+mod common;
+use common::{collect_warnings, TempDir};
+
+use file_chest::{FileRef, NotesDB};
+use file_chest::export::ImportMode;
+use file_chest::xattrs::{COMMENT_XATTR, TAGS_XATTR};
+
+use std::path::{Path, PathBuf};
+
+// Tests are made on tmpfs where it is available, which keeps user attributes since Linux 6.6
+fn base() -> PathBuf {
+	let base = Path::new("/dev/shm");
+	if base.is_dir() { base.to_path_buf() } else { std::env::temp_dir() }
+}
+
+fn read(file: &FileRef, name: &str) -> Option<String> {
+	xattr::get(&file.file_path, name).unwrap().map(|value| String::from_utf8(value).unwrap())
+}
+
+#[test]
+fn round_trip() {
+	let dir = TempDir::in_dir(&base(), "round-trip", &[]);
+	let budget = dir.file("budget.ods");
+	if xattr::set(&budget.file_path, "user.filechest.test", b"").is_err() {
+		eprintln!("Skipping, {} does not support user attributes", dir.0.display());
+		return;
+	}
+
+	let mut db = NotesDB::open_in_memory().unwrap();
+	db.set_xattr_sync(true);
+	db.set_tags(&budget, vec!["finance", "proj/alpha"]).unwrap();
+	db.set_note(&budget, "Q3 draft").unwrap();
+	assert_eq!(read(&budget, TAGS_XATTR).as_deref(), Some("finance,proj/alpha"));
+	assert_eq!(read(&budget, COMMENT_XATTR).as_deref(), Some("Q3 draft"));
+
+	// Managing tags rewrites the attributes of every file carrying them
+	db.add_tag(&budget, "urgent").unwrap();
+	db.rename_tag("proj", "project").unwrap();
+	assert_eq!(read(&budget, TAGS_XATTR).as_deref(), Some("finance,project/alpha,urgent"));
+	db.merge_tags("urgent", "finance").unwrap();
+	assert_eq!(read(&budget, TAGS_XATTR).as_deref(), Some("finance,project/alpha"));
+	db.delete_tag("project").unwrap();
+	assert_eq!(read(&budget, TAGS_XATTR).as_deref(), Some("finance"));
+	db.delete_tag("finance").unwrap();
+	assert_eq!(read(&budget, TAGS_XATTR), None);
+
+	// The attributes alone are enough to rebuild the database
+	db.set_tags(&budget, vec!["archive"]).unwrap();
+	let fresh = NotesDB::open_in_memory().unwrap();
+	let report = fresh.import_xattrs(&dir.0, ImportMode::Merge).unwrap();
+	assert_eq!(report.added, 1);
+	assert_eq!(fresh.get_tags(&budget).unwrap(), vec!["archive"]);
+	assert_eq!(fresh.get_note_no_update(&budget).unwrap(), "Q3 draft");
+}
+
+#[test]
+fn failures_are_not_errors() {
+	let mut db = NotesDB::open_in_memory().unwrap();
+	db.set_xattr_sync(true);
+	let warnings = collect_warnings(&mut db);
+
+	// User attributes are refused on device files
+	let null = FileRef::from_pathbuf(&PathBuf::from("/dev/null")).unwrap();
+	db.set_tags(&null, vec!["device"]).unwrap();
+	db.set_note(&null, "the bit bucket").unwrap();
+	assert_eq!(db.get_tags(&null).unwrap(), vec!["device"]);
+	assert_eq!(db.get_note_no_update(&null).unwrap(), "the bit bucket");
+	let written: Vec<String> = warnings.lock().unwrap().drain(..).collect();
+	assert_eq!(written.len(), 2, "{written:?}");
+	assert!(written[0].starts_with(&format!("Could not write {TAGS_XATTR} to /dev/null")), "{written:?}");
+
+	// No manifest can be written in a directory which is not one
+	db.set_sidecar_sync(true);
+	let nowhere = FileRef { file_path: PathBuf::from("/dev/null/ghost"), device: 7, inode: 42, fingerprint: None };
+	db.set_note(&nowhere, "still stored").unwrap();
+	db.add_tag(&nowhere, "ghost").unwrap();
+	db.rename_tag("ghost", "spirit").unwrap();
+	assert_eq!(db.get_tags(&nowhere).unwrap(), vec!["spirit"]);
+	assert!(warnings.lock().unwrap().iter().any(|w| w.starts_with("Could not update the sidecar manifest for /dev/null/ghost")));
+}